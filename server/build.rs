@@ -1,4 +1,14 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("../proto/checklist.proto")?;
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile(
+            &[
+                "../proto/google/rpc/status.proto",
+                "../proto/google/rpc/error_details.proto",
+            ],
+            &["../proto"],
+        )?;
     Ok(())
 }
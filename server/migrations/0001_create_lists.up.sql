@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS lists (
+  id CHAR(36) PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL
+);
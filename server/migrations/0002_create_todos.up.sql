@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS todos (
+  id CHAR(36) PRIMARY KEY NOT NULL,
+  list_id CHAR(36) NOT NULL REFERENCES lists ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL,
+  FOREIGN KEY (list_id) REFERENCES lists (id)
+);
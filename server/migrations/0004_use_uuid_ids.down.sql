@@ -0,0 +1,13 @@
+ALTER TABLE todos
+DROP CONSTRAINT todos_list_id_fkey;
+
+ALTER TABLE lists
+ALTER COLUMN id TYPE CHAR(36) USING id::TEXT;
+
+ALTER TABLE todos
+ALTER COLUMN id TYPE CHAR(36) USING id::TEXT,
+ALTER COLUMN list_id TYPE CHAR(36) USING list_id::TEXT;
+
+ALTER TABLE todos
+ADD CONSTRAINT todos_list_id_fkey
+FOREIGN KEY (list_id) REFERENCES lists (id) ON DELETE CASCADE;
@@ -0,0 +1,22 @@
+DO $$
+DECLARE
+  constraint_name TEXT;
+BEGIN
+  FOR constraint_name IN
+    SELECT conname FROM pg_constraint
+    WHERE conrelid = 'todos'::regclass AND contype = 'f'
+  LOOP
+    EXECUTE format('ALTER TABLE todos DROP CONSTRAINT %I', constraint_name);
+  END LOOP;
+END $$;
+
+ALTER TABLE lists
+ALTER COLUMN id TYPE UUID USING id::TEXT::UUID;
+
+ALTER TABLE todos
+ALTER COLUMN id TYPE UUID USING id::TEXT::UUID,
+ALTER COLUMN list_id TYPE UUID USING list_id::TEXT::UUID;
+
+ALTER TABLE todos
+ADD CONSTRAINT todos_list_id_fkey
+FOREIGN KEY (list_id) REFERENCES lists (id) ON DELETE CASCADE;
@@ -0,0 +1,4 @@
+ALTER TABLE todos
+DROP COLUMN IF EXISTS version;
+ALTER TABLE lists
+DROP COLUMN IF EXISTS version;
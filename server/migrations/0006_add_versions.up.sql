@@ -0,0 +1,4 @@
+ALTER TABLE lists
+ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
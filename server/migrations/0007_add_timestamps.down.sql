@@ -0,0 +1,7 @@
+ALTER TABLE todos
+DROP COLUMN IF EXISTS completed_at,
+DROP COLUMN IF EXISTS updated_at,
+DROP COLUMN IF EXISTS created_at;
+
+ALTER TABLE lists
+DROP COLUMN IF EXISTS updated_at;
@@ -0,0 +1,11 @@
+ALTER TABLE lists
+ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+UPDATE lists SET updated_at = created_at;
+
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+ADD COLUMN IF NOT EXISTS completed_at TIMESTAMPTZ;
+
+UPDATE todos SET completed_at = updated_at WHERE done;
@@ -0,0 +1,6 @@
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS due_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS due_all_day BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX IF NOT EXISTS todos_due_at_idx ON todos (due_at)
+WHERE due_at IS NOT NULL AND NOT done;
@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS todos_parent_id_idx;
+
+ALTER TABLE todos
+DROP CONSTRAINT IF EXISTS todos_parent_id_fkey,
+DROP CONSTRAINT IF EXISTS todos_parent_id_check,
+DROP COLUMN IF EXISTS parent_id;
+
+ALTER TABLE todos
+DROP CONSTRAINT IF EXISTS todos_list_id_id_key;
@@ -0,0 +1,14 @@
+ALTER TABLE todos
+ADD CONSTRAINT todos_list_id_id_key UNIQUE (list_id, id);
+
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS parent_id UUID;
+
+ALTER TABLE todos
+ADD CONSTRAINT todos_parent_id_check CHECK (parent_id <> id),
+ADD CONSTRAINT todos_parent_id_fkey
+FOREIGN KEY (list_id, parent_id) REFERENCES todos (list_id, id)
+ON UPDATE CASCADE ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS todos_parent_id_idx ON todos (parent_id)
+WHERE parent_id IS NOT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS position TEXT COLLATE "C" NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS todos_list_id_position_idx ON todos (list_id, position, id);
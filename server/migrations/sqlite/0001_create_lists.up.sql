@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS lists (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL
+);
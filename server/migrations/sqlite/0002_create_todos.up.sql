@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS todos (
+  id TEXT PRIMARY KEY NOT NULL,
+  list_id TEXT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL
+);
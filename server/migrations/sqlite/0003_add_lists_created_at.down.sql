@@ -0,0 +1,11 @@
+CREATE TABLE lists_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL
+);
+
+INSERT INTO lists_new (id, name)
+SELECT id, name FROM lists;
+
+DROP TABLE lists;
+
+ALTER TABLE lists_new RENAME TO lists;
@@ -0,0 +1,4 @@
+ALTER TABLE lists
+ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+
+UPDATE lists SET created_at = strftime('%Y-%m-%d %H:%M:%f', 'now');
@@ -0,0 +1,3 @@
+UPDATE lists SET id = lower(id);
+
+UPDATE todos SET id = lower(id), list_id = lower(list_id);
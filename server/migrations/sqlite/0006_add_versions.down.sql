@@ -0,0 +1,26 @@
+CREATE TABLE todos_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  list_id TEXT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL
+);
+
+INSERT INTO todos_new (id, list_id, description, done)
+SELECT id, list_id, description, done FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE TABLE lists_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO lists_new (id, name, created_at)
+SELECT id, name, created_at FROM lists;
+
+DROP TABLE lists;
+
+ALTER TABLE lists_new RENAME TO lists;
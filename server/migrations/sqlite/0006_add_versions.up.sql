@@ -0,0 +1,5 @@
+ALTER TABLE lists
+ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+ALTER TABLE todos
+ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,28 @@
+CREATE TABLE todos_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  list_id TEXT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL,
+  version INTEGER NOT NULL DEFAULT 1
+);
+
+INSERT INTO todos_new (id, list_id, description, done, version)
+SELECT id, list_id, description, done, version FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE TABLE lists_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT '',
+  version INTEGER NOT NULL DEFAULT 1
+);
+
+INSERT INTO lists_new (id, name, created_at, version)
+SELECT id, name, created_at, version FROM lists;
+
+DROP TABLE lists;
+
+ALTER TABLE lists_new RENAME TO lists;
@@ -0,0 +1,19 @@
+ALTER TABLE lists
+ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+
+UPDATE lists SET updated_at = created_at;
+
+ALTER TABLE todos
+ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE todos
+ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE todos
+ADD COLUMN completed_at TEXT;
+
+UPDATE todos
+SET created_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+  updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now');
+
+UPDATE todos SET completed_at = updated_at WHERE done;
@@ -0,0 +1,22 @@
+DROP INDEX IF EXISTS todos_due_at_idx;
+
+CREATE TABLE todos_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  list_id TEXT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL,
+  version INTEGER NOT NULL DEFAULT 1,
+  created_at TEXT NOT NULL DEFAULT '',
+  updated_at TEXT NOT NULL DEFAULT '',
+  completed_at TEXT
+);
+
+INSERT INTO todos_new (
+  id, list_id, description, done, version, created_at, updated_at, completed_at
+)
+SELECT id, list_id, description, done, version, created_at, updated_at, completed_at
+FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
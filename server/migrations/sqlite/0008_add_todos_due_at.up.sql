@@ -0,0 +1,8 @@
+ALTER TABLE todos
+ADD COLUMN due_at TEXT;
+
+ALTER TABLE todos
+ADD COLUMN due_all_day BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS todos_due_at_idx ON todos (due_at)
+WHERE due_at IS NOT NULL AND NOT done;
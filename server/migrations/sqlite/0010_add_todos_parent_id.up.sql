@@ -0,0 +1,34 @@
+CREATE TABLE todos_new (
+  id TEXT PRIMARY KEY NOT NULL,
+  list_id TEXT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+  description TEXT NOT NULL,
+  done BOOLEAN NOT NULL,
+  version INTEGER NOT NULL DEFAULT 1,
+  created_at TEXT NOT NULL DEFAULT '',
+  updated_at TEXT NOT NULL DEFAULT '',
+  completed_at TEXT,
+  due_at TEXT,
+  due_all_day BOOLEAN NOT NULL DEFAULT 0,
+  parent_id TEXT CHECK (parent_id <> id),
+  UNIQUE (list_id, id),
+  FOREIGN KEY (list_id, parent_id) REFERENCES todos (list_id, id)
+  ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+INSERT INTO todos_new (
+  id, list_id, description, done, version, created_at, updated_at, completed_at, due_at,
+  due_all_day
+)
+SELECT id, list_id, description, done, version, created_at, updated_at, completed_at, due_at,
+  due_all_day
+FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE INDEX IF NOT EXISTS todos_due_at_idx ON todos (due_at)
+WHERE due_at IS NOT NULL AND NOT done;
+
+CREATE INDEX IF NOT EXISTS todos_parent_id_idx ON todos (parent_id)
+WHERE parent_id IS NOT NULL;
@@ -0,0 +1,128 @@
+use super::super::common::model::Error as ModelError;
+use super::model::{Todo, TodoList};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+pub const NOTIFICATION_CHANNEL: &str = "checklist_events";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Removed,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Removed => "removed",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<ChangeKind> {
+        match kind {
+            "created" => Some(ChangeKind::Created),
+            "updated" => Some(ChangeKind::Updated),
+            "removed" => Some(ChangeKind::Removed),
+            _ => None,
+        }
+    }
+}
+
+// Postgres caps NOTIFY payloads at 8000 bytes, so only identifiers cross the wire and listeners
+// reload the current row themselves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Notification {
+    pub kind: ChangeKind,
+    pub list_id: Uuid,
+    pub todo_id: Option<Uuid>,
+}
+
+impl Notification {
+    pub fn to_payload(&self) -> String {
+        let payload = json!({
+            "kind": self.kind.as_str(),
+            "list_id": self.list_id.to_hyphenated().to_string(),
+            "todo_id": self.todo_id.map(|id| id.to_hyphenated().to_string()),
+        });
+
+        payload.to_string()
+    }
+
+    pub fn from_payload(payload: &str) -> Result<Notification, ModelError> {
+        let invalid =
+            || ModelError::Validation(format!("'{}' is not a valid notification", payload));
+
+        let value = serde_json::from_str::<Value>(payload).map_err(|_| invalid())?;
+
+        let kind = value["kind"]
+            .as_str()
+            .and_then(ChangeKind::parse)
+            .ok_or_else(invalid)?;
+
+        let list_id = value["list_id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(invalid)?;
+
+        let todo_id = match &value["todo_id"] {
+            Value::Null => None,
+            Value::String(id) => Some(Uuid::parse_str(id).map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Notification {
+            kind,
+            list_id,
+            todo_id,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    List {
+        kind: ChangeKind,
+        list_id: Uuid,
+        list: Option<TodoList>,
+    },
+    Todo {
+        kind: ChangeKind,
+        list_id: Uuid,
+        todo_id: Uuid,
+        todo: Option<Todo>,
+    },
+}
+
+impl Event {
+    pub fn list_id(&self) -> Uuid {
+        match self {
+            Event::List { list_id, .. } => *list_id,
+            Event::Todo { list_id, .. } => *list_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn notification_round_trip() {
+        let notification = Notification {
+            kind: ChangeKind::Removed,
+            list_id: Uuid::new_v4(),
+            todo_id: Some(Uuid::new_v4()),
+        };
+
+        let payload = notification.to_payload();
+
+        assert_eq!(Notification::from_payload(&payload).unwrap(), notification);
+        assert!(Notification::from_payload("{}").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use super::model::{Due, Todo, TodoList};
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub request_hash: String,
+    pub response: String,
+}
+
+// A claim is written in the same transaction as the row it creates, so a retry finds either the
+// finished response or nothing at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotencyClaim {
+    pub key: String,
+    pub request_hash: String,
+}
+
+// The hash lets a replayed key be told apart from a key reused for a different request.
+pub fn hash_request(operation: &str, fields: &[Option<String>]) -> String {
+    let request = json!([operation, fields]);
+    format!("{:x}", Sha256::digest(request.to_string().as_bytes()))
+}
+
+pub fn encode_due(due: &Option<Due>) -> Option<String> {
+    match due {
+        Some(Due::Date(date)) => Some(date.format("%Y-%m-%d").to_string()),
+        Some(Due::Time(time)) => Some(time.to_rfc3339()),
+        None => None,
+    }
+}
+
+pub fn encode_list(list: &TodoList) -> String {
+    let response = json!({
+        "id": list.id.to_hyphenated().to_string(),
+        "name": list.name,
+        "version": list.version,
+        "created_at": list.created_at.to_rfc3339(),
+        "updated_at": list.updated_at.to_rfc3339(),
+    });
+
+    response.to_string()
+}
+
+pub fn decode_list(response: &str) -> Result<TodoList> {
+    let value = serde_json::from_str::<Value>(response)?;
+
+    Ok(TodoList {
+        id: decode_id(&value["id"], response)?,
+        name: decode_str(&value["name"], response)?,
+        version: decode_version(&value["version"], response)?,
+        created_at: decode_timestamp(&value["created_at"], response)?,
+        updated_at: decode_timestamp(&value["updated_at"], response)?,
+    })
+}
+
+pub fn encode_todo(todo: &Todo) -> String {
+    let (due_at, due_all_day) = Due::to_columns(&todo.due);
+
+    let response = json!({
+        "list_id": todo.list_id.to_hyphenated().to_string(),
+        "id": todo.id.to_hyphenated().to_string(),
+        "description": todo.description,
+        "done": todo.done,
+        "version": todo.version,
+        "created_at": todo.created_at.to_rfc3339(),
+        "updated_at": todo.updated_at.to_rfc3339(),
+        "completed_at": todo.completed_at.map(|completed_at| completed_at.to_rfc3339()),
+        "due_at": due_at.map(|due_at| due_at.to_rfc3339()),
+        "due_all_day": due_all_day,
+        "parent_id": todo.parent_id.map(|parent_id| parent_id.to_hyphenated().to_string()),
+        "position": todo.position,
+    });
+
+    response.to_string()
+}
+
+pub fn decode_todo(response: &str) -> Result<Todo> {
+    let value = serde_json::from_str::<Value>(response)?;
+
+    Ok(Todo {
+        list_id: decode_id(&value["list_id"], response)?,
+        id: decode_id(&value["id"], response)?,
+        description: decode_str(&value["description"], response)?,
+        done: value["done"]
+            .as_bool()
+            .ok_or_else(|| create_invalid_error(response))?,
+        version: decode_version(&value["version"], response)?,
+        created_at: decode_timestamp(&value["created_at"], response)?,
+        updated_at: decode_timestamp(&value["updated_at"], response)?,
+        completed_at: decode_optional_timestamp(&value["completed_at"], response)?,
+        due: Due::from_columns(
+            decode_optional_timestamp(&value["due_at"], response)?,
+            value["due_all_day"].as_bool().unwrap_or_default(),
+        ),
+        parent_id: decode_optional_id(&value["parent_id"], response)?,
+        position: value["position"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn decode_id(value: &Value, response: &str) -> Result<Uuid> {
+    value
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| create_invalid_error(response))
+}
+
+fn decode_optional_id(value: &Value, response: &str) -> Result<Option<Uuid>> {
+    match value {
+        Value::Null => return Ok(None),
+        value => return decode_id(value, response).map(Some),
+    };
+}
+
+fn decode_str(value: &Value, response: &str) -> Result<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| create_invalid_error(response))
+}
+
+fn decode_version(value: &Value, response: &str) -> Result<i64> {
+    value.as_i64().ok_or_else(|| create_invalid_error(response))
+}
+
+fn decode_timestamp(value: &Value, response: &str) -> Result<DateTime<Utc>> {
+    value
+        .as_str()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .ok_or_else(|| create_invalid_error(response))
+}
+
+fn decode_optional_timestamp(value: &Value, response: &str) -> Result<Option<DateTime<Utc>>> {
+    match value {
+        Value::Null => return Ok(None),
+        value => return decode_timestamp(value, response).map(Some),
+    };
+}
+
+fn create_invalid_error(response: &str) -> Error {
+    anyhow!("'{}' is not a valid stored response", response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn response_round_trip() {
+        let todo = Todo {
+            list_id: Uuid::new_v4(),
+            id: Uuid::new_v4(),
+            description: "new_todo".to_string(),
+            done: true,
+            version: 2,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: Some(Utc::now()),
+            due: Some(Due::Date(NaiveDate::from_ymd(2020, 6, 9))),
+            parent_id: Some(Uuid::new_v4()),
+            position: "V".to_string(),
+        };
+
+        let decoded = decode_todo(&encode_todo(&todo)).unwrap();
+
+        assert_eq!(decoded.id, todo.id);
+        assert_eq!(decoded.list_id, todo.list_id);
+        assert_eq!(decoded.description, todo.description);
+        assert_eq!(decoded.done, todo.done);
+        assert_eq!(decoded.version, todo.version);
+        assert_eq!(decoded.created_at, todo.created_at);
+        assert_eq!(decoded.completed_at, todo.completed_at);
+        assert_eq!(decoded.due, todo.due);
+        assert_eq!(decoded.parent_id, todo.parent_id);
+        assert_eq!(decoded.position, todo.position);
+        assert!(decode_list("{}").is_err());
+    }
+
+    #[test]
+    fn hash_depends_on_request() {
+        let first = hash_request("add_list", &[None, Some("first".to_string())]);
+        let second = hash_request("add_list", &[None, Some("second".to_string())]);
+
+        assert_eq!(
+            first,
+            hash_request("add_list", &[None, Some("first".to_string())])
+        );
+        assert_ne!(first, second);
+    }
+}
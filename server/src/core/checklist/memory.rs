@@ -0,0 +1,982 @@
+use super::super::common::model::Error as ModelError;
+use super::super::common::pagination::{self, Cursor, Page};
+use super::super::common::position;
+use super::idempotency::{self, IdempotencyClaim, IdempotencyRecord};
+use super::model::{
+    self, Due, DueRange, ListOrder, ListUpdate, NewTodo, Tag, Todo, TodoList, TodoOrder, TodoUpdate,
+};
+use super::store::ChecklistStore;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(Debug, Default)]
+struct State {
+    lists: HashMap<Uuid, TodoList>,
+    todos: HashMap<Uuid, Todo>,
+    tags: HashMap<Uuid, Tag>,
+    todo_tags: HashSet<(Uuid, Uuid)>,
+    idempotency_records: HashMap<String, IdempotencyRecord>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryModel {
+    state: RwLock<State>,
+}
+
+impl MemoryModel {
+    pub fn new() -> MemoryModel {
+        MemoryModel::default()
+    }
+}
+
+#[async_trait]
+impl ChecklistStore for MemoryModel {
+    async fn create_list(
+        &self,
+        id: &Option<&Uuid>,
+        name: &str,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<TodoList> {
+        let id = match *id {
+            Some(id) => id.to_owned(),
+            None => Uuid::new_v4(),
+        };
+
+        let mut state = self.state.write().unwrap();
+
+        check_claim(&state, claim)?;
+
+        if state.lists.contains_key(&id) {
+            return Err(Error::new(ModelError::Conflict(id)));
+        }
+
+        let now = Utc::now();
+
+        let list = TodoList {
+            id,
+            name: name.to_string(),
+            version: 1,
+            created_at: now,
+            updated_at: now,
+        };
+
+        state.lists.insert(id, list.clone());
+        save_claim(&mut state, claim, idempotency::encode_list(&list));
+
+        Ok(list)
+    }
+
+    async fn get_list(&self, id: &Uuid) -> Result<TodoList> {
+        let state = self.state.read().unwrap();
+
+        match state.lists.get(id) {
+            Some(list) => return Ok(list.clone()),
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn list_lists(
+        &self,
+        page_size: i64,
+        page_token: &Option<&str>,
+        name_prefix: &Option<&str>,
+        name_contains: &Option<&str>,
+        order: ListOrder,
+    ) -> Result<Page<TodoList>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let name_contains = name_contains.map(str::to_lowercase);
+        let state = self.state.read().unwrap();
+
+        let mut entries = state
+            .lists
+            .values()
+            .filter(|list| match name_prefix {
+                Some(prefix) => list.name.starts_with(prefix),
+                None => true,
+            })
+            .filter(|list| match &name_contains {
+                Some(needle) => list.name.to_lowercase().contains(needle),
+                None => true,
+            })
+            .map(|list| {
+                // Fixed-width timestamps sort as text in the same order as in time.
+                let key = match order {
+                    ListOrder::Name => list.name.clone(),
+                    ListOrder::CreatedAt => {
+                        list.created_at.to_rfc3339_opts(SecondsFormat::Nanos, true)
+                    }
+                };
+
+                Cursor { key, id: list.id }
+            })
+            .collect::<Vec<Cursor>>();
+
+        let page = paginate(&mut entries, cursor, page_size);
+
+        let lists = page
+            .iter()
+            .map(|entry| state.lists[&entry.id].clone())
+            .collect();
+
+        Ok(Page {
+            items: lists,
+            next_page_token: next_page_token(&page, &entries, order.as_str()),
+        })
+    }
+
+    async fn update_list(
+        &self,
+        id: &Uuid,
+        update: &ListUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<TodoList> {
+        let mut state = self.state.write().unwrap();
+
+        match state.lists.get_mut(id) {
+            Some(list) => {
+                model::check_version(id, list.version, expected_version)?;
+
+                if update.columns().is_empty() {
+                    return Ok(list.clone());
+                }
+
+                if let Some(name) = &update.name {
+                    list.name = name.to_string();
+                }
+
+                list.updated_at = Utc::now();
+                list.version += 1;
+                return Ok(list.clone());
+            }
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn destroy_list(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        match state.lists.get(id) {
+            Some(list) => model::check_version(id, list.version, expected_version)?,
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+
+        state.lists.remove(id);
+
+        state.todos.retain(|_, todo| todo.list_id != *id);
+
+        let todos = &state.todos;
+        state
+            .todo_tags
+            .retain(|(todo_id, _)| todos.contains_key(todo_id));
+
+        Ok(())
+    }
+
+    async fn create_todo(
+        &self,
+        id: &Option<&Uuid>,
+        list_id: &Uuid,
+        description: &str,
+        due: &Option<Due>,
+        parent_id: &Option<&Uuid>,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<Todo> {
+        let id = match *id {
+            Some(id) => id.to_owned(),
+            None => Uuid::new_v4(),
+        };
+
+        let mut state = self.state.write().unwrap();
+
+        check_claim(&state, claim)?;
+
+        if state.todos.contains_key(&id) {
+            return Err(Error::new(ModelError::Conflict(id)));
+        }
+
+        if !state.lists.contains_key(list_id) {
+            return Err(Error::new(ModelError::Validation(format!(
+                "list ID '{}' not in collection",
+                list_id
+            ))));
+        }
+
+        let last_position = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == *list_id)
+            .map(|todo| todo.position.as_str())
+            .max();
+
+        let position = position::after(last_position);
+        let now = Utc::now();
+
+        let todo = Todo {
+            list_id: list_id.to_owned(),
+            id,
+            description: description.to_string(),
+            done: false,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            due: *due,
+            parent_id: parent_id.copied(),
+            position,
+        };
+
+        state.todos.insert(todo.id, todo.clone());
+        save_claim(&mut state, claim, idempotency::encode_todo(&todo));
+
+        Ok(todo)
+    }
+
+    async fn create_todos(&self, list_id: &Uuid, todos: &[NewTodo]) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        if !state.lists.contains_key(list_id) {
+            return Err(Error::new(ModelError::Validation(format!(
+                "list ID '{}' not in collection",
+                list_id
+            ))));
+        }
+
+        let mut position = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == *list_id)
+            .map(|todo| todo.position.clone())
+            .max();
+
+        let now = Utc::now();
+        let mut created = Vec::with_capacity(todos.len());
+
+        for new_todo in todos {
+            let id = match new_todo.id {
+                Some(id) => id,
+                None => Uuid::new_v4(),
+            };
+
+            if state.todos.contains_key(&id) || created.iter().any(|todo: &Todo| todo.id == id) {
+                return Err(Error::new(ModelError::Validation(
+                    "a todo ID in the batch is already in collection".to_string(),
+                )));
+            }
+
+            let next = position::after(position.as_deref());
+
+            created.push(Todo {
+                list_id: *list_id,
+                id,
+                description: new_todo.description.clone(),
+                done: false,
+                version: 1,
+                created_at: now,
+                updated_at: now,
+                completed_at: None,
+                due: new_todo.due,
+                parent_id: new_todo.parent_id,
+                position: next.clone(),
+            });
+
+            position = Some(next);
+        }
+
+        for todo in &created {
+            state.todos.insert(todo.id, todo.clone());
+        }
+
+        Ok(created)
+    }
+
+    async fn get_todo(&self, id: &Uuid) -> Result<Todo> {
+        let state = self.state.read().unwrap();
+
+        match state.todos.get(id) {
+            Some(todo) => return Ok(todo.clone()),
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn get_todos_for_lists(&self, list_ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let state = self.state.read().unwrap();
+
+        let mut todos = state
+            .todos
+            .values()
+            .filter(|todo| list_ids.contains(&todo.list_id))
+            .cloned()
+            .collect::<Vec<Todo>>();
+
+        todos.sort_by(|a, b| (a.list_id, &a.position, a.id).cmp(&(b.list_id, &b.position, b.id)));
+
+        Ok(todos)
+    }
+
+    async fn get_todos_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let state = self.state.read().unwrap();
+
+        let mut todos = ids
+            .iter()
+            .filter_map(|id| state.todos.get(id))
+            .cloned()
+            .collect::<Vec<Todo>>();
+
+        todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+        todos.dedup_by_key(|todo| todo.id);
+
+        Ok(todos)
+    }
+
+    async fn list_todos(
+        &self,
+        list_id: &Uuid,
+        page_size: i64,
+        page_token: &Option<&str>,
+        done: &Option<bool>,
+        tag_ids: &[Uuid],
+        order: TodoOrder,
+    ) -> Result<Page<Todo>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let state = self.state.read().unwrap();
+
+        let mut entries = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == *list_id)
+            .filter(|todo| match done {
+                Some(done) => todo.done == *done,
+                None => true,
+            })
+            .filter(|todo| {
+                tag_ids
+                    .iter()
+                    .all(|tag_id| state.todo_tags.contains(&(todo.id, *tag_id)))
+            })
+            .map(|todo| {
+                let key = match order {
+                    TodoOrder::Id => todo.id.to_hyphenated().to_string(),
+                    TodoOrder::Description => todo.description.clone(),
+                    TodoOrder::Position => todo.position.clone(),
+                };
+
+                Cursor { key, id: todo.id }
+            })
+            .collect::<Vec<Cursor>>();
+
+        let page = paginate(&mut entries, cursor, page_size);
+
+        let todos = page
+            .iter()
+            .map(|entry| state.todos[&entry.id].clone())
+            .collect();
+
+        Ok(Page {
+            items: todos,
+            next_page_token: next_page_token(&page, &entries, order.as_str()),
+        })
+    }
+
+    async fn update_todo(
+        &self,
+        id: &Uuid,
+        update: &TodoUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo> {
+        let mut state = self.state.write().unwrap();
+
+        if let Some(Some(parent_id)) = update.parent_id {
+            if collect_subtree(&state, id).contains(&parent_id) {
+                return Err(model::create_nesting_error(id));
+            }
+        }
+
+        match state.todos.get_mut(id) {
+            Some(todo) => {
+                model::check_version(id, todo.version, expected_version)?;
+
+                if update.columns().is_empty() {
+                    return Ok(todo.clone());
+                }
+
+                if let Some(description) = &update.description {
+                    todo.description = description.to_string();
+                }
+
+                if let Some(done) = update.done {
+                    mark_done(todo, done);
+                }
+
+                if let Some(due) = update.due {
+                    todo.due = due;
+                }
+
+                if let Some(parent_id) = update.parent_id {
+                    todo.parent_id = parent_id;
+                }
+
+                todo.updated_at = Utc::now();
+                todo.version += 1;
+                return Ok(todo.clone());
+            }
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn get_todo_tree(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let state = self.state.read().unwrap();
+
+        if !state.todos.contains_key(id) {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        let mut todos = collect_subtree(&state, id)
+            .iter()
+            .map(|id| state.todos[id].clone())
+            .collect::<Vec<Todo>>();
+
+        todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+        Ok(todos)
+    }
+
+    async fn get_subtasks(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let state = self.state.read().unwrap();
+
+        let mut todos = state
+            .todos
+            .values()
+            .filter(|todo| todo.parent_id == Some(*parent_id))
+            .cloned()
+            .collect::<Vec<Todo>>();
+
+        todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+        Ok(todos)
+    }
+
+    async fn move_todo_to_list(&self, id: &Uuid, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        let todo = match state.todos.get(id) {
+            Some(todo) => todo.clone(),
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+
+        if todo.list_id == *list_id {
+            return Ok(vec![todo]);
+        }
+
+        if !state.lists.contains_key(list_id) {
+            return Err(Error::new(ModelError::Validation(format!(
+                "list ID '{}' not in collection",
+                list_id
+            ))));
+        }
+
+        let mut moved = collect_subtree(&state, id)
+            .into_iter()
+            .filter(|todo_id| todo_id != id)
+            .map(|todo_id| state.todos[&todo_id].clone())
+            .collect::<Vec<Todo>>();
+
+        moved.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+        moved.insert(0, todo);
+
+        let mut position = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == *list_id)
+            .map(|todo| todo.position.clone())
+            .max();
+
+        let now = Utc::now();
+
+        for todo in moved.iter_mut() {
+            let next = position::after(position.as_deref());
+
+            if todo.id == *id {
+                todo.parent_id = None;
+            }
+
+            todo.list_id = *list_id;
+            todo.position = next.clone();
+            todo.updated_at = now;
+            todo.version += 1;
+            state.todos.insert(todo.id, todo.clone());
+            position = Some(next);
+        }
+
+        Ok(moved)
+    }
+
+    async fn get_adjacent_todo(
+        &self,
+        anchor: &Todo,
+        following: bool,
+        exclude_id: &Uuid,
+    ) -> Result<Option<Todo>> {
+        let state = self.state.read().unwrap();
+        let key = (&anchor.position, anchor.id);
+
+        let candidates = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == anchor.list_id && todo.id != *exclude_id)
+            .filter(|todo| match following {
+                true => (&todo.position, todo.id) > key,
+                false => (&todo.position, todo.id) < key,
+            });
+
+        let adjacent = match following {
+            true => candidates.min_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id))),
+            false => candidates.max_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id))),
+        };
+
+        Ok(adjacent.cloned())
+    }
+
+    async fn set_position(&self, id: &Uuid, position: &str) -> Result<Todo> {
+        let mut state = self.state.write().unwrap();
+
+        match state.todos.get_mut(id) {
+            Some(todo) => {
+                todo.position = position.to_string();
+                todo.updated_at = Utc::now();
+                todo.version += 1;
+                return Ok(todo.clone());
+            }
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn rebalance_positions(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        let mut todos = state
+            .todos
+            .values_mut()
+            .filter(|todo| todo.list_id == *list_id)
+            .collect::<Vec<&mut Todo>>();
+
+        todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+        let positions = position::spread(todos.len());
+
+        Ok(todos
+            .into_iter()
+            .zip(positions)
+            .map(|(todo, position)| {
+                todo.position = position;
+                touch_todo(todo)
+            })
+            .collect())
+    }
+
+    async fn set_done(&self, id: &Uuid, done: bool) -> Result<Todo> {
+        let mut state = self.state.write().unwrap();
+
+        match state.todos.get_mut(id) {
+            Some(todo) => {
+                set_todo_done(todo, done);
+                return Ok(todo.clone());
+            }
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+    }
+
+    async fn complete_todo(
+        &self,
+        id: &Uuid,
+        done: bool,
+        cascade: bool,
+        expected_version: &Option<i64>,
+    ) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        let todo = match state.todos.get_mut(id) {
+            Some(todo) => {
+                model::check_version(id, todo.version, expected_version)?;
+                set_todo_done(todo, done);
+                todo.clone()
+            }
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+
+        let parent_id = todo.parent_id;
+        let mut changed = vec![todo];
+
+        if done && cascade {
+            let subtree = collect_subtree(&state, id);
+
+            let mut subtasks = state
+                .todos
+                .values_mut()
+                .filter(|todo| subtree.contains(&todo.id) && !todo.done)
+                .collect::<Vec<&mut Todo>>();
+
+            subtasks.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+            for subtask in subtasks {
+                set_todo_done(subtask, true);
+                changed.push(subtask.clone());
+            }
+        }
+
+        if let Some(parent_id) = parent_id {
+            changed.extend(roll_up(&mut state, &parent_id));
+        }
+
+        Ok(changed)
+    }
+
+    async fn roll_up(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+        Ok(roll_up(&mut state, parent_id))
+    }
+
+    async fn get_due_todos(&self, timed: &DueRange, all_day: &DueRange) -> Result<Vec<Todo>> {
+        let state = self.state.read().unwrap();
+
+        let mut todos = state
+            .todos
+            .values()
+            .filter(|todo| !todo.done)
+            .filter_map(|todo| match Due::to_columns(&todo.due) {
+                (Some(due_at), true) if all_day.contains(due_at) => Some((due_at, todo)),
+                (Some(due_at), false) if timed.contains(due_at) => Some((due_at, todo)),
+                _ => None,
+            })
+            .collect::<Vec<(DateTime<Utc>, &Todo)>>();
+
+        todos.sort_by(|a, b| (a.0, a.1.id).cmp(&(b.0, b.1.id)));
+
+        Ok(todos.into_iter().map(|(_, todo)| todo.clone()).collect())
+    }
+
+    async fn complete_todos(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+        let now = Utc::now();
+
+        let mut todos = state
+            .todos
+            .values_mut()
+            .filter(|todo| todo.list_id == *list_id && !todo.done)
+            .map(|todo| {
+                mark_done(todo, true);
+                todo.updated_at = now;
+                todo.version += 1;
+                todo.clone()
+            })
+            .collect::<Vec<Todo>>();
+
+        todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+        Ok(todos)
+    }
+
+    async fn destroy_completed_todos(&self, list_id: &Uuid) -> Result<Vec<Uuid>> {
+        let mut state = self.state.write().unwrap();
+
+        let removed = state
+            .todos
+            .values()
+            .filter(|todo| todo.list_id == *list_id && todo.done)
+            .flat_map(|todo| collect_subtree(&state, &todo.id))
+            .collect::<HashSet<Uuid>>();
+
+        state.todos.retain(|todo_id, _| !removed.contains(todo_id));
+        state
+            .todo_tags
+            .retain(|(todo_id, _)| !removed.contains(todo_id));
+
+        Ok(removed.into_iter().collect())
+    }
+
+    async fn destroy_todo(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<Vec<Uuid>> {
+        let mut state = self.state.write().unwrap();
+
+        match state.todos.get(id) {
+            Some(todo) => model::check_version(id, todo.version, expected_version)?,
+            None => return Err(Error::new(ModelError::NotFound(*id))),
+        };
+
+        let removed = collect_subtree(&state, id);
+
+        state.todos.retain(|todo_id, _| !removed.contains(todo_id));
+        state
+            .todo_tags
+            .retain(|(todo_id, _)| !removed.contains(todo_id));
+
+        let mut ids = vec![*id];
+        ids.extend(removed.into_iter().filter(|todo_id| todo_id != id));
+
+        Ok(ids)
+    }
+
+    async fn create_tag(&self, name: &str) -> Result<Tag> {
+        let mut state = self.state.write().unwrap();
+
+        check_tag_name(&state, name)?;
+
+        let tag = Tag {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+        };
+
+        state.tags.insert(tag.id, tag.clone());
+
+        Ok(tag)
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>> {
+        let state = self.state.read().unwrap();
+
+        let mut tags = state.tags.values().cloned().collect::<Vec<Tag>>();
+        tags.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+
+        Ok(tags)
+    }
+
+    async fn rename_tag(&self, id: &Uuid, name: &str) -> Result<(Tag, Vec<Todo>)> {
+        let mut state = self.state.write().unwrap();
+
+        if !state.tags.contains_key(id) {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        if state.tags[id].name != name {
+            check_tag_name(&state, name)?;
+        }
+
+        let tag = state.tags.get_mut(id).unwrap();
+        tag.name = name.to_string();
+        let tag = tag.clone();
+
+        Ok((tag, touch_tagged_todos(&mut state, id)))
+    }
+
+    async fn destroy_tag(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        if state.tags.remove(id).is_none() {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        let todos = touch_tagged_todos(&mut state, id);
+        state.todo_tags.retain(|(_, tag_id)| tag_id != id);
+
+        Ok(todos)
+    }
+
+    async fn attach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        if !state.todos.contains_key(todo_id) {
+            return Err(Error::new(ModelError::NotFound(*todo_id)));
+        }
+
+        if !state.tags.contains_key(tag_id) {
+            return Err(Error::new(ModelError::NotFound(*tag_id)));
+        }
+
+        if !state.todo_tags.insert((*todo_id, *tag_id)) {
+            return Ok(None);
+        }
+
+        Ok(state.todos.get_mut(todo_id).map(touch_todo))
+    }
+
+    async fn detach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let mut state = self.state.write().unwrap();
+
+        if !state.todo_tags.remove(&(*todo_id, *tag_id)) {
+            return Ok(None);
+        }
+
+        Ok(state.todos.get_mut(todo_id).map(touch_todo))
+    }
+
+    async fn get_tags_for_todo(&self, todo_id: &Uuid) -> Result<Vec<Tag>> {
+        let state = self.state.read().unwrap();
+
+        let mut tags = state
+            .todo_tags
+            .iter()
+            .filter(|(id, _)| id == todo_id)
+            .map(|(_, tag_id)| state.tags[tag_id].clone())
+            .collect::<Vec<Tag>>();
+
+        tags.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+
+        Ok(tags)
+    }
+
+    async fn get_idempotency_record(&self, key: &str) -> Result<Option<IdempotencyRecord>> {
+        let state = self.state.read().unwrap();
+        Ok(state.idempotency_records.get(key).cloned())
+    }
+}
+
+fn check_claim(state: &State, claim: &Option<&IdempotencyClaim>) -> Result<()> {
+    match claim {
+        Some(claim) if state.idempotency_records.contains_key(&claim.key) => {
+            return Err(Error::new(ModelError::KeyConflict(claim.key.clone())))
+        }
+        _ => return Ok(()),
+    };
+}
+
+fn save_claim(state: &mut State, claim: &Option<&IdempotencyClaim>, response: String) {
+    if let Some(claim) = claim {
+        let record = IdempotencyRecord {
+            key: claim.key.clone(),
+            request_hash: claim.request_hash.clone(),
+            response,
+        };
+
+        state.idempotency_records.insert(record.key.clone(), record);
+    }
+}
+
+// The subtree holds the todo itself and every subtask beneath it, however deeply nested.
+fn collect_subtree(state: &State, id: &Uuid) -> HashSet<Uuid> {
+    let mut subtree = HashSet::new();
+    let mut pending = vec![*id];
+
+    while let Some(id) = pending.pop() {
+        if !subtree.insert(id) {
+            continue;
+        }
+
+        pending.extend(
+            state
+                .todos
+                .values()
+                .filter(|todo| todo.parent_id == Some(id))
+                .map(|todo| todo.id),
+        );
+    }
+
+    subtree
+}
+
+// Finishing the last open subtask completes the parent, and an open subtask reopens it. Either
+// change can carry on up through the ancestors.
+fn roll_up(state: &mut State, parent_id: &Uuid) -> Vec<Todo> {
+    let mut changed = Vec::new();
+    let mut next = Some(*parent_id);
+
+    while let Some(id) = next {
+        let subtasks = state
+            .todos
+            .values()
+            .filter(|todo| todo.parent_id == Some(id))
+            .map(|todo| todo.done)
+            .collect::<Vec<bool>>();
+
+        let done = subtasks.iter().all(|done| *done);
+
+        let parent = match state.todos.get_mut(&id) {
+            Some(parent) => parent,
+            None => break,
+        };
+
+        if subtasks.is_empty() || parent.done == done {
+            break;
+        }
+
+        set_todo_done(parent, done);
+        changed.push(parent.clone());
+        next = parent.parent_id;
+    }
+
+    changed
+}
+
+fn check_tag_name(state: &State, name: &str) -> Result<()> {
+    if state.tags.values().any(|tag| tag.name == name) {
+        return Err(Error::new(ModelError::NameConflict(name.to_string())));
+    }
+
+    Ok(())
+}
+
+fn touch_tagged_todos(state: &mut State, tag_id: &Uuid) -> Vec<Todo> {
+    let ids = state
+        .todo_tags
+        .iter()
+        .filter(|(_, id)| id == tag_id)
+        .map(|(todo_id, _)| *todo_id)
+        .collect::<HashSet<Uuid>>();
+
+    let mut todos = state
+        .todos
+        .values_mut()
+        .filter(|todo| ids.contains(&todo.id))
+        .map(touch_todo)
+        .collect::<Vec<Todo>>();
+
+    todos.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+    todos
+}
+
+fn touch_todo(todo: &mut Todo) -> Todo {
+    todo.updated_at = Utc::now();
+    todo.version += 1;
+    todo.clone()
+}
+
+fn set_todo_done(todo: &mut Todo, done: bool) {
+    mark_done(todo, done);
+    touch_todo(todo);
+}
+
+fn mark_done(todo: &mut Todo, done: bool) {
+    todo.completed_at = match (done, todo.completed_at) {
+        (true, Some(completed_at)) => Some(completed_at),
+        (true, None) => Some(Utc::now()),
+        (false, _) => None,
+    };
+
+    todo.done = done;
+}
+
+fn paginate(entries: &mut Vec<Cursor>, cursor: Option<Cursor>, page_size: i64) -> Vec<Cursor> {
+    entries.sort_by(|a, b| (&a.key, a.id).cmp(&(&b.key, b.id)));
+
+    if let Some(cursor) = cursor {
+        entries.retain(|entry| (&entry.key, entry.id) > (&cursor.key, cursor.id));
+    }
+
+    entries
+        .iter()
+        .take(page_size as usize)
+        .map(|entry| Cursor {
+            key: entry.key.clone(),
+            id: entry.id,
+        })
+        .collect()
+}
+
+fn next_page_token(page: &[Cursor], entries: &[Cursor], order: &str) -> Option<String> {
+    if entries.len() <= page.len() {
+        return None;
+    }
+
+    page.last()
+        .map(|cursor| pagination::encode_page_token(order, cursor))
+}
@@ -1,2 +1,7 @@
+pub mod event;
+pub mod idempotency;
+pub mod memory;
 pub mod model;
 pub mod service;
+pub mod sqlite;
+pub mod store;
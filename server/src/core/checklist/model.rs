@@ -1,100 +1,1718 @@
 use super::super::common::model::Error as ModelError;
+use super::super::common::pagination::{self, Cursor, Page};
+use super::super::common::position;
 use super::super::database;
+use super::event::{ChangeKind, Event, Notification, NOTIFICATION_CHANNEL};
+use super::idempotency::{self, IdempotencyClaim, IdempotencyRecord};
+use super::store::ChecklistStore;
 use anyhow::{Error, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 use database::ErrorCode as DatabaseErrorCode;
-use sqlx::{postgres::PgQueryAs, Error as SqlxError, PgPool};
+use database::{create_insert_statement, create_not_found_error, create_update_statement};
+use database::{extract_database_error_code, MAX_INSERT_ROWS};
+use sqlx::postgres::{PgListener, PgQueryAs};
+use sqlx::{pool::PoolConnection, Error as SqlxError, PgConnection, PgPool, Transaction};
+use std::cmp;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_LISTEN_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 const INSERT_LIST: &str = "
   INSERT INTO lists (id, name)
+  VALUES ($1, $2)
+  RETURNING created_at, updated_at;
+";
+
+const SELECT_LIST: &str = "
+  SELECT id, name, version, created_at, updated_at FROM lists
+  WHERE id = $1;
+";
+
+const SELECT_LISTS_BY_NAME: &str = "
+  SELECT id, name, version, created_at, updated_at, name FROM lists
+  WHERE ($1::TEXT IS NULL OR name LIKE $1 || '%')
+    AND ($2::TEXT IS NULL OR name ILIKE '%' || $2 || '%')
+    AND ($3::TEXT IS NULL OR (name, id) > ($3, $4))
+  ORDER BY name, id
+  LIMIT $5;
+";
+
+const SELECT_LISTS_BY_CREATED_AT: &str = "
+  SELECT id, name, version, created_at, updated_at, created_at::TEXT FROM lists
+  WHERE ($1::TEXT IS NULL OR name LIKE $1 || '%')
+    AND ($2::TEXT IS NULL OR name ILIKE '%' || $2 || '%')
+    AND ($3::TEXT IS NULL OR (created_at, id) > ($3::TIMESTAMPTZ, $4))
+  ORDER BY created_at, id
+  LIMIT $5;
+";
+
+const DELETE_LIST: &str = "
+  DELETE FROM lists
+  WHERE id = $1
+    AND ($2::BIGINT IS NULL OR version = $2)
+  RETURNING id;
+";
+
+const INSERT_TODO: &str = "
+  INSERT INTO todos (list_id, id, description, done, due_at, due_all_day, parent_id, position)
+  VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+  RETURNING created_at, updated_at;
+";
+
+const SELECT_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id = $1;
+";
+
+const SELECT_TODO_FOR_UPDATE: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id = $1
+  FOR UPDATE;
+";
+
+const SELECT_TODOS_FOR_LISTS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ANY($1)
+  ORDER BY list_id, position, id;
+";
+
+const SELECT_TODOS_BY_IDS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id = ANY($1)
+  ORDER BY position, id;
+";
+
+const SELECT_TODOS_BY_ID: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = $1
+    AND ($2::BOOLEAN IS NULL OR done = $2)
+    AND ($3::UUID IS NULL OR id > $3)
+    AND (cardinality($5::UUID[]) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id = ANY($5)
+      GROUP BY todo_id
+      HAVING COUNT(*) = cardinality($5)
+    ))
+  ORDER BY id
+  LIMIT $4;
+";
+
+const SELECT_TODOS_BY_DESCRIPTION: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = $1
+    AND ($2::BOOLEAN IS NULL OR done = $2)
+    AND ($3::TEXT IS NULL OR (description, id) > ($3, $4))
+    AND (cardinality($6::UUID[]) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id = ANY($6)
+      GROUP BY todo_id
+      HAVING COUNT(*) = cardinality($6)
+    ))
+  ORDER BY description, id
+  LIMIT $5;
+";
+
+const SELECT_TODOS_BY_POSITION: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = $1
+    AND ($2::BOOLEAN IS NULL OR done = $2)
+    AND ($3::TEXT IS NULL OR (position, id) > ($3, $4))
+    AND (cardinality($6::UUID[]) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id = ANY($6)
+      GROUP BY todo_id
+      HAVING COUNT(*) = cardinality($6)
+    ))
+  ORDER BY position, id
+  LIMIT $5;
+";
+
+const SELECT_NEXT_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = $1
+    AND (position, id) > ($2, $3)
+    AND id <> $4
+  ORDER BY position, id
+  LIMIT 1;
+";
+
+const SELECT_PREVIOUS_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = $1
+    AND (position, id) < ($2, $3)
+    AND id <> $4
+  ORDER BY position DESC, id DESC
+  LIMIT 1;
+";
+
+const LOCK_LIST: &str = "
+  SELECT id FROM lists
+  WHERE id = $1
+  FOR UPDATE;
+";
+
+const SELECT_LAST_POSITION: &str = "
+  SELECT MAX(position) FROM todos
+  WHERE list_id = $1;
+";
+
+const UPDATE_TODO_POSITION: &str = "
+  UPDATE todos
+  SET position = $2,
+    updated_at = now(),
+    version = version + 1
+  WHERE id = $1
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const UPDATE_TODO_LIST: &str = "
+  UPDATE todos
+  SET list_id = $2,
+    parent_id = NULL
+  WHERE id = $1;
+";
+
+const UPDATE_MOVED_TODOS: &str = "
+  UPDATE todos
+  SET position = moved.position,
+    updated_at = now(),
+    version = todos.version + 1
+  FROM unnest($1::UUID[], $2::TEXT[]) AS moved (id, position)
+  WHERE todos.id = moved.id
+  RETURNING todos.list_id, todos.id, todos.description, todos.done, todos.version,
+    todos.created_at, todos.updated_at, todos.completed_at, todos.due_at, todos.due_all_day,
+    todos.parent_id, todos.position;
+";
+
+const SELECT_TODO_IDS_BY_POSITION: &str = "
+  SELECT id FROM todos
+  WHERE list_id = $1
+  ORDER BY position, id
+  FOR UPDATE;
+";
+
+const LOCK_TODO_LIST: &str = "
+  SELECT lists.id FROM lists
+  JOIN todos ON todos.list_id = lists.id
+  WHERE todos.id = $1
+  FOR UPDATE OF lists;
+";
+
+const SELECT_TODO_TREE: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE id = $1
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id IN (SELECT id FROM tree)
+  ORDER BY position, id;
+";
+
+const SELECT_SUBTASKS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE parent_id = $1
+  ORDER BY position, id;
+";
+
+const UPDATE_TODO_DONE: &str = "
+  UPDATE todos
+  SET done = $2,
+    completed_at = CASE WHEN $2 THEN COALESCE(completed_at, now()) ELSE NULL END,
+    updated_at = now(),
+    version = version + 1
+  WHERE id = $1
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const COMPLETE_SUBTREE: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE parent_id = $1
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  UPDATE todos
+  SET done = TRUE,
+    completed_at = now(),
+    updated_at = now(),
+    version = version + 1
+  WHERE id IN (SELECT id FROM tree)
+    AND NOT done
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const SELECT_SUBTASK_COUNTS: &str = "
+  SELECT done, parent_id,
+    (SELECT COUNT(*) FROM todos AS subtasks WHERE subtasks.parent_id = todos.id),
+    (SELECT COUNT(*) FROM todos AS subtasks WHERE subtasks.parent_id = todos.id AND NOT subtasks.done)
+  FROM todos
+  WHERE id = $1;
+";
+
+const SELECT_DUE_TODOS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE NOT done
+    AND (
+      (NOT due_all_day AND ($1::TIMESTAMPTZ IS NULL OR due_at >= $1) AND due_at < $2)
+      OR (due_all_day AND ($3::TIMESTAMPTZ IS NULL OR due_at >= $3) AND due_at < $4)
+    )
+  ORDER BY due_at, id;
+";
+
+const DELETE_TODO: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE id = $1
+      AND ($2::BIGINT IS NULL OR version = $2)
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  DELETE FROM todos
+  WHERE id IN (SELECT id FROM tree)
+  RETURNING list_id, id;
+";
+
+const SELECT_IDEMPOTENCY_RECORD: &str = "
+  SELECT idempotency_key, request_hash, response FROM idempotency_keys
+  WHERE idempotency_key = $1;
+";
+
+const INSERT_IDEMPOTENCY_CLAIM: &str = "
+  INSERT INTO idempotency_keys (idempotency_key, request_hash, response)
+  VALUES ($1, $2, '');
+";
+
+const UPDATE_IDEMPOTENCY_RESPONSE: &str = "
+  UPDATE idempotency_keys SET response = $2
+  WHERE idempotency_key = $1;
+";
+
+const INSERT_TAG: &str = "
+  INSERT INTO tags (id, name)
   VALUES ($1, $2);
 ";
 
-const SELECT_LIST: &str = "
-  SELECT id, name FROM lists
-  WHERE id = $1;
-";
+const SELECT_TAGS: &str = "
+  SELECT id, name FROM tags
+  ORDER BY name, id;
+";
+
+const UPDATE_TAG: &str = "
+  UPDATE tags
+  SET name = $2
+  WHERE id = $1
+  RETURNING id, name;
+";
+
+const DELETE_TAG: &str = "
+  DELETE FROM tags
+  WHERE id = $1
+  RETURNING id;
+";
+
+const INSERT_TODO_TAG: &str = "
+  WITH inserted AS (
+    INSERT INTO todo_tags (todo_id, tag_id)
+    VALUES ($1, $2)
+    ON CONFLICT (todo_id, tag_id) DO NOTHING
+    RETURNING todo_id
+  )
+  UPDATE todos
+  SET updated_at = now(),
+    version = version + 1
+  WHERE id IN (SELECT todo_id FROM inserted)
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const DELETE_TODO_TAG: &str = "
+  WITH deleted AS (
+    DELETE FROM todo_tags
+    WHERE todo_id = $1 AND tag_id = $2
+    RETURNING todo_id
+  )
+  UPDATE todos
+  SET updated_at = now(),
+    version = version + 1
+  WHERE id IN (SELECT todo_id FROM deleted)
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const UPDATE_TAGGED_TODOS: &str = "
+  UPDATE todos
+  SET updated_at = now(),
+    version = version + 1
+  WHERE id IN (SELECT todo_id FROM todo_tags WHERE tag_id = $1)
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const SELECT_TAGS_FOR_TODO: &str = "
+  SELECT tags.id, tags.name FROM tags
+  JOIN todo_tags ON todo_tags.tag_id = tags.id
+  WHERE todo_tags.todo_id = $1
+  ORDER BY tags.name, tags.id;
+";
+
+const COMPLETE_TODOS: &str = "
+  UPDATE todos
+  SET done = TRUE,
+    completed_at = now(),
+    updated_at = now(),
+    version = version + 1
+  WHERE list_id = $1
+    AND NOT done
+  RETURNING list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position;
+";
+
+const DELETE_COMPLETED_TODOS: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE list_id = $1
+      AND done
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  DELETE FROM todos
+  WHERE id IN (SELECT id FROM tree)
+  RETURNING id;
+";
+
+const TODO_INSERT_COLUMNS: &[&str] = &[
+    "list_id",
+    "id",
+    "description",
+    "done",
+    "due_at",
+    "due_all_day",
+    "parent_id",
+    "position",
+];
+
+const NOTIFY: &str = "SELECT pg_notify($1, $2);";
+
+const NOW: &str = "now()";
+
+pub type ListRow = (Uuid, String, i64, DateTime<Utc>, DateTime<Utc>);
+
+#[derive(sqlx::FromRow)]
+pub struct TodoRow {
+    pub list_id: Uuid,
+    pub id: Uuid,
+    pub description: String,
+    pub done: bool,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_all_day: bool,
+    pub parent_id: Option<Uuid>,
+    pub position: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoList {
+    pub id: Uuid,
+    pub name: String,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Todo {
+    pub list_id: Uuid,
+    pub id: Uuid,
+    pub description: String,
+    pub done: bool,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due: Option<Due>,
+    pub parent_id: Option<Uuid>,
+    pub position: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoTree {
+    pub todo: Todo,
+    pub subtasks: Vec<TodoTree>,
+}
+
+impl TodoTree {
+    // Assembles the tree rooted at `root_id` from the root and its descendants in any order.
+    pub fn build(root_id: &Uuid, todos: Vec<Todo>) -> Option<TodoTree> {
+        let mut root = None;
+        let mut subtasks = HashMap::<Uuid, Vec<Todo>>::new();
+
+        for todo in todos {
+            if todo.id == *root_id {
+                root = Some(todo);
+            } else if let Some(parent_id) = todo.parent_id {
+                subtasks.entry(parent_id).or_default().push(todo);
+            }
+        }
+
+        root.map(|root| TodoTree::attach(root, &mut subtasks))
+    }
+
+    fn attach(todo: Todo, subtasks: &mut HashMap<Uuid, Vec<Todo>>) -> TodoTree {
+        let children = subtasks.remove(&todo.id).unwrap_or_default();
+
+        TodoTree {
+            todo,
+            subtasks: children
+                .into_iter()
+                .map(|child| TodoTree::attach(child, subtasks))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Due {
+    Date(NaiveDate),
+    Time(DateTime<Utc>),
+}
+
+impl Due {
+    // An all-day date is stored as midnight UTC next to a flag, so both kinds share one column.
+    pub fn to_columns(due: &Option<Due>) -> (Option<DateTime<Utc>>, bool) {
+        match due {
+            Some(Due::Date(date)) => (Some(Due::date_at(*date)), true),
+            Some(Due::Time(time)) => (Some(*time), false),
+            None => (None, false),
+        }
+    }
+
+    pub fn date_at(date: NaiveDate) -> DateTime<Utc> {
+        DateTime::from_utc(date.and_hms(0, 0, 0), Utc)
+    }
+
+    pub fn from_columns(due_at: Option<DateTime<Utc>>, all_day: bool) -> Option<Due> {
+        match due_at {
+            Some(due_at) if all_day => Some(Due::Date(due_at.naive_utc().date())),
+            Some(due_at) => Some(Due::Time(due_at)),
+            None => None,
+        }
+    }
+}
+
+// Timed todos and all-day todos are matched against separate ranges, since an all-day date only
+// means something within the caller's time zone. Both ranges are half-open, and a missing start
+// is unbounded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DueRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: DateTime<Utc>,
+}
+
+impl DueRange {
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.start.map_or(true, |start| time >= start) && time < self.end
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListUpdate {
+    pub name: Option<String>,
+}
+
+impl ListUpdate {
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns = Vec::new();
+
+        if self.name.is_some() {
+            columns.push("name");
+        }
+
+        columns
+    }
+}
+
+// A todo in a batch insert. Subtasks may name a parent earlier in the same batch by its ID.
+#[derive(Debug, Clone)]
+pub struct NewTodo {
+    pub id: Option<Uuid>,
+    pub description: String,
+    pub due: Option<Due>,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TodoUpdate {
+    pub description: Option<String>,
+    pub done: Option<bool>,
+    pub due: Option<Option<Due>>,
+    pub parent_id: Option<Option<Uuid>>,
+}
+
+impl TodoUpdate {
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns = Vec::new();
+
+        if self.description.is_some() {
+            columns.push("description");
+        }
+
+        if self.done.is_some() {
+            columns.push("done");
+        }
+
+        if self.due.is_some() {
+            columns.push("due_at");
+            columns.push("due_all_day");
+        }
+
+        if self.parent_id.is_some() {
+            columns.push("parent_id");
+        }
+
+        columns
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOrder {
+    Name,
+    CreatedAt,
+}
+
+impl ListOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListOrder::Name => "name",
+            ListOrder::CreatedAt => "created_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DueWindow {
+    Overdue,
+    Today,
+    ThisWeek,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TodoOrder {
+    Id,
+    Description,
+    Position,
+}
+
+impl TodoOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TodoOrder::Id => "id",
+            TodoOrder::Description => "description",
+            TodoOrder::Position => "position",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Model {
+    pool: Arc<PgPool>,
+}
+
+impl Model {
+    pub fn new(pool: Arc<PgPool>) -> Model {
+        Model { pool }
+    }
+
+    async fn notify(&self, kind: ChangeKind, list_id: &Uuid, todo_id: Option<&Uuid>) {
+        let notification = Notification {
+            kind,
+            list_id: *list_id,
+            todo_id: todo_id.copied(),
+        };
+
+        let result = sqlx::query(NOTIFY)
+            .bind(NOTIFICATION_CHANNEL)
+            .bind(notification.to_payload())
+            .execute(self.pool.as_ref())
+            .await;
+
+        if let Err(err) = result {
+            log::error!("{:?}", err);
+        }
+    }
+
+    // Tagging only touches the todo when a link was actually added or removed.
+    async fn notify_tagged(&self, row: Option<TodoRow>) -> Option<Todo> {
+        let todo = row.map(create_todo_from_row)?;
+
+        self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+            .await;
+
+        Some(todo)
+    }
+
+    // Only returns on a connection or database error, and resets the retry delay once listening.
+    async fn relay_notifications(
+        &self,
+        events: &broadcast::Sender<Event>,
+        delay: &mut Duration,
+    ) -> Result<()> {
+        let mut listener = PgListener::from_pool(self.pool.as_ref()).await?;
+        listener.listen(NOTIFICATION_CHANNEL).await?;
+        *delay = LISTEN_RETRY_DELAY;
+
+        loop {
+            let notification = listener.recv().await?;
+
+            let notification = match Notification::from_payload(notification.payload()) {
+                Ok(notification) => notification,
+                Err(err) => {
+                    log::warn!("{}", err);
+                    continue;
+                }
+            };
+
+            // The row may already be gone again by the time the notification arrives, in which
+            // case the matching removal notification follows.
+            let event = match self.load_event(notification).await {
+                Ok(event) => event,
+                Err(err) => match err.downcast_ref::<ModelError>() {
+                    Some(ModelError::NotFound(_)) => continue,
+                    _ => return Err(err),
+                },
+            };
+
+            let _ = events.send(event);
+        }
+    }
+
+    async fn load_event(&self, notification: Notification) -> Result<Event> {
+        let Notification {
+            kind,
+            list_id,
+            todo_id,
+        } = notification;
+
+        let event = match (kind, todo_id) {
+            (ChangeKind::Removed, None) => Event::List {
+                kind,
+                list_id,
+                list: None,
+            },
+            (ChangeKind::Removed, Some(todo_id)) => Event::Todo {
+                kind,
+                list_id,
+                todo_id,
+                todo: None,
+            },
+            (_, None) => Event::List {
+                kind,
+                list_id,
+                list: Some(self.get_list(&list_id).await?),
+            },
+            (_, Some(todo_id)) => Event::Todo {
+                kind,
+                list_id,
+                todo_id,
+                todo: Some(self.get_todo(&todo_id).await?),
+            },
+        };
+
+        Ok(event)
+    }
+
+    // A conditional write that matched no row either targeted a missing row or a stale version.
+    async fn create_list_write_error(
+        &self,
+        error: SqlxError,
+        id: &Uuid,
+        expected_version: &Option<i64>,
+    ) -> Error {
+        match (&error, expected_version) {
+            (SqlxError::RowNotFound, Some(version)) => match self.get_list(id).await {
+                Ok(_) => return Error::new(ModelError::PreconditionFailed(*id, *version)),
+                Err(err) => return err,
+            },
+            _ => return create_not_found_error(error, id),
+        };
+    }
+
+    async fn create_todo_write_error(
+        &self,
+        error: SqlxError,
+        id: &Uuid,
+        expected_version: &Option<i64>,
+    ) -> Error {
+        match (&error, expected_version) {
+            (SqlxError::RowNotFound, Some(version)) => match self.get_todo(id).await {
+                Ok(_) => return Error::new(ModelError::PreconditionFailed(*id, *version)),
+                Err(err) => return err,
+            },
+            _ => return create_not_found_error(error, id),
+        };
+    }
+}
+
+#[async_trait]
+impl ChecklistStore for Model {
+    async fn create_list(
+        &self,
+        id: &Option<&Uuid>,
+        name: &str,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<TodoList> {
+        let mut tx = self.pool.begin().await?;
+        let list = create_list(&mut tx, id, name, claim).await?;
+        tx.commit().await?;
+
+        self.notify(ChangeKind::Created, &list.id, None).await;
+
+        Ok(list)
+    }
+
+    async fn get_list(&self, id: &Uuid) -> Result<TodoList> {
+        let result = sqlx::query_as::<_, ListRow>(SELECT_LIST)
+            .bind(id)
+            .fetch_one(self.pool.as_ref())
+            .await;
+
+        let row = match result {
+            Err(err) => return Err(create_not_found_error(err, id)),
+            Ok(row) => row,
+        };
+
+        let list = create_list_from_row(row);
+
+        Ok(list)
+    }
+
+    async fn list_lists(
+        &self,
+        page_size: i64,
+        page_token: &Option<&str>,
+        name_prefix: &Option<&str>,
+        name_contains: &Option<&str>,
+        order: ListOrder,
+    ) -> Result<Page<TodoList>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let query = match order {
+            ListOrder::Name => SELECT_LISTS_BY_NAME,
+            ListOrder::CreatedAt => SELECT_LISTS_BY_CREATED_AT,
+        };
+
+        let (cursor_key, cursor_id) = match cursor {
+            Some(Cursor { key, id }) => (Some(key), Some(id)),
+            None => (None, None),
+        };
+
+        let mut rows =
+            sqlx::query_as::<_, (Uuid, String, i64, DateTime<Utc>, DateTime<Utc>, String)>(query)
+                .bind(name_prefix.map(escape_like))
+                .bind(name_contains.map(escape_like))
+                .bind(cursor_key)
+                .bind(cursor_id)
+                .bind(page_size + 1)
+                .fetch_all(self.pool.as_ref())
+                .await?;
+
+        let next_cursor = if rows.len() as i64 > page_size {
+            rows.truncate(page_size as usize);
+            rows.last().map(|row| Cursor {
+                key: row.5.clone(),
+                id: row.0,
+            })
+        } else {
+            None
+        };
+
+        let lists = rows
+            .into_iter()
+            .map(|(id, name, version, created_at, updated_at, _)| {
+                create_list_from_row((id, name, version, created_at, updated_at))
+            })
+            .collect();
+
+        let next_page_token =
+            next_cursor.map(|cursor| pagination::encode_page_token(order.as_str(), &cursor));
+
+        Ok(Page {
+            items: lists,
+            next_page_token,
+        })
+    }
+
+    async fn update_list(
+        &self,
+        id: &Uuid,
+        update: &ListUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<TodoList> {
+        let columns = update.columns();
+
+        if columns.is_empty() {
+            let list = self.get_list(id).await?;
+            check_version(id, list.version, expected_version)?;
+            return Ok(list);
+        }
+
+        let statement = format!(
+            "{} RETURNING id, name, version, created_at, updated_at;",
+            create_update_statement("lists", &columns, "$", NOW, expected_version.is_some())
+        );
+
+        let mut query = sqlx::query_as::<_, ListRow>(&statement).bind(id);
+
+        if let Some(name) = &update.name {
+            query = query.bind(name);
+        }
+
+        if let Some(version) = *expected_version {
+            query = query.bind(version);
+        }
+
+        let result = query.fetch_one(self.pool.as_ref()).await;
+
+        let row = match result {
+            Err(err) => {
+                return Err(self
+                    .create_list_write_error(err, id, expected_version)
+                    .await)
+            }
+            Ok(row) => row,
+        };
+
+        let list = create_list_from_row(row);
+        self.notify(ChangeKind::Updated, &list.id, None).await;
+
+        Ok(list)
+    }
+
+    async fn destroy_list(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()> {
+        let result = sqlx::query_as::<_, (Uuid,)>(DELETE_LIST)
+            .bind(id)
+            .bind(*expected_version)
+            .fetch_one(self.pool.as_ref())
+            .await;
+
+        match result {
+            Ok(_) => {
+                self.notify(ChangeKind::Removed, id, None).await;
+                return Ok(());
+            }
+            Err(err) => {
+                return Err(self
+                    .create_list_write_error(err, id, expected_version)
+                    .await)
+            }
+        };
+    }
+
+    async fn create_todo(
+        &self,
+        id: &Option<&Uuid>,
+        list_id: &Uuid,
+        description: &str,
+        due: &Option<Due>,
+        parent_id: &Option<&Uuid>,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<Todo> {
+        let mut tx = self.pool.begin().await?;
+        let todo = create_todo(&mut tx, id, list_id, description, due, parent_id, claim).await?;
+        tx.commit().await?;
+
+        self.notify(ChangeKind::Created, &todo.list_id, Some(&todo.id))
+            .await;
+
+        Ok(todo)
+    }
+
+    async fn create_todos(&self, list_id: &Uuid, todos: &[NewTodo]) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let created = create_todos(&mut tx, list_id, todos).await?;
+        tx.commit().await?;
+
+        for todo in &created {
+            self.notify(ChangeKind::Created, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(created)
+    }
+
+    async fn get_todo(&self, id: &Uuid) -> Result<Todo> {
+        let result = sqlx::query_as::<_, TodoRow>(SELECT_TODO)
+            .bind(id)
+            .fetch_one(self.pool.as_ref())
+            .await;
+
+        let row = match result {
+            Err(err) => return Err(create_not_found_error(err, id)),
+            Ok(row) => row,
+        };
+
+        let todo = create_todo_from_row(row);
+
+        Ok(todo)
+    }
+
+    async fn get_todos_for_lists(&self, list_ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_FOR_LISTS)
+            .bind(list_ids.to_vec())
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let todos = rows.into_iter().map(create_todo_from_row).collect();
+
+        Ok(todos)
+    }
+
+    async fn get_todos_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+            .bind(ids.to_vec())
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let todos = rows.into_iter().map(create_todo_from_row).collect();
+
+        Ok(todos)
+    }
+
+    async fn list_todos(
+        &self,
+        list_id: &Uuid,
+        page_size: i64,
+        page_token: &Option<&str>,
+        done: &Option<bool>,
+        tag_ids: &[Uuid],
+        order: TodoOrder,
+    ) -> Result<Page<Todo>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let cursor_id = cursor.as_ref().map(|c| c.id);
+
+        let query = match order {
+            TodoOrder::Id => sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_ID)
+                .bind(list_id)
+                .bind(*done)
+                .bind(cursor_id),
+            TodoOrder::Description => sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_DESCRIPTION)
+                .bind(list_id)
+                .bind(*done)
+                .bind(cursor.map(|c| c.key))
+                .bind(cursor_id),
+            TodoOrder::Position => sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_POSITION)
+                .bind(list_id)
+                .bind(*done)
+                .bind(cursor.map(|c| c.key))
+                .bind(cursor_id),
+        };
+
+        let rows = query
+            .bind(page_size + 1)
+            .bind(tag_ids.to_vec())
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let mut todos = rows
+            .into_iter()
+            .map(create_todo_from_row)
+            .collect::<Vec<Todo>>();
+
+        let next_page_token = if todos.len() as i64 > page_size {
+            todos.truncate(page_size as usize);
+            todos.last().map(|todo| {
+                let key = match order {
+                    TodoOrder::Id => todo.id.to_hyphenated().to_string(),
+                    TodoOrder::Description => todo.description.clone(),
+                    TodoOrder::Position => todo.position.clone(),
+                };
+
+                pagination::encode_page_token(order.as_str(), &Cursor { key, id: todo.id })
+            })
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items: todos,
+            next_page_token,
+        })
+    }
+
+    async fn update_todo(
+        &self,
+        id: &Uuid,
+        update: &TodoUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo> {
+        let columns = update.columns();
+
+        if columns.is_empty() {
+            let todo = self.get_todo(id).await?;
+            check_version(id, todo.version, expected_version)?;
+            return Ok(todo);
+        }
+
+        let statement = format!(
+            "{} RETURNING list_id, id, description, done, version, created_at, updated_at, \
+             completed_at, due_at, due_all_day, parent_id, position;",
+            create_update_statement("todos", &columns, "$", NOW, expected_version.is_some())
+        );
+
+        let mut query = sqlx::query_as::<_, TodoRow>(&statement).bind(id);
+
+        if let Some(description) = &update.description {
+            query = query.bind(description);
+        }
+
+        if let Some(done) = update.done {
+            query = query.bind(done);
+        }
+
+        if let Some(due) = &update.due {
+            let (due_at, due_all_day) = Due::to_columns(due);
+            query = query.bind(due_at).bind(due_all_day);
+        }
+
+        if let Some(parent_id) = update.parent_id {
+            query = query.bind(parent_id);
+        }
+
+        if let Some(version) = *expected_version {
+            query = query.bind(version);
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(Some(parent_id)) = update.parent_id {
+            check_nesting(&mut tx, id, &parent_id).await?;
+        }
+
+        let result = query.fetch_one(&mut tx).await;
+
+        let row = match result {
+            Err(err) => {
+                return Err(self
+                    .create_todo_write_error(err, id, expected_version)
+                    .await)
+            }
+            Ok(row) => row,
+        };
+
+        tx.commit().await?;
+
+        let todo = create_todo_from_row(row);
+        self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+            .await;
+
+        Ok(todo)
+    }
+
+    async fn get_todo_tree(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+            .bind(id)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        if rows.is_empty() {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        let todos = rows.into_iter().map(create_todo_from_row).collect();
+
+        Ok(todos)
+    }
+
+    async fn get_subtasks(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_SUBTASKS)
+            .bind(parent_id)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let todos = rows.into_iter().map(create_todo_from_row).collect();
+
+        Ok(todos)
+    }
+
+    async fn move_todo_to_list(&self, id: &Uuid, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let (source_list_id, todos) = move_todo_to_list(&mut tx, id, list_id).await?;
+        tx.commit().await?;
+
+        if source_list_id != *list_id {
+            for todo in &todos {
+                self.notify(ChangeKind::Removed, &source_list_id, Some(&todo.id))
+                    .await;
+                self.notify(ChangeKind::Created, &todo.list_id, Some(&todo.id))
+                    .await;
+            }
+        }
+
+        Ok(todos)
+    }
+
+    async fn get_adjacent_todo(
+        &self,
+        anchor: &Todo,
+        following: bool,
+        exclude_id: &Uuid,
+    ) -> Result<Option<Todo>> {
+        let query = match following {
+            true => SELECT_NEXT_TODO,
+            false => SELECT_PREVIOUS_TODO,
+        };
+
+        let row = sqlx::query_as::<_, TodoRow>(query)
+            .bind(anchor.list_id)
+            .bind(&anchor.position)
+            .bind(anchor.id)
+            .bind(exclude_id)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
+
+        Ok(row.map(create_todo_from_row))
+    }
+
+    async fn set_position(&self, id: &Uuid, position: &str) -> Result<Todo> {
+        let result = sqlx::query_as::<_, TodoRow>(UPDATE_TODO_POSITION)
+            .bind(id)
+            .bind(position)
+            .fetch_one(self.pool.as_ref())
+            .await;
+
+        let row = match result {
+            Err(err) => return Err(create_not_found_error(err, id)),
+            Ok(row) => row,
+        };
+
+        let todo = create_todo_from_row(row);
+        self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+            .await;
+
+        Ok(todo)
+    }
+
+    async fn rebalance_positions(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let todos = rebalance_positions(&mut tx, list_id).await?;
+        tx.commit().await?;
+
+        for todo in &todos {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(todos)
+    }
+
+    async fn set_done(&self, id: &Uuid, done: bool) -> Result<Todo> {
+        let result = sqlx::query_as::<_, TodoRow>(UPDATE_TODO_DONE)
+            .bind(id)
+            .bind(done)
+            .fetch_one(self.pool.as_ref())
+            .await;
+
+        let row = match result {
+            Err(err) => return Err(create_not_found_error(err, id)),
+            Ok(row) => row,
+        };
+
+        let todo = create_todo_from_row(row);
+        self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+            .await;
+
+        Ok(todo)
+    }
+
+    async fn complete_todo(
+        &self,
+        id: &Uuid,
+        done: bool,
+        cascade: bool,
+        expected_version: &Option<i64>,
+    ) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let changed = complete_todo(&mut tx, id, done, cascade, expected_version).await?;
+        tx.commit().await?;
+
+        for todo in &changed {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(changed)
+    }
+
+    async fn roll_up(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let changed = roll_up(&mut tx, parent_id).await?;
+        tx.commit().await?;
+
+        for todo in &changed {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(changed)
+    }
+
+    async fn get_due_todos(&self, timed: &DueRange, all_day: &DueRange) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_DUE_TODOS)
+            .bind(timed.start)
+            .bind(timed.end)
+            .bind(all_day.start)
+            .bind(all_day.end)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let todos = rows.into_iter().map(create_todo_from_row).collect();
+
+        Ok(todos)
+    }
+
+    async fn complete_todos(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let rows = sqlx::query_as::<_, TodoRow>(COMPLETE_TODOS)
+            .bind(list_id)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let todos = rows
+            .into_iter()
+            .map(create_todo_from_row)
+            .collect::<Vec<Todo>>();
+
+        for todo in &todos {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(todos)
+    }
+
+    async fn destroy_completed_todos(&self, list_id: &Uuid) -> Result<Vec<Uuid>> {
+        let ids = sqlx::query_as::<_, (Uuid,)>(DELETE_COMPLETED_TODOS)
+            .bind(list_id)
+            .fetch_all(self.pool.as_ref())
+            .await?
+            .into_iter()
+            .map(|(id,)| id)
+            .collect::<Vec<Uuid>>();
+
+        for id in &ids {
+            self.notify(ChangeKind::Removed, list_id, Some(id)).await;
+        }
+
+        Ok(ids)
+    }
+
+    async fn destroy_todo(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<Vec<Uuid>> {
+        let rows = sqlx::query_as::<_, (Uuid, Uuid)>(DELETE_TODO)
+            .bind(id)
+            .bind(*expected_version)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        let list_id = match rows.first() {
+            Some((list_id, _)) => *list_id,
+            None => {
+                return Err(self
+                    .create_todo_write_error(SqlxError::RowNotFound, id, expected_version)
+                    .await)
+            }
+        };
+
+        let mut ids = vec![*id];
+        ids.extend(
+            rows.into_iter()
+                .map(|(_, todo_id)| todo_id)
+                .filter(|todo_id| todo_id != id),
+        );
+
+        for todo_id in &ids {
+            self.notify(ChangeKind::Removed, &list_id, Some(todo_id))
+                .await;
+        }
+
+        Ok(ids)
+    }
+
+    async fn create_tag(&self, name: &str) -> Result<Tag> {
+        let tag = Tag {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+        };
+
+        let result = sqlx::query(INSERT_TAG)
+            .bind(tag.id)
+            .bind(&tag.name)
+            .execute(self.pool.as_ref())
+            .await;
+
+        match result {
+            Ok(_) => return Ok(tag),
+            Err(err) => return Err(create_tag_name_error(err, name)),
+        };
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>> {
+        let rows = sqlx::query_as::<_, (Uuid, String)>(SELECT_TAGS)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+
+        Ok(rows.into_iter().map(create_tag_from_row).collect())
+    }
+
+    async fn rename_tag(&self, id: &Uuid, name: &str) -> Result<(Tag, Vec<Todo>)> {
+        let mut tx = self.pool.begin().await?;
+        let (tag, todos) = rename_tag(&mut tx, id, name).await?;
+        tx.commit().await?;
+
+        for todo in &todos {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok((tag, todos))
+    }
+
+    async fn destroy_tag(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let todos = destroy_tag(&mut tx, id).await?;
+        tx.commit().await?;
+
+        for todo in &todos {
+            self.notify(ChangeKind::Updated, &todo.list_id, Some(&todo.id))
+                .await;
+        }
+
+        Ok(todos)
+    }
+
+    async fn attach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let result = sqlx::query_as::<_, TodoRow>(INSERT_TODO_TAG)
+            .bind(todo_id)
+            .bind(tag_id)
+            .fetch_optional(self.pool.as_ref())
+            .await;
+
+        let error = match result {
+            Ok(row) => return Ok(self.notify_tagged(row).await),
+            Err(err) => err,
+        };
+
+        match extract_database_error_code(&error) {
+            Ok(code) if code == DatabaseErrorCode::ForeignKeyViolation => {
+                // Either side may be missing, so look up the todo to report the right one.
+                self.get_todo(todo_id).await?;
+                return Err(Error::new(ModelError::NotFound(*tag_id)));
+            }
+            _ => return Err(Error::new(error)),
+        };
+    }
+
+    async fn detach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let row = sqlx::query_as::<_, TodoRow>(DELETE_TODO_TAG)
+            .bind(todo_id)
+            .bind(tag_id)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
 
-const UPDATE_LIST: &str = "
-  UPDATE lists
-  SET name = $2
-  WHERE id = $1
-  RETURNING id, name;
-";
+        Ok(self.notify_tagged(row).await)
+    }
 
-const DELETE_LIST: &str = "
-  DELETE FROM lists
-  WHERE id = $1
-  RETURNING id;
-";
+    async fn get_tags_for_todo(&self, todo_id: &Uuid) -> Result<Vec<Tag>> {
+        let rows = sqlx::query_as::<_, (Uuid, String)>(SELECT_TAGS_FOR_TODO)
+            .bind(todo_id)
+            .fetch_all(self.pool.as_ref())
+            .await?;
 
-const INSERT_TODO: &str = "
-  INSERT INTO todos (list_id, id, description, done)
-  VALUES ($1, $2, $3, $4);
-";
+        Ok(rows.into_iter().map(create_tag_from_row).collect())
+    }
 
-#[derive(Debug)]
-pub struct TodoList {
-    pub id: Uuid,
-    pub name: String,
-}
+    async fn get_idempotency_record(&self, key: &str) -> Result<Option<IdempotencyRecord>> {
+        let row = sqlx::query_as::<_, (String, String, String)>(SELECT_IDEMPOTENCY_RECORD)
+            .bind(key)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
 
-#[derive(Debug)]
-pub struct Todo {
-    pub list_id: Uuid,
-    pub id: Uuid,
-    pub description: String,
-    pub done: bool,
+        Ok(row.map(create_idempotency_record_from_row))
+    }
+
+    fn emits_events(&self) -> bool {
+        true
+    }
+
+    async fn listen(&self, events: broadcast::Sender<Event>) {
+        let mut delay = LISTEN_RETRY_DELAY;
+
+        loop {
+            if let Err(err) = self.relay_notifications(&events, &mut delay).await {
+                log::warn!("{:?}", err);
+            }
+
+            tokio::time::delay_for(delay).await;
+            delay = cmp::min(delay * 2, MAX_LISTEN_RETRY_DELAY);
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Model {
-    pool: Arc<PgPool>,
+async fn create_list(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Option<&Uuid>,
+    name: &str,
+    claim: &Option<&IdempotencyClaim>,
+) -> Result<TodoList> {
+    let id = match *id {
+        Some(id) => id.to_owned(),
+        None => Uuid::new_v4(),
+    };
+
+    if let Some(claim) = claim {
+        claim_idempotency_key(&mut *tx, claim).await?;
+    }
+
+    let result = sqlx::query_as::<_, (DateTime<Utc>, DateTime<Utc>)>(INSERT_LIST)
+        .bind(id)
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await;
+
+    let (created_at, updated_at) = match result {
+        Ok(timestamps) => timestamps,
+        Err(err) => {
+            let error_code = match extract_database_error_code(&err) {
+                Err(_) => return Err(Error::new(err)),
+                Ok(code) => code,
+            };
+
+            match error_code.as_ref() {
+                DatabaseErrorCode::UniqueViolation => {
+                    return Err(Error::new(ModelError::Conflict(id)))
+                }
+                _ => return Err(Error::new(err)),
+            };
+        }
+    };
+
+    let list = TodoList {
+        id,
+        name: name.to_string(),
+        version: 1,
+        created_at,
+        updated_at,
+    };
+
+    if let Some(claim) = claim {
+        save_idempotency_response(tx, &claim.key, &idempotency::encode_list(&list)).await?;
+    }
+
+    Ok(list)
 }
 
-impl Model {
-    pub fn new(pool: Arc<PgPool>) -> Model {
-        Model { pool }
+async fn create_todo(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Option<&Uuid>,
+    list_id: &Uuid,
+    description: &str,
+    due: &Option<Due>,
+    parent_id: &Option<&Uuid>,
+    claim: &Option<&IdempotencyClaim>,
+) -> Result<Todo> {
+    let id = match *id {
+        Some(id) => id.to_owned(),
+        None => Uuid::new_v4(),
+    };
+
+    if let Some(claim) = claim {
+        claim_idempotency_key(&mut *tx, claim).await?;
     }
 
-    pub async fn create_list(&self, id: &Option<&Uuid>, name: &str) -> Result<TodoList> {
-        let id = match *id {
-            Some(id) => id.to_owned(),
-            None => Uuid::new_v4(),
-        };
+    let (due_at, due_all_day) = Due::to_columns(due);
 
-        let list = TodoList {
-            id,
-            name: name.to_string(),
-        };
+    // Appends to the same list would otherwise read the same last position.
+    sqlx::query(LOCK_LIST)
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await?;
 
-        let result = sqlx::query(INSERT_LIST)
-            .bind(&list.id.to_hyphenated().to_string())
-            .bind(&list.name)
-            .execute(self.pool.as_ref())
-            .await;
+    let (last_position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id)
+        .fetch_one(&mut *tx)
+        .await?;
 
-        let error = match result {
-            Ok(_) => return Ok(list),
-            Err(err) => err,
-        };
+    let position = position::after(last_position.as_deref());
 
-        let error_code = match extract_database_error_code(&error) {
-            Err(_) => return Err(Error::new(error)),
-            Ok(code) => code,
-        };
+    let result = sqlx::query_as::<_, (DateTime<Utc>, DateTime<Utc>)>(INSERT_TODO)
+        .bind(list_id)
+        .bind(id)
+        .bind(description)
+        .bind(false)
+        .bind(due_at)
+        .bind(due_all_day)
+        .bind(parent_id.copied())
+        .bind(&position)
+        .fetch_one(&mut *tx)
+        .await;
 
-        match error_code.as_ref() {
-            DatabaseErrorCode::UniqueViolation => return Err(Error::new(ModelError::Conflict(id))),
-            _ => return Err(Error::new(error)),
-        };
+    let (created_at, updated_at) = match result {
+        Ok(timestamps) => timestamps,
+        Err(err) => {
+            let error_code = match extract_database_error_code(&err) {
+                Err(_) => return Err(Error::new(err)),
+                Ok(code) => code,
+            };
+
+            match error_code.as_ref() {
+                DatabaseErrorCode::ForeignKeyViolation => {
+                    return Err(create_missing_list_error(list_id))
+                }
+                DatabaseErrorCode::UniqueViolation => {
+                    return Err(Error::new(ModelError::Conflict(id)))
+                }
+                _ => return Err(Error::new(err)),
+            };
+        }
+    };
+
+    let todo = Todo {
+        list_id: list_id.to_owned(),
+        id,
+        description: description.to_string(),
+        done: false,
+        version: 1,
+        created_at,
+        updated_at,
+        completed_at: None,
+        due: *due,
+        parent_id: parent_id.copied(),
+        position,
+    };
+
+    if let Some(claim) = claim {
+        save_idempotency_response(tx, &claim.key, &idempotency::encode_todo(&todo)).await?;
     }
 
-    pub async fn get_list(&self, id: &Uuid) -> Result<TodoList> {
-        let result = sqlx::query_as::<_, (String, String)>(SELECT_LIST)
-            .bind(id.to_hyphenated().to_string())
-            .fetch_one(self.pool.as_ref())
+    Ok(todo)
+}
+
+async fn rename_tag(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Uuid,
+    name: &str,
+) -> Result<(Tag, Vec<Todo>)> {
+    let result = sqlx::query_as::<_, (Uuid, String)>(UPDATE_TAG)
+        .bind(id)
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await;
+
+    let tag = match result {
+        Ok(row) => create_tag_from_row(row),
+        Err(SqlxError::RowNotFound) => return Err(Error::new(ModelError::NotFound(*id))),
+        Err(err) => return Err(create_tag_name_error(err, name)),
+    };
+
+    let rows = sqlx::query_as::<_, TodoRow>(UPDATE_TAGGED_TODOS)
+        .bind(id)
+        .fetch_all(tx)
+        .await?;
+
+    Ok((tag, rows.into_iter().map(create_todo_from_row).collect()))
+}
+
+// The tagged todos are touched first, since deleting the tag cascades to their links.
+async fn destroy_tag(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let rows = sqlx::query_as::<_, TodoRow>(UPDATE_TAGGED_TODOS)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let result = sqlx::query_as::<_, (Uuid,)>(DELETE_TAG)
+        .bind(id)
+        .fetch_one(tx)
+        .await;
+
+    match result {
+        Ok(_) => return Ok(rows.into_iter().map(create_todo_from_row).collect()),
+        Err(err) => return Err(create_not_found_error(err, id)),
+    };
+}
+
+// The todo comes first, followed by the subtasks it closed and the ancestors that rolled up.
+async fn complete_todo(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Uuid,
+    done: bool,
+    cascade: bool,
+    expected_version: &Option<i64>,
+) -> Result<Vec<Todo>> {
+    sqlx::query(LOCK_TODO_LIST)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    if expected_version.is_some() {
+        let result = sqlx::query_as::<_, TodoRow>(SELECT_TODO_FOR_UPDATE)
+            .bind(id)
+            .fetch_one(&mut *tx)
             .await;
 
         let row = match result {
@@ -102,108 +1720,442 @@ impl Model {
             Ok(row) => row,
         };
 
-        let list = create_list_from_row(row)?;
+        check_version(id, row.version, expected_version)?;
+    }
+
+    let result = sqlx::query_as::<_, TodoRow>(UPDATE_TODO_DONE)
+        .bind(id)
+        .bind(done)
+        .fetch_one(&mut *tx)
+        .await;
 
-        Ok(list)
+    let todo = match result {
+        Err(err) => return Err(create_not_found_error(err, id)),
+        Ok(row) => create_todo_from_row(row),
+    };
+
+    let parent_id = todo.parent_id;
+    let mut changed = vec![todo];
+
+    if done && cascade {
+        let rows = sqlx::query_as::<_, TodoRow>(COMPLETE_SUBTREE)
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        changed.extend(rows.into_iter().map(create_todo_from_row));
     }
 
-    pub async fn update_list(&self, id: &Uuid, name: &str) -> Result<TodoList> {
-        let result = sqlx::query_as::<_, (String, String)>(UPDATE_LIST)
-            .bind(id.to_hyphenated().to_string())
-            .bind(name)
-            .fetch_one(self.pool.as_ref())
-            .await;
+    if let Some(parent_id) = parent_id {
+        changed.extend(roll_up(tx, &parent_id).await?);
+    }
 
-        let row = match result {
-            Err(err) => return Err(create_not_found_error(err, id)),
-            Ok(row) => row,
+    Ok(changed)
+}
+
+// Finishing the last open subtask completes the parent, and an open subtask reopens it. Either
+// change can carry on up through the ancestors.
+async fn roll_up(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    parent_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    sqlx::query(LOCK_TODO_LIST)
+        .bind(parent_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut changed = Vec::new();
+    let mut next = Some(*parent_id);
+
+    while let Some(id) = next {
+        let row = sqlx::query_as::<_, (bool, Option<Uuid>, i64, i64)>(SELECT_SUBTASK_COUNTS)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let (done, parent_id, subtasks, open) = match row {
+            Some(row) => row,
+            None => break,
         };
 
-        let list = create_list_from_row(row)?;
+        if subtasks == 0 || done == (open == 0) {
+            break;
+        }
 
-        Ok(list)
+        let row = sqlx::query_as::<_, TodoRow>(UPDATE_TODO_DONE)
+            .bind(id)
+            .bind(open == 0)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        changed.push(create_todo_from_row(row));
+        next = parent_id;
     }
 
-    pub async fn destroy_list(&self, id: &Uuid) -> Result<()> {
-        let result = sqlx::query_as::<_, (String,)>(DELETE_LIST)
-            .bind(&id.to_hyphenated().to_string())
-            .fetch_one(self.pool.as_ref())
-            .await;
+    Ok(changed)
+}
 
-        match result {
-            Ok(_) => return Ok(()),
-            Err(err) => return Err(create_not_found_error(err, id)),
-        };
+// Locking the list makes concurrent re-parents take turns, so two moves can't each pass the check
+// and close a loop together.
+async fn check_nesting(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Uuid,
+    parent_id: &Uuid,
+) -> Result<()> {
+    sqlx::query(LOCK_TODO_LIST)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+        .bind(id)
+        .fetch_all(tx)
+        .await?;
+
+    if rows.iter().any(|row| row.id == *parent_id) {
+        return Err(create_nesting_error(id));
     }
 
-    pub async fn create_todo(&self, list_id: &Uuid, description: &str) -> Result<Todo> {
-        let todo = Todo {
-            list_id: list_id.to_owned(),
-            id: Uuid::new_v4(),
-            description: description.to_string(),
-            done: false,
-        };
+    Ok(())
+}
 
-        let result = sqlx::query(INSERT_TODO)
-            .bind(&todo.list_id.to_hyphenated().to_string())
-            .bind(&todo.id.to_hyphenated().to_string())
-            .bind(&todo.description)
-            .bind(&todo.done)
-            .execute(self.pool.as_ref())
-            .await;
+// A concurrent request holding the same key makes this insert wait for it to finish, so the
+// caller only ever sees a committed response.
+async fn claim_idempotency_key(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    claim: &IdempotencyClaim,
+) -> Result<()> {
+    let result = sqlx::query(INSERT_IDEMPOTENCY_CLAIM)
+        .bind(&claim.key)
+        .bind(&claim.request_hash)
+        .execute(tx)
+        .await;
 
-        let error = match result {
-            Ok(_) => return Ok(todo),
-            Err(err) => err,
-        };
+    let error = match result {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+
+    let error_code = match extract_database_error_code(&error) {
+        Err(_) => return Err(Error::new(error)),
+        Ok(code) => code,
+    };
 
-        let error_code = match extract_database_error_code(&error) {
-            Err(_) => return Err(Error::new(error)),
-            Ok(code) => code,
+    match error_code.as_ref() {
+        DatabaseErrorCode::UniqueViolation => {
+            return Err(Error::new(ModelError::KeyConflict(claim.key.clone())))
+        }
+        _ => return Err(Error::new(error)),
+    };
+}
+
+async fn save_idempotency_response(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    key: &str,
+    response: &str,
+) -> Result<()> {
+    sqlx::query(UPDATE_IDEMPOTENCY_RESPONSE)
+        .bind(key)
+        .bind(response)
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+// Foreign keys are checked once each statement finishes, so a subtask may share a chunk with its
+// parent.
+async fn create_todos(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    list_id: &Uuid,
+    todos: &[NewTodo],
+) -> Result<Vec<Todo>> {
+    sqlx::query(LOCK_LIST)
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let (mut position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let mut created = Vec::with_capacity(todos.len());
+
+    for chunk in todos.chunks(MAX_INSERT_ROWS) {
+        let statement = format!(
+            "{} RETURNING id, created_at, updated_at;",
+            create_insert_statement("todos", TODO_INSERT_COLUMNS, chunk.len(), "$")
+        );
+
+        let mut query = sqlx::query_as::<_, (Uuid, DateTime<Utc>, DateTime<Utc>)>(&statement);
+        let mut pending = Vec::with_capacity(chunk.len());
+
+        for new_todo in chunk {
+            let id = match new_todo.id {
+                Some(id) => id,
+                None => Uuid::new_v4(),
+            };
+
+            let next = position::after(position.as_deref());
+            let (due_at, due_all_day) = Due::to_columns(&new_todo.due);
+
+            query = query
+                .bind(list_id)
+                .bind(id)
+                .bind(&new_todo.description)
+                .bind(false)
+                .bind(due_at)
+                .bind(due_all_day)
+                .bind(new_todo.parent_id)
+                .bind(&next);
+
+            pending.push((id, new_todo, next.clone()));
+            position = Some(next);
+        }
+
+        let result = query.fetch_all(&mut *tx).await;
+
+        let timestamps = match result {
+            Ok(rows) => rows
+                .into_iter()
+                .map(|(id, created_at, updated_at)| (id, (created_at, updated_at)))
+                .collect::<HashMap<Uuid, (DateTime<Utc>, DateTime<Utc>)>>(),
+            Err(err) => return Err(create_batch_write_error(err, list_id)),
         };
 
-        match error_code.as_ref() {
-            DatabaseErrorCode::ForeignKeyViolation => {
-                return Err(Error::new(ModelError::Validation(format!(
-                    "list ID '{}' not in collection",
-                    list_id
-                ))))
+        for (id, new_todo, position) in pending {
+            let (created_at, updated_at) = timestamps[&id];
+
+            created.push(Todo {
+                list_id: *list_id,
+                id,
+                description: new_todo.description.clone(),
+                done: false,
+                version: 1,
+                created_at,
+                updated_at,
+                completed_at: None,
+                due: new_todo.due,
+                parent_id: new_todo.parent_id,
+                position,
+            });
+        }
+    }
+
+    Ok(created)
+}
+
+// Subtasks follow through the cascading (list_id, parent_id) key, while the todo itself leaves its
+// parent behind. The moved todos are appended to the target list with the todo first.
+async fn move_todo_to_list(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    id: &Uuid,
+    list_id: &Uuid,
+) -> Result<(Uuid, Vec<Todo>)> {
+    let result = sqlx::query_as::<_, TodoRow>(SELECT_TODO_FOR_UPDATE)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await;
+
+    let todo = match result {
+        Err(err) => return Err(create_not_found_error(err, id)),
+        Ok(row) => create_todo_from_row(row),
+    };
+
+    if todo.list_id == *list_id {
+        return Ok((todo.list_id, vec![todo]));
+    }
+
+    sqlx::query(LOCK_LIST)
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let (last_position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let result = sqlx::query(UPDATE_TODO_LIST)
+        .bind(id)
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await;
+
+    if let Err(err) = result {
+        match extract_database_error_code(&err) {
+            Ok(code) if code == DatabaseErrorCode::ForeignKeyViolation => {
+                return Err(create_missing_list_error(list_id))
             }
-            _ => return Err(Error::new(error)),
+            _ => return Err(Error::new(err)),
         };
     }
+
+    let mut ids = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .filter(|todo_id| todo_id != id)
+        .collect::<Vec<Uuid>>();
+
+    ids.insert(0, *id);
+
+    let mut positions = Vec::with_capacity(ids.len());
+    let mut position = last_position;
+
+    for _ in &ids {
+        let next = position::after(position.as_deref());
+        positions.push(next.clone());
+        position = Some(next);
+    }
+
+    let rows = sqlx::query_as::<_, TodoRow>(UPDATE_MOVED_TODOS)
+        .bind(ids)
+        .bind(positions)
+        .fetch_all(tx)
+        .await?;
+
+    let mut todos = rows
+        .into_iter()
+        .map(create_todo_from_row)
+        .collect::<Vec<Todo>>();
+
+    todos.sort_by(|a, b| a.position.cmp(&b.position));
+
+    Ok((todo.list_id, todos))
 }
 
-fn create_list_from_row(row: (String, String)) -> Result<TodoList> {
-    let list = TodoList {
-        id: Uuid::parse_str(row.0.as_ref())?,
+// Locking the rows keeps a concurrent move from landing between the read and the renumbering.
+async fn rebalance_positions(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    list_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let ids = sqlx::query_as::<_, (Uuid,)>(SELECT_TODO_IDS_BY_POSITION)
+        .bind(list_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<Uuid>>();
+
+    let positions = position::spread(ids.len());
+
+    let rows = sqlx::query_as::<_, TodoRow>(UPDATE_MOVED_TODOS)
+        .bind(ids)
+        .bind(positions)
+        .fetch_all(tx)
+        .await?;
+
+    Ok(rows.into_iter().map(create_todo_from_row).collect())
+}
+
+pub fn create_list_from_row(row: ListRow) -> TodoList {
+    TodoList {
+        id: row.0,
         name: row.1,
-    };
+        version: row.2,
+        created_at: row.3,
+        updated_at: row.4,
+    }
+}
 
-    Ok(list)
+pub fn create_todo_from_row(row: TodoRow) -> Todo {
+    Todo {
+        list_id: row.list_id,
+        id: row.id,
+        description: row.description,
+        done: row.done,
+        version: row.version,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+        completed_at: row.completed_at,
+        due: Due::from_columns(row.due_at, row.due_all_day),
+        parent_id: row.parent_id,
+        position: row.position,
+    }
 }
 
-fn create_not_found_error(error: SqlxError, id: &Uuid) -> Error {
-    match error {
-        SqlxError::RowNotFound => return Error::new(ModelError::NotFound(id.to_owned())),
-        _ => return Error::new(error),
+// A batch reports a failed insert as a whole, since the database doesn't say which row failed.
+pub fn create_batch_write_error(error: SqlxError, list_id: &Uuid) -> Error {
+    if database::is_foreign_key_violation(&error) {
+        return create_missing_list_error(list_id);
+    }
+
+    if database::is_unique_violation(&error) {
+        return Error::new(ModelError::Validation(
+            "a todo ID in the batch is already in collection".to_string(),
+        ));
     }
+
+    Error::new(error)
 }
 
-fn extract_database_error_code(error: &SqlxError) -> std::result::Result<String, &SqlxError> {
-    let database_error = match error {
-        SqlxError::Database(err) => err,
-        _ => return Err(error),
-    };
+pub fn create_nesting_error(id: &Uuid) -> Error {
+    Error::new(ModelError::Validation(format!(
+        "todo '{}' cannot be nested beneath itself",
+        id
+    )))
+}
+
+pub fn create_missing_list_error(list_id: &Uuid) -> Error {
+    Error::new(ModelError::Validation(format!(
+        "list ID '{}' not in collection",
+        list_id
+    )))
+}
+
+pub fn create_missing_parent_error(parent_id: &Uuid) -> Error {
+    Error::new(ModelError::Validation(format!(
+        "parent ID '{}' not in collection",
+        parent_id
+    )))
+}
+
+pub fn create_tag_from_row(row: (Uuid, String)) -> Tag {
+    Tag {
+        id: row.0,
+        name: row.1,
+    }
+}
+
+pub fn create_tag_name_error(error: SqlxError, name: &str) -> Error {
+    if database::is_unique_violation(&error) {
+        return Error::new(ModelError::NameConflict(name.to_string()));
+    }
+
+    Error::new(error)
+}
 
-    match database_error.code() {
-        None => return Err(error),
-        Some(code) => return Ok(code.to_string()),
+pub fn check_version(id: &Uuid, version: i64, expected_version: &Option<i64>) -> Result<()> {
+    match *expected_version {
+        Some(expected) if expected != version => {
+            return Err(Error::new(ModelError::PreconditionFailed(*id, expected)))
+        }
+        _ => return Ok(()),
     };
 }
 
+pub fn create_idempotency_record_from_row(row: (String, String, String)) -> IdempotencyRecord {
+    IdempotencyRecord {
+        key: row.0,
+        request_hash: row.1,
+        response: row.2,
+    }
+}
+
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 #[cfg(test)]
 mod tests {
+    use super::super::super::migration;
     use super::*;
     use dotenv::dotenv;
     use pretty_assertions::assert_eq;
@@ -213,7 +2165,7 @@ mod tests {
     async fn setup() -> Result<Arc<PgPool>> {
         dotenv().ok();
         let pool = database::create_pool(&env::var("DATABASE_URL")?).await?;
-        database::create_schema(&pool).await?;
+        migration::up(&pool).await?;
         Ok(pool)
     }
 
@@ -228,7 +2180,9 @@ mod tests {
 
         let list_id = Uuid::new_v4();
         let list_name = "new_list";
-        let list = model.create_list(&Some(&list_id), &list_name).await?;
+        let list = model
+            .create_list(&Some(&list_id), &list_name, &None)
+            .await?;
 
         assert_eq!(list.id, list_id);
         assert_eq!(list.name, list_name);
@@ -236,13 +2190,51 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn list_lists() -> Result<()> {
+        let model = create_model().await?;
+
+        let prefix = Uuid::new_v4().to_hyphenated().to_string();
+
+        for suffix in &["b", "a", "c"] {
+            let name = format!("{}_{}", prefix, suffix);
+            model.create_list(&None, &name, &None).await?;
+        }
+
+        let first = model
+            .list_lists(2, &None, &Some(&prefix), &None, ListOrder::Name)
+            .await?;
+
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[0].name, format!("{}_a", prefix));
+
+        let token = first.next_page_token.expect("missing next page token");
+        let second = model
+            .list_lists(2, &Some(&token), &Some(&prefix), &None, ListOrder::Name)
+            .await?;
+
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].name, format!("{}_c", prefix));
+
+        let created = model
+            .list_lists(10, &None, &None, &Some(&prefix), ListOrder::CreatedAt)
+            .await?;
+
+        assert_eq!(created.items.len(), 3);
+        assert_eq!(created.items[0].name, format!("{}_b", prefix));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn create_todo() -> Result<()> {
         let model = create_model().await?;
 
         let list_id = Uuid::new_v4();
         let todo_description = "new_todo";
-        let todo = model.create_todo(&list_id, &todo_description).await?;
+        let todo = model
+            .create_todo(&None, &list_id, &todo_description, &None, &None, &None)
+            .await?;
 
         assert_eq!(todo.list_id, list_id);
         assert_eq!(todo.description, todo_description);
@@ -250,4 +2242,143 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn list_todos() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "list_todos", &None).await?;
+
+        for description in &["c", "a", "b"] {
+            model
+                .create_todo(&None, &list.id, description, &None, &None, &None)
+                .await?;
+        }
+
+        let first = model
+            .list_todos(&list.id, 2, &None, &None, &[], TodoOrder::Description)
+            .await?;
+
+        let descriptions = first
+            .items
+            .iter()
+            .map(|todo| todo.description.as_ref())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(descriptions, vec!["a", "b"]);
+
+        let token = first.next_page_token.expect("missing next page token");
+        let second = model
+            .list_todos(
+                &list.id,
+                2,
+                &Some(&token),
+                &None,
+                &[],
+                TodoOrder::Description,
+            )
+            .await?;
+
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].description, "c");
+        assert!(second.next_page_token.is_none());
+
+        let done = model
+            .list_todos(&list.id, 10, &None, &Some(true), &[], TodoOrder::Id)
+            .await?;
+
+        assert!(done.items.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_todos_for_lists() -> Result<()> {
+        let model = create_model().await?;
+
+        let first = model.create_list(&None, "first_list", &None).await?;
+        let second = model.create_list(&None, "second_list", &None).await?;
+        let empty = model.create_list(&None, "empty_list", &None).await?;
+
+        model
+            .create_todo(&None, &first.id, "first_todo", &None, &None, &None)
+            .await?;
+        model
+            .create_todo(&None, &second.id, "second_todo", &None, &None, &None)
+            .await?;
+        model
+            .create_todo(&None, &second.id, "third_todo", &None, &None, &None)
+            .await?;
+
+        let todos = model
+            .get_todos_for_lists(&[first.id, second.id, empty.id])
+            .await?;
+
+        assert_eq!(todos.len(), 3);
+        assert_eq!(todos.iter().filter(|t| t.list_id == second.id).count(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn todo_lifecycle() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "todo_lifecycle", &None).await?;
+        let todo = model
+            .create_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+
+        let fetched = model.get_todo(&todo.id).await?;
+        assert_eq!(fetched.list_id, list.id);
+        assert_eq!(fetched.description, "new_todo");
+
+        let update = TodoUpdate {
+            description: Some("updated_todo".to_string()),
+            done: None,
+            due: None,
+            parent_id: None,
+        };
+
+        let updated = model
+            .update_todo(&todo.id, &update, &Some(todo.version))
+            .await?;
+        assert_eq!(updated.id, todo.id);
+        assert_eq!(updated.description, "updated_todo");
+        assert_eq!(updated.done, false);
+        assert_eq!(updated.version, todo.version + 1);
+
+        let error = model
+            .update_todo(&todo.id, &update, &Some(todo.version))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::PreconditionFailed(..))
+        ));
+
+        let unchanged = model
+            .update_todo(&todo.id, &TodoUpdate::default(), &None)
+            .await?;
+        assert_eq!(unchanged.description, "updated_todo");
+
+        assert!(updated.updated_at >= todo.updated_at);
+        assert!(updated.completed_at.is_none());
+
+        let completed = model.set_done(&todo.id, true).await?;
+        assert_eq!(completed.done, true);
+        assert!(completed.completed_at.is_some());
+
+        model
+            .destroy_todo(&todo.id, &Some(completed.version))
+            .await?;
+
+        let error = model.get_todo(&todo.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::NotFound(_))
+        ));
+
+        Ok(())
+    }
 }
@@ -1,32 +1,81 @@
 use super::super::common;
-use super::model::{Model, Todo, TodoList};
+use super::event::{ChangeKind, Event, EVENT_CHANNEL_CAPACITY};
+use super::idempotency::{self, IdempotencyClaim};
+use super::model::{
+    Due, DueRange, DueWindow, ListOrder, ListUpdate, NewTodo, Tag, Todo, TodoList, TodoOrder,
+    TodoTree, TodoUpdate,
+};
+use super::store::ChecklistStore;
 use anyhow::{Error, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use common::model::Error as ModelError;
-use common::service::Error as ServiceError;
+use common::pagination::{self, Page};
+use common::position::{self, REBALANCE_LENGTH};
+use common::service::{Error as ServiceError, ItemViolation};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+pub const MAX_BATCH_SIZE: usize = 1000;
+
 #[derive(Debug)]
 pub struct Service {
-    model: Model,
+    store: Box<dyn ChecklistStore>,
+    events: broadcast::Sender<Event>,
 }
 
 impl Service {
-    pub fn new(model: Model) -> Arc<Service> {
-        Arc::new(Service { model })
+    pub fn new(store: Box<dyn ChecklistStore>) -> Arc<Service> {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Arc::new(Service { store, events })
     }
 
-    pub async fn add_list(&self, id: &Option<&Uuid>, name: &str) -> Result<TodoList> {
-        let result = self.model.create_list(id, name).await;
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
 
-        match result {
-            Ok(list) => return Ok(list),
-            Err(err) => return Err(create_conflict_error(err)),
+    pub async fn listen(&self) {
+        self.store.listen(self.events.clone()).await
+    }
+
+    pub async fn add_list(
+        &self,
+        id: &Option<&Uuid>,
+        name: &str,
+        idempotency_key: &Option<&str>,
+    ) -> Result<TodoList> {
+        let request_hash = idempotency::hash_request(
+            "add_list",
+            &[id.map(Uuid::to_string), Some(name.to_string())],
+        );
+
+        if let Some(response) = self.replay(idempotency_key, &request_hash).await? {
+            return idempotency::decode_list(&response);
+        }
+
+        let claim = create_claim(idempotency_key, &request_hash);
+        let result = self.store.create_list(id, name, &claim.as_ref()).await;
+
+        let list = match result {
+            Ok(list) => list,
+            Err(err) => match self
+                .replay_claimed(err, idempotency_key, &request_hash)
+                .await
+            {
+                Ok(response) => return idempotency::decode_list(&response),
+                Err(err) => return Err(create_conflict_error(err)),
+            },
         };
+
+        self.publish_list(ChangeKind::Created, &list);
+
+        Ok(list)
     }
 
     pub async fn get_list(&self, id: &Uuid) -> Result<TodoList> {
-        let result = self.model.get_list(id).await;
+        let result = self.store.get_list(id).await;
 
         match result {
             Ok(list) => return Ok(list),
@@ -34,107 +83,2013 @@ impl Service {
         };
     }
 
-    pub async fn update_list(&self, id: &Uuid, name: &str) -> Result<TodoList> {
-        let result = self.model.update_list(id, name).await;
+    pub async fn list_lists(
+        &self,
+        page_size: i32,
+        page_token: &Option<&str>,
+        name_prefix: &Option<&str>,
+        name_contains: &Option<&str>,
+        order: ListOrder,
+    ) -> Result<Page<TodoList>> {
+        if page_size < 0 {
+            return Err(Error::new(ServiceError::Validation(
+                "page_size",
+                "page size must not be negative".to_string(),
+            )));
+        }
+
+        let page_size = pagination::clamp_page_size(i64::from(page_size));
+        let result = self
+            .store
+            .list_lists(page_size, page_token, name_prefix, name_contains, order)
+            .await;
 
         match result {
-            Ok(list) => return Ok(list),
+            Ok(page) => return Ok(page),
+            Err(err) => return Err(create_validation_error(err, "page_token")),
+        };
+    }
+
+    pub async fn update_list(
+        &self,
+        id: &Uuid,
+        update: &ListUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<TodoList> {
+        let result = self.store.update_list(id, update, expected_version).await;
+
+        match result {
+            Ok(list) => {
+                self.publish_list(ChangeKind::Updated, &list);
+                return Ok(list);
+            }
+            Err(err) => return Err(create_precondition_error(create_not_found_error(err))),
+        };
+    }
+
+    pub async fn remove_list(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()> {
+        let result = self.store.destroy_list(id, expected_version).await;
+
+        match result {
+            Ok(_) => {
+                self.publish(Event::List {
+                    kind: ChangeKind::Removed,
+                    list_id: *id,
+                    list: None,
+                });
+                return Ok(());
+            }
+            Err(err) => return Err(create_precondition_error(create_not_found_error(err))),
+        };
+    }
+
+    pub async fn add_todo(
+        &self,
+        id: &Option<&Uuid>,
+        list_id: &Uuid,
+        description: &str,
+        due: &Option<Due>,
+        parent_id: &Option<&Uuid>,
+        idempotency_key: &Option<&str>,
+    ) -> Result<Todo> {
+        validate_due(due)?;
+
+        let request_hash = idempotency::hash_request(
+            "add_todo",
+            &[
+                id.map(Uuid::to_string),
+                Some(list_id.to_string()),
+                Some(description.to_string()),
+                idempotency::encode_due(due),
+                parent_id.map(Uuid::to_string),
+            ],
+        );
+
+        if let Some(response) = self.replay(idempotency_key, &request_hash).await? {
+            return idempotency::decode_todo(&response);
+        }
+
+        if let Some(parent_id) = *parent_id {
+            self.check_parent(list_id, parent_id, &None).await?;
+        }
+
+        let claim = create_claim(idempotency_key, &request_hash);
+        let result = self
+            .store
+            .create_todo(id, list_id, description, due, parent_id, &claim.as_ref())
+            .await;
+
+        let mut todo = match result {
+            Ok(todo) => todo,
+            Err(err) => match self
+                .replay_claimed(err, idempotency_key, &request_hash)
+                .await
+            {
+                Ok(response) => return idempotency::decode_todo(&response),
+                Err(err) => {
+                    return Err(create_conflict_error(create_validation_error(
+                        err, "list_id",
+                    )))
+                }
+            },
+        };
+
+        if todo.position.len() > REBALANCE_LENGTH {
+            self.rebalance_positions(list_id, &Some(&todo.id)).await?;
+            todo = self.store.get_todo(&todo.id).await?;
+        }
+
+        self.publish_todo(ChangeKind::Created, &todo);
+        self.roll_up(&todo).await?;
+
+        Ok(todo)
+    }
+
+    // Every item is checked before anything is written, and the batch is inserted all or nothing. A
+    // subtask may name a parent that comes earlier in the batch.
+    pub async fn add_todos(&self, list_id: &Uuid, todos: &[NewTodo]) -> Result<Vec<Todo>> {
+        if todos.is_empty() || todos.len() > MAX_BATCH_SIZE {
+            return Err(Error::new(ServiceError::Validation(
+                "todos",
+                format!("a batch must hold between 1 and {} todos", MAX_BATCH_SIZE),
+            )));
+        }
+
+        if let Err(err) = self.store.get_list(list_id).await {
+            return match err.downcast_ref::<ModelError>() {
+                Some(ModelError::NotFound(_)) => Err(Error::new(ServiceError::Validation(
+                    "list_id",
+                    format!("list ID '{}' not in collection", list_id),
+                ))),
+                _ => Err(err),
+            };
+        }
+
+        let batch_ids = todos
+            .iter()
+            .filter_map(|todo| todo.id)
+            .collect::<HashSet<Uuid>>();
+
+        // Client-supplied ids and the parents they name are all looked up in one round trip.
+        let lookup_ids = todos
+            .iter()
+            .flat_map(|todo| todo.id.into_iter().chain(todo.parent_id))
+            .collect::<HashSet<Uuid>>()
+            .into_iter()
+            .collect::<Vec<Uuid>>();
+
+        let existing = self
+            .store
+            .get_todos_by_ids(&lookup_ids)
+            .await?
+            .into_iter()
+            .map(|todo| (todo.id, todo))
+            .collect::<HashMap<Uuid, Todo>>();
+
+        let mut violations = Vec::new();
+        let mut earlier_ids = HashSet::new();
+
+        for (index, todo) in todos.iter().enumerate() {
+            if let Err(err) = validate_due(&todo.due) {
+                violations.push(create_item_violation(index, list_id, err)?);
+            }
+
+            if let Some(parent_id) = &todo.parent_id {
+                let result =
+                    check_batch_parent(list_id, parent_id, &earlier_ids, &batch_ids, &existing);
+
+                if let Some(description) = result {
+                    violations.push(ItemViolation {
+                        index,
+                        field: "parent_id",
+                        description,
+                    });
+                }
+            }
+
+            if let Some(id) = todo.id {
+                if !earlier_ids.insert(id) {
+                    violations.push(ItemViolation {
+                        index,
+                        field: "id",
+                        description: format!("'{}' appears more than once in the batch", id),
+                    });
+                } else if existing.contains_key(&id) {
+                    violations.push(ItemViolation {
+                        index,
+                        field: "id",
+                        description: format!("'{}' already in collection", id),
+                    });
+                }
+            }
+        }
+
+        if !violations.is_empty() {
+            return Err(Error::new(ServiceError::InvalidItems(violations)));
+        }
+
+        let result = self.store.create_todos(list_id, todos).await;
+
+        let created = match result {
+            Ok(created) => created,
+            Err(err) => return Err(create_validation_error(err, "todos")),
+        };
+
+        for todo in &created {
+            self.publish_todo(ChangeKind::Created, todo);
+        }
+
+        // New subtasks reopen a finished parent outside the batch.
+        let mut rolled_up = HashSet::new();
+
+        for todo in &created {
+            if let Some(parent_id) = todo.parent_id {
+                if !batch_ids.contains(&parent_id) && rolled_up.insert(parent_id) {
+                    self.roll_up(todo).await?;
+                }
+            }
+        }
+
+        Ok(created)
+    }
+
+    // Unlike add_todos, ingestion inserts the valid items and reports the rest.
+    pub async fn ingest_todos(
+        &self,
+        list_id: &Uuid,
+        todos: &[NewTodo],
+    ) -> Result<Vec<ItemViolation>> {
+        let mut indexes = (0..todos.len()).collect::<Vec<usize>>();
+        let mut rejected = Vec::new();
+
+        while !indexes.is_empty() {
+            let batch = indexes
+                .iter()
+                .map(|index| todos[*index].clone())
+                .collect::<Vec<NewTodo>>();
+
+            let result = self.add_todos(list_id, &batch).await;
+
+            let violations = match result {
+                Ok(_) => break,
+                Err(err) => match err.downcast::<ServiceError>() {
+                    Ok(ServiceError::InvalidItems(violations)) => violations,
+                    Ok(ServiceError::Validation(_, _)) => {
+                        rejected.extend(self.ingest_todos_singly(list_id, todos, &indexes).await?);
+                        break;
+                    }
+                    Ok(err) => return Err(Error::new(err)),
+                    Err(err) => return Err(err),
+                },
+            };
+
+            // Dropping an item can orphan its subtasks, so validate what is left again.
+            let mut dropped = HashSet::new();
+
+            for violation in violations {
+                let index = indexes[violation.index];
+
+                if dropped.insert(index) {
+                    rejected.push(ItemViolation { index, ..violation });
+                }
+            }
+
+            indexes.retain(|index| !dropped.contains(index));
+        }
+
+        rejected.sort_by_key(|violation| violation.index);
+
+        Ok(rejected)
+    }
+
+    pub async fn get_todo(&self, id: &Uuid) -> Result<Todo> {
+        let result = self.store.get_todo(id).await;
+
+        match result {
+            Ok(todo) => return Ok(todo),
             Err(err) => return Err(create_not_found_error(err)),
         };
     }
 
-    pub async fn remove_list(&self, id: &Uuid) -> Result<()> {
-        let result = self.model.destroy_list(id).await;
+    pub async fn get_todos_for_lists(&self, list_ids: &[Uuid]) -> Result<Vec<Todo>> {
+        self.store.get_todos_for_lists(list_ids).await
+    }
+
+    pub async fn list_todos(
+        &self,
+        list_id: &Uuid,
+        page_size: i32,
+        page_token: &Option<&str>,
+        done: &Option<bool>,
+        tag_ids: &[Uuid],
+        order: TodoOrder,
+    ) -> Result<Page<Todo>> {
+        if page_size < 0 {
+            return Err(Error::new(ServiceError::Validation(
+                "page_size",
+                "page size must not be negative".to_string(),
+            )));
+        }
+
+        self.get_list(list_id).await?;
+
+        // A todo matches when it carries every requested tag, so repeats add nothing.
+        let mut tag_ids = tag_ids.to_vec();
+        tag_ids.sort();
+        tag_ids.dedup();
+
+        let page_size = pagination::clamp_page_size(i64::from(page_size));
+        let result = self
+            .store
+            .list_todos(list_id, page_size, page_token, done, &tag_ids, order)
+            .await;
 
         match result {
-            Ok(list) => return Ok(list),
+            Ok(page) => return Ok(page),
+            Err(err) => return Err(create_validation_error(err, "page_token")),
+        };
+    }
+
+    pub async fn update_todo(
+        &self,
+        id: &Uuid,
+        update: &TodoUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo> {
+        if let Some(due) = &update.due {
+            validate_due(due)?;
+        }
+
+        let previous = match update.parent_id {
+            Some(_) => Some(self.get_todo(id).await?),
+            None => None,
+        };
+
+        if let (Some(previous), Some(Some(parent_id))) = (&previous, &update.parent_id) {
+            self.check_parent(&previous.list_id, parent_id, &Some(id))
+                .await?;
+        }
+
+        if update.done == Some(true) {
+            let open = self.find_open_subtasks(id).await?;
+
+            if !open.is_empty() {
+                return Err(create_open_subtasks_error(id, open.len(), "done"));
+            }
+        }
+
+        let result = self.store.update_todo(id, update, expected_version).await;
+
+        let todo = match result {
+            Ok(todo) => todo,
+            Err(err) => {
+                return Err(create_precondition_error(create_not_found_error(
+                    create_validation_error(err, "parent_id"),
+                )))
+            }
+        };
+
+        self.publish_todo(ChangeKind::Updated, &todo);
+
+        if update.done.is_some() || update.parent_id.is_some() {
+            self.roll_up(&todo).await?;
+        }
+
+        // The old parent may now have only finished subtasks left.
+        if let Some(previous) = &previous {
+            if previous.parent_id != todo.parent_id {
+                self.roll_up(previous).await?;
+            }
+        }
+
+        Ok(todo)
+    }
+
+    // A todo moves to just after `after_id`, just before `before_id`, or between the two. Only its
+    // own position changes unless the keys around it have run out of room.
+    pub async fn move_todo(
+        &self,
+        id: &Uuid,
+        after_id: &Option<&Uuid>,
+        before_id: &Option<&Uuid>,
+    ) -> Result<Todo> {
+        if after_id.is_none() && before_id.is_none() {
+            return Err(Error::new(ServiceError::Validation(
+                "before_id",
+                "one of before_id or after_id is required".to_string(),
+            )));
+        }
+
+        let todo = self.get_todo(id).await?;
+
+        let position = match self.find_position(&todo, after_id, before_id).await? {
+            Some(position) => position,
+            None => {
+                self.rebalance_positions(&todo.list_id, &None).await?;
+
+                match self.find_position(&todo, after_id, before_id).await? {
+                    Some(position) => position,
+                    None => {
+                        return Err(Error::new(ServiceError::Validation(
+                            "before_id",
+                            format!("no room to move todo '{}'", id),
+                        )))
+                    }
+                }
+            }
+        };
+
+        let result = self.store.set_position(id, &position).await;
+
+        let mut todo = match result {
+            Ok(todo) => todo,
+            Err(err) => return Err(create_not_found_error(err)),
+        };
+
+        if todo.position.len() > REBALANCE_LENGTH {
+            self.rebalance_positions(&todo.list_id, &Some(id)).await?;
+            todo = self.store.get_todo(id).await?;
+        }
+
+        self.publish_todo(ChangeKind::Updated, &todo);
+
+        Ok(todo)
+    }
+
+    // Subtasks move along with the todo, which is detached from its own parent since that stays
+    // behind in the old list.
+    pub async fn move_todo_to_list(&self, id: &Uuid, list_id: &Uuid) -> Result<Todo> {
+        let source = self.get_todo(id).await?;
+        let result = self.store.move_todo_to_list(id, list_id).await;
+
+        let todos = match result {
+            Ok(todos) => todos,
+            Err(err) => {
+                return Err(create_validation_error(
+                    create_not_found_error(err),
+                    "list_id",
+                ))
+            }
+        };
+
+        if source.list_id != *list_id {
+            for todo in &todos {
+                self.publish(Event::Todo {
+                    kind: ChangeKind::Removed,
+                    list_id: source.list_id,
+                    todo_id: todo.id,
+                    todo: None,
+                });
+                self.publish_todo(ChangeKind::Created, todo);
+            }
+        }
+
+        // The old parent may now have only finished subtasks left.
+        self.roll_up(&source).await?;
+
+        match todos.into_iter().next() {
+            Some(todo) => return Ok(todo),
+            None => return Err(Error::new(ServiceError::NotFound(*id))),
+        };
+    }
+
+    pub async fn get_todo_tree(&self, id: &Uuid) -> Result<TodoTree> {
+        let result = self.store.get_todo_tree(id).await;
+
+        let todos = match result {
+            Ok(todos) => todos,
             Err(err) => return Err(create_not_found_error(err)),
         };
+
+        match TodoTree::build(id, todos) {
+            Some(tree) => return Ok(tree),
+            None => return Err(Error::new(ServiceError::NotFound(*id))),
+        };
+    }
+
+    pub async fn complete_all_todos(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        self.get_list(list_id).await?;
+        let todos = self.store.complete_todos(list_id).await?;
+
+        for todo in &todos {
+            self.publish_todo(ChangeKind::Updated, todo);
+        }
+
+        Ok(todos)
+    }
+
+    pub async fn clear_completed_todos(&self, list_id: &Uuid) -> Result<Vec<Uuid>> {
+        self.get_list(list_id).await?;
+        let ids = self.store.destroy_completed_todos(list_id).await?;
+
+        for id in &ids {
+            self.publish(Event::Todo {
+                kind: ChangeKind::Removed,
+                list_id: *list_id,
+                todo_id: *id,
+                todo: None,
+            });
+        }
+
+        Ok(ids)
+    }
+
+    pub async fn list_due_todos(
+        &self,
+        window: DueWindow,
+        time_zone: &Option<&str>,
+    ) -> Result<Vec<Todo>> {
+        let time_zone = parse_time_zone(time_zone)?;
+        let (timed, all_day) = create_due_ranges(window, time_zone, Utc::now());
+
+        self.store.get_due_todos(&timed, &all_day).await
+    }
+
+    pub async fn remove_todo(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()> {
+        let todo = self.get_todo(id).await?;
+        let result = self.store.destroy_todo(id, expected_version).await;
+
+        let ids = match result {
+            Ok(ids) => ids,
+            Err(err) => return Err(create_precondition_error(create_not_found_error(err))),
+        };
+
+        for removed_id in &ids {
+            self.publish(Event::Todo {
+                kind: ChangeKind::Removed,
+                list_id: todo.list_id,
+                todo_id: *removed_id,
+                todo: None,
+            });
+        }
+
+        // The parent may now have only finished subtasks left.
+        self.roll_up(&todo).await
+    }
+
+    // Completing a todo with open subtasks needs `cascade`, which completes the whole subtree.
+    pub async fn complete_todo(
+        &self,
+        id: &Uuid,
+        done: bool,
+        cascade: bool,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo> {
+        if done {
+            let open = self.find_open_subtasks(id).await?;
+
+            if !open.is_empty() && !cascade {
+                return Err(create_open_subtasks_error(id, open.len(), "cascade"));
+            }
+        }
+
+        let result = self
+            .store
+            .complete_todo(id, done, cascade, expected_version)
+            .await;
+
+        let changed = match result {
+            Ok(changed) => changed,
+            Err(err) => return Err(create_precondition_error(create_not_found_error(err))),
+        };
+
+        for todo in &changed {
+            self.publish_todo(ChangeKind::Updated, todo);
+        }
+
+        Ok(changed[0].clone())
     }
 
-    pub async fn add_todo(&self, list_id: &Uuid, description: &str) -> Result<Todo> {
-        let result = self.model.create_todo(list_id, description).await;
+    pub async fn add_tag(&self, name: &str) -> Result<Tag> {
+        let result = self.store.create_tag(name).await;
 
         match result {
-            Ok(todo) => return Ok(todo),
-            Err(err) => return Err(create_validation_error(err)),
+            Ok(tag) => return Ok(tag),
+            Err(err) => return Err(create_conflict_error(err)),
         };
     }
-}
 
-fn create_conflict_error(error: Error) -> Error {
-    match error.downcast_ref::<ModelError>() {
-        Some(ModelError::Conflict(id)) => return Error::new(ServiceError::Conflict(*id)),
-        _ => return error,
+    pub async fn list_tags(&self) -> Result<Vec<Tag>> {
+        self.store.list_tags().await
     }
-}
 
-fn create_not_found_error(error: Error) -> Error {
-    match error.downcast_ref::<ModelError>() {
-        Some(ModelError::NotFound(id)) => return Error::new(ServiceError::NotFound(*id)),
-        _ => return error,
+    // A todo's tags are part of it, so every tagged todo changes along with the tag.
+    pub async fn rename_tag(&self, id: &Uuid, name: &str) -> Result<Tag> {
+        let result = self.store.rename_tag(id, name).await;
+
+        let (tag, todos) = match result {
+            Ok(renamed) => renamed,
+            Err(err) => return Err(create_conflict_error(create_not_found_error(err))),
+        };
+
+        for todo in &todos {
+            self.publish_todo(ChangeKind::Updated, todo);
+        }
+
+        Ok(tag)
     }
-}
 
-fn create_validation_error(error: Error) -> Error {
-    match error.downcast_ref::<ModelError>() {
-        Some(ModelError::Validation(msg)) => {
-            return Error::new(ServiceError::Validation(msg.to_string()))
+    pub async fn remove_tag(&self, id: &Uuid) -> Result<()> {
+        let result = self.store.destroy_tag(id).await;
+
+        let todos = match result {
+            Ok(todos) => todos,
+            Err(err) => return Err(create_not_found_error(err)),
+        };
+
+        for todo in &todos {
+            self.publish_todo(ChangeKind::Updated, todo);
         }
-        _ => return error,
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::super::super::database;
-    use super::*;
-    use dotenv::dotenv;
-    use pretty_assertions::assert_eq;
-    use sqlx::PgPool;
-    use std::env;
+    pub async fn attach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<()> {
+        self.get_todo(todo_id).await?;
+        let result = self.store.attach_tag(todo_id, tag_id).await;
+
+        let todo = match result {
+            Ok(todo) => todo,
+            Err(err) => return Err(create_not_found_error(err)),
+        };
 
-    async fn setup() -> Result<Arc<PgPool>> {
-        dotenv().ok();
-        let pool = database::create_pool(&env::var("DATABASE_URL")?).await?;
-        database::create_schema(&pool).await?;
-        Ok(pool)
+        // Attaching a tag the todo already has changes nothing.
+        if let Some(todo) = todo {
+            self.publish_todo(ChangeKind::Updated, &todo);
+        }
+
+        Ok(())
     }
 
-    async fn create_model() -> Result<Model> {
-        let pool = setup().await?;
-        Ok(Model::new(pool.clone()))
+    pub async fn detach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<()> {
+        self.get_todo(todo_id).await?;
+        if let Some(todo) = self.store.detach_tag(todo_id, tag_id).await? {
+            self.publish_todo(ChangeKind::Updated, &todo);
+        }
+
+        Ok(())
     }
 
-    async fn create_service() -> Result<Arc<Service>> {
-        Ok(Service::new(create_model().await?))
+    pub async fn list_todo_tags(&self, todo_id: &Uuid) -> Result<Vec<Tag>> {
+        self.get_todo(todo_id).await?;
+        self.store.get_tags_for_todo(todo_id).await
     }
 
-    #[tokio::test]
-    async fn add_list() -> Result<()> {
-        let service = create_service().await?;
+    // A subtask shares its parent's list, and a todo can't be nested beneath its own subtree.
+    async fn check_parent(
+        &self,
+        list_id: &Uuid,
+        parent_id: &Uuid,
+        id: &Option<&Uuid>,
+    ) -> Result<()> {
+        let parent = match self.store.get_todo(parent_id).await {
+            Ok(parent) => Some(parent),
+            Err(err) => match err.downcast_ref::<ModelError>() {
+                Some(ModelError::NotFound(_)) => None,
+                _ => return Err(err),
+            },
+        };
 
-        let list_id = Uuid::new_v4();
-        let list_name = "new_list";
-        let list = service.add_list(&Some(&list_id), &list_name).await?;
+        if let Some(description) = check_parent_list(list_id, parent_id, parent.as_ref()) {
+            return Err(Error::new(ServiceError::Validation(
+                "parent_id",
+                description,
+            )));
+        }
 
-        assert_eq!(list.id, list_id);
-        assert_eq!(list.name, list_name);
+        let id = match *id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let subtree = self.store.get_todo_tree(id).await?;
+
+        if subtree.iter().any(|todo| todo.id == *parent_id) {
+            return Err(Error::new(ServiceError::Validation(
+                "parent_id",
+                format!("todo '{}' cannot be nested beneath itself", id),
+            )));
+        }
 
         Ok(())
     }
 
-    #[tokio::test]
-    async fn add_todo() -> Result<()> {
-        let service = create_service().await?;
+    // Naming one neighbor places the todo right beside it, so the other bound is whichever todo
+    // currently sits on that side.
+    async fn find_position(
+        &self,
+        todo: &Todo,
+        after_id: &Option<&Uuid>,
+        before_id: &Option<&Uuid>,
+    ) -> Result<Option<String>> {
+        let after = match *after_id {
+            Some(after_id) => Some(self.find_anchor(todo, after_id, "after_id").await?),
+            None => None,
+        };
 
-        let list_id = Uuid::new_v4();
-        let todo_description = "new_todo";
-        let todo = service.add_todo(&list_id, &todo_description).await?;
+        let before = match *before_id {
+            Some(before_id) => Some(self.find_anchor(todo, before_id, "before_id").await?),
+            None => None,
+        };
 
-        assert_eq!(todo.list_id, list_id);
-        assert_eq!(todo.description, todo_description);
-        assert_eq!(todo.done, false);
+        let (after, before) = match (after, before) {
+            (Some(after), None) => {
+                let before = self.store.get_adjacent_todo(&after, true, &todo.id).await?;
+                (Some(after), before)
+            }
+            (None, Some(before)) => {
+                let after = self
+                    .store
+                    .get_adjacent_todo(&before, false, &todo.id)
+                    .await?;
+                (after, Some(before))
+            }
+            bounds => bounds,
+        };
+
+        if let (Some(after), Some(before)) = (&after, &before) {
+            if (&after.position, after.id) >= (&before.position, before.id) {
+                return Err(Error::new(ServiceError::Validation(
+                    "before_id",
+                    format!(
+                        "todo '{}' does not come after todo '{}'",
+                        before.id, after.id
+                    ),
+                )));
+            }
+        }
+
+        Ok(position::between(
+            after.as_ref().map(|todo| todo.position.as_str()),
+            before.as_ref().map(|todo| todo.position.as_str()),
+        ))
+    }
+
+    async fn find_anchor(
+        &self,
+        todo: &Todo,
+        anchor_id: &Uuid,
+        field: &'static str,
+    ) -> Result<Todo> {
+        if *anchor_id == todo.id {
+            return Err(Error::new(ServiceError::Validation(
+                field,
+                format!("todo '{}' cannot be moved next to itself", anchor_id),
+            )));
+        }
+
+        let anchor = match self.store.get_todo(anchor_id).await {
+            Ok(anchor) => anchor,
+            Err(err) => match err.downcast_ref::<ModelError>() {
+                Some(ModelError::NotFound(_)) => {
+                    return Err(Error::new(ServiceError::Validation(
+                        field,
+                        format!("todo ID '{}' not in collection", anchor_id),
+                    )))
+                }
+                _ => return Err(err),
+            },
+        };
+
+        if anchor.list_id != todo.list_id {
+            return Err(Error::new(ServiceError::Validation(
+                field,
+                format!("todo ID '{}' belongs to a different list", anchor_id),
+            )));
+        }
+
+        Ok(anchor)
+    }
+
+    // A batch the store refuses as a whole is retried one item at a time to find the culprits.
+    async fn ingest_todos_singly(
+        &self,
+        list_id: &Uuid,
+        todos: &[NewTodo],
+        indexes: &[usize],
+    ) -> Result<Vec<ItemViolation>> {
+        let mut rejected = Vec::new();
+
+        for index in indexes {
+            let result = self
+                .add_todos(list_id, std::slice::from_ref(&todos[*index]))
+                .await;
+
+            let err = match result {
+                Ok(_) => continue,
+                Err(err) => err,
+            };
+
+            match err.downcast::<ServiceError>() {
+                Ok(ServiceError::InvalidItems(mut violations)) => rejected.push(ItemViolation {
+                    index: *index,
+                    ..violations.remove(0)
+                }),
+                Ok(err) => rejected.push(create_item_violation(*index, list_id, Error::new(err))?),
+                Err(err) => return Err(err),
+            };
+        }
+
+        Ok(rejected)
+    }
+
+    // Rebalancing moves every todo in the list. The todo being written, if any, is left for the
+    // caller to announce once.
+    async fn rebalance_positions(&self, list_id: &Uuid, except: &Option<&Uuid>) -> Result<()> {
+        for todo in self.store.rebalance_positions(list_id).await? {
+            if Some(&todo.id) != *except {
+                self.publish_todo(ChangeKind::Updated, &todo);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn find_open_subtasks(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let result = self.store.get_todo_tree(id).await;
+
+        let subtree = match result {
+            Ok(subtree) => subtree,
+            Err(err) => return Err(create_not_found_error(err)),
+        };
+
+        Ok(subtree
+            .into_iter()
+            .filter(|todo| todo.id != *id && !todo.done)
+            .collect())
+    }
+
+    async fn roll_up(&self, todo: &Todo) -> Result<()> {
+        let parent_id = match todo.parent_id {
+            Some(parent_id) => parent_id,
+            None => return Ok(()),
+        };
+
+        for parent in self.store.roll_up(&parent_id).await? {
+            self.publish_todo(ChangeKind::Updated, &parent);
+        }
+
+        Ok(())
+    }
+
+    async fn replay(
+        &self,
+        idempotency_key: &Option<&str>,
+        request_hash: &str,
+    ) -> Result<Option<String>> {
+        let key = match *idempotency_key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let record = match self.store.get_idempotency_record(key).await? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        if record.request_hash != request_hash {
+            return Err(Error::new(ServiceError::Validation(
+                "idempotency_key",
+                format!("idempotency key '{}' was used for a different request", key),
+            )));
+        }
+
+        Ok(Some(record.response))
+    }
+
+    // A request that claimed the same key first has committed by the time the store reports the
+    // conflict, so its response is replayed instead.
+    async fn replay_claimed(
+        &self,
+        error: Error,
+        idempotency_key: &Option<&str>,
+        request_hash: &str,
+    ) -> Result<String> {
+        if !matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::KeyConflict(_))
+        ) {
+            return Err(error);
+        }
+
+        match self.replay(idempotency_key, request_hash).await? {
+            Some(response) => return Ok(response),
+            None => return Err(error),
+        };
+    }
+
+    fn publish_list(&self, kind: ChangeKind, list: &TodoList) {
+        self.publish(Event::List {
+            kind,
+            list_id: list.id,
+            list: Some(list.clone()),
+        });
+    }
+
+    fn publish_todo(&self, kind: ChangeKind, todo: &Todo) {
+        self.publish(Event::Todo {
+            kind,
+            list_id: todo.list_id,
+            todo_id: todo.id,
+            todo: Some(todo.clone()),
+        });
+    }
+
+    fn publish(&self, event: Event) {
+        // Stores that emit their own events deliver them through `listen`, including writes made
+        // by other server instances.
+        if self.store.emits_events() {
+            return;
+        }
+
+        // Sending only fails when nobody is subscribed, which is not an error for the writer.
+        let _ = self.events.send(event);
+    }
+}
+
+fn validate_due(due: &Option<Due>) -> Result<()> {
+    let year = match due {
+        Some(Due::Date(date)) => date.year(),
+        Some(Due::Time(time)) => time.year(),
+        None => return Ok(()),
+    };
+
+    if year < 1 || year > 9999 {
+        return Err(Error::new(ServiceError::Validation(
+            "due",
+            "due date must fall between the years 1 and 9999".to_string(),
+        )));
+    }
+
+    Ok(())
+}
+
+fn parse_time_zone(time_zone: &Option<&str>) -> Result<Tz> {
+    let name = match *time_zone {
+        Some(name) => name,
+        None => return Ok(Tz::UTC),
+    };
+
+    match name.parse::<Tz>() {
+        Ok(time_zone) => return Ok(time_zone),
+        Err(_) => {
+            return Err(Error::new(ServiceError::Validation(
+                "time_zone",
+                format!("'{}' is not an IANA time zone", name),
+            )))
+        }
+    };
+}
+
+// Timed todos are due at an instant, so their range follows the caller's local days. All-day
+// todos are due on a calendar date, so their range covers the same dates regardless of zone.
+// "This week" runs from today through Sunday.
+fn create_due_ranges(window: DueWindow, time_zone: Tz, now: DateTime<Utc>) -> (DueRange, DueRange) {
+    let today = now.with_timezone(&time_zone).date().naive_local();
+
+    let (first, last) = match window {
+        DueWindow::Overdue => (None, today),
+        DueWindow::Today => (Some(today), today.succ()),
+        DueWindow::ThisWeek => {
+            let days_left = 7 - i64::from(today.weekday().num_days_from_monday());
+            (Some(today), today + Duration::days(days_left))
+        }
+    };
+
+    let timed = match window {
+        DueWindow::Overdue => DueRange {
+            start: None,
+            end: now,
+        },
+        _ => DueRange {
+            start: first.map(|date| start_of_day(time_zone, date)),
+            end: start_of_day(time_zone, last),
+        },
+    };
+
+    let all_day = DueRange {
+        start: first.map(Due::date_at),
+        end: Due::date_at(last),
+    };
+
+    (timed, all_day)
+}
+
+fn start_of_day(time_zone: Tz, date: NaiveDate) -> DateTime<Utc> {
+    // Midnight can fall in a daylight saving gap, in which case the day starts at the first local
+    // hour that exists.
+    (0..24)
+        .filter_map(|hour| {
+            time_zone
+                .from_local_datetime(&date.and_hms(hour, 0, 0))
+                .earliest()
+        })
+        .map(|start| start.with_timezone(&Utc))
+        .next()
+        .unwrap_or_else(|| Due::date_at(date))
+}
+
+fn check_parent_list(list_id: &Uuid, parent_id: &Uuid, parent: Option<&Todo>) -> Option<String> {
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return Some(format!("parent ID '{}' not in collection", parent_id)),
+    };
+
+    if parent.list_id != *list_id {
+        return Some(format!(
+            "parent ID '{}' belongs to a different list",
+            parent_id
+        ));
+    }
+
+    None
+}
+
+// A subtask may name an earlier item in the batch, or a todo already in the list.
+fn check_batch_parent(
+    list_id: &Uuid,
+    parent_id: &Uuid,
+    earlier_ids: &HashSet<Uuid>,
+    batch_ids: &HashSet<Uuid>,
+    existing: &HashMap<Uuid, Todo>,
+) -> Option<String> {
+    if earlier_ids.contains(parent_id) {
+        return None;
+    }
+
+    if batch_ids.contains(parent_id) {
+        return Some(format!(
+            "parent ID '{}' must come before its subtasks",
+            parent_id
+        ));
+    }
+
+    check_parent_list(list_id, parent_id, existing.get(parent_id))
+}
+
+fn create_open_subtasks_error(id: &Uuid, count: usize, field: &'static str) -> Error {
+    Error::new(ServiceError::Validation(
+        field,
+        format!(
+            "todo '{}' has {} open subtasks; complete it with cascade to close them too",
+            id, count
+        ),
+    ))
+}
+
+fn create_claim(idempotency_key: &Option<&str>, request_hash: &str) -> Option<IdempotencyClaim> {
+    idempotency_key.map(|key| IdempotencyClaim {
+        key: key.to_string(),
+        request_hash: request_hash.to_string(),
+    })
+}
+
+// Errors about a single new todo become a violation of the field that caused them, and anything
+// else fails the whole request.
+pub fn create_item_violation(index: usize, list_id: &Uuid, error: Error) -> Result<ItemViolation> {
+    let field = match error.downcast_ref::<ServiceError>() {
+        Some(ServiceError::Validation(field, _)) => *field,
+        Some(ServiceError::NotFound(id)) if id == list_id => "list_id",
+        Some(ServiceError::NotFound(_)) => "parent_id",
+        Some(ServiceError::Conflict(_)) | Some(ServiceError::PreconditionFailed(_, _)) => "id",
+        _ => return Err(error),
+    };
+
+    let description = match error.downcast::<ServiceError>() {
+        Ok(ServiceError::Validation(_, description)) => description,
+        Ok(err) => err.to_string(),
+        Err(err) => return Err(err),
+    };
+
+    Ok(ItemViolation {
+        index,
+        field,
+        description,
+    })
+}
+
+fn create_precondition_error(error: Error) -> Error {
+    match error.downcast_ref::<ModelError>() {
+        Some(ModelError::PreconditionFailed(id, version)) => {
+            return Error::new(ServiceError::PreconditionFailed(*id, *version))
+        }
+        _ => return error,
+    }
+}
+
+fn create_conflict_error(error: Error) -> Error {
+    match error.downcast_ref::<ModelError>() {
+        Some(ModelError::Conflict(id)) => return Error::new(ServiceError::Conflict(*id)),
+        Some(ModelError::NameConflict(name)) => {
+            return Error::new(ServiceError::NameConflict(name.clone()))
+        }
+        _ => return error,
+    }
+}
+
+fn create_not_found_error(error: Error) -> Error {
+    match error.downcast_ref::<ModelError>() {
+        Some(ModelError::NotFound(id)) => return Error::new(ServiceError::NotFound(*id)),
+        _ => return error,
+    }
+}
+
+fn create_validation_error(error: Error, field: &'static str) -> Error {
+    match error.downcast_ref::<ModelError>() {
+        Some(ModelError::Validation(msg)) => {
+            return Error::new(ServiceError::Validation(field, msg.to_string()))
+        }
+        _ => return error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::MemoryModel;
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    async fn create_service() -> Result<Arc<Service>> {
+        Ok(Service::new(Box::new(MemoryModel::new())))
+    }
+
+    fn new_todo(description: &str, id: Option<Uuid>, parent_id: Option<Uuid>) -> NewTodo {
+        NewTodo {
+            id,
+            description: description.to_string(),
+            due: None,
+            parent_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn add_list() -> Result<()> {
+        let service = create_service().await?;
+
+        let list_id = Uuid::new_v4();
+        let list_name = "new_list";
+        let list = service.add_list(&Some(&list_id), &list_name, &None).await?;
+
+        assert_eq!(list.id, list_id);
+        assert_eq!(list.name, list_name);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_lists_by_created_at() -> Result<()> {
+        let service = create_service().await?;
+
+        let mut expected = Vec::new();
+
+        for name in &["c_list", "a_list", "b_list"] {
+            expected.push(service.add_list(&None, name, &None).await?);
+        }
+
+        expected.sort_by(|a, b| (a.created_at, a.id).cmp(&(b.created_at, b.id)));
+
+        let first = service
+            .list_lists(2, &None, &None, &None, ListOrder::CreatedAt)
+            .await?;
+        let second = service
+            .list_lists(
+                2,
+                &first.next_page_token.as_deref(),
+                &None,
+                &None,
+                ListOrder::CreatedAt,
+            )
+            .await?;
+
+        let ids = first
+            .items
+            .iter()
+            .chain(&second.items)
+            .map(|list| list.id)
+            .collect::<Vec<Uuid>>();
+
+        assert_eq!(
+            ids,
+            expected.iter().map(|list| list.id).collect::<Vec<Uuid>>()
+        );
+        assert_eq!(second.next_page_token, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_todo() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "new_list", &None).await?;
+        let todo_description = "new_todo";
+        let todo = service
+            .add_todo(&None, &list.id, &todo_description, &None, &None, &None)
+            .await?;
+
+        assert_eq!(todo.list_id, list.id);
+        assert_eq!(todo.description, todo_description);
+        assert_eq!(todo.done, false);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_todo_to_missing_list() -> Result<()> {
+        let service = create_service().await?;
+
+        let error = service
+            .add_todo(&None, &Uuid::new_v4(), "new_todo", &None, &None, &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("list_id", _))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_todo_is_idempotent() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "new_list", &None).await?;
+        let key = Some("add_todo_is_idempotent");
+
+        let todo = service
+            .add_todo(&None, &list.id, "new_todo", &None, &None, &key)
+            .await?;
+        let retried = service
+            .add_todo(&None, &list.id, "new_todo", &None, &None, &key)
+            .await?;
+
+        assert_eq!(retried.id, todo.id);
+
+        let page = service
+            .list_todos(&list.id, 0, &None, &None, &[], TodoOrder::Id)
+            .await?;
+
+        assert_eq!(page.items.len(), 1);
+
+        let error = service
+            .add_todo(&None, &list.id, "other_todo", &None, &None, &key)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("idempotency_key", _))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_due_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "list_due_todos", &None).await?;
+        let two_days_ago = Utc::now().date().naive_utc().pred().pred();
+        let due = Some(Due::Date(two_days_ago));
+
+        let overdue = service
+            .add_todo(&None, &list.id, "overdue", &due, &None, &None)
+            .await?;
+        service
+            .add_todo(&None, &list.id, "undated", &None, &None, &None)
+            .await?;
+
+        let todos = service
+            .list_due_todos(DueWindow::Overdue, &Some("Europe/Berlin"))
+            .await?;
+
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, overdue.id);
+
+        let error = service
+            .list_due_todos(DueWindow::Today, &Some("Mars/Olympus_Mons"))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("time_zone", _))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn due_ranges_follow_time_zone() {
+        let time_zone = "America/New_York".parse::<Tz>().unwrap();
+        let now = Utc.ymd(2020, 6, 10).and_hms(2, 0, 0);
+
+        let (timed, all_day) = create_due_ranges(DueWindow::Today, time_zone, now);
+
+        assert_eq!(timed.start, Some(Utc.ymd(2020, 6, 9).and_hms(4, 0, 0)));
+        assert_eq!(timed.end, Utc.ymd(2020, 6, 10).and_hms(4, 0, 0));
+        assert_eq!(all_day.start, Some(Utc.ymd(2020, 6, 9).and_hms(0, 0, 0)));
+
+        let (_, week) = create_due_ranges(DueWindow::ThisWeek, time_zone, now);
+
+        assert_eq!(week.end, Utc.ymd(2020, 6, 15).and_hms(0, 0, 0));
+
+        let (overdue, _) = create_due_ranges(DueWindow::Overdue, time_zone, now);
+
+        assert_eq!(overdue.start, None);
+        assert_eq!(overdue.end, now);
+    }
+
+    #[tokio::test]
+    async fn update_list_with_stale_version() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "new_list", &None).await?;
+        let update = ListUpdate {
+            name: Some("renamed_list".to_string()),
+        };
+
+        let updated = service
+            .update_list(&list.id, &update, &Some(list.version))
+            .await?;
+
+        assert_eq!(updated.version, list.version + 1);
+
+        let error = service
+            .update_list(&list.id, &update, &Some(list.version))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::PreconditionFailed(..))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_list_conflict() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "new_list", &None).await?;
+        let error = service
+            .add_list(&Some(&list.id), "new_list", &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "list_todos", &None).await?;
+
+        for description in &["c", "a", "b"] {
+            service
+                .add_todo(&None, &list.id, description, &None, &None, &None)
+                .await?;
+        }
+
+        let first = service
+            .list_todos(&list.id, 2, &None, &None, &[], TodoOrder::Description)
+            .await?;
+
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[0].description, "a");
+
+        let token = first.next_page_token.expect("missing next page token");
+        let second = service
+            .list_todos(
+                &list.id,
+                2,
+                &Some(&token),
+                &None,
+                &[],
+                TodoOrder::Description,
+            )
+            .await?;
+
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].description, "c");
+        assert!(second.next_page_token.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_todos_by_tag() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "list_todos_by_tag", &None).await?;
+        let first = service
+            .add_todo(&None, &list.id, "first", &None, &None, &None)
+            .await?;
+        let second = service
+            .add_todo(&None, &list.id, "second", &None, &None, &None)
+            .await?;
+        let urgent = service.add_tag("urgent").await?;
+        let home = service.add_tag("home").await?;
+
+        service.attach_tag(&first.id, &urgent.id).await?;
+        service.attach_tag(&first.id, &home.id).await?;
+        service.attach_tag(&second.id, &urgent.id).await?;
+
+        let page = service
+            .list_todos(&list.id, 10, &None, &None, &[urgent.id], TodoOrder::Id)
+            .await?;
+        assert_eq!(page.items.len(), 2);
+
+        let page = service
+            .list_todos(
+                &list.id,
+                10,
+                &None,
+                &None,
+                &[home.id, urgent.id, home.id],
+                TodoOrder::Id,
+            )
+            .await?;
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, first.id);
+
+        let error = service.add_tag("home").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NameConflict(name)) if name == "home"
+        ));
+
+        service.remove_tag(&home.id).await?;
+
+        let tags = service.list_todo_tags(&first.id).await?;
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "urgent");
+
+        service.remove_list(&list.id, &None).await?;
+
+        let error = service.attach_tag(&first.id, &urgent.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(id)) if *id == first.id
+        ));
+        assert_eq!(service.list_tags().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn complete_subtasks() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "complete_subtasks", &None).await?;
+        let parent = service
+            .add_todo(&None, &list.id, "parent", &None, &None, &None)
+            .await?;
+        let first = service
+            .add_todo(&None, &list.id, "first", &None, &Some(&parent.id), &None)
+            .await?;
+        let second = service
+            .add_todo(&None, &list.id, "second", &None, &Some(&parent.id), &None)
+            .await?;
+
+        let tree = service.get_todo_tree(&parent.id).await?;
+        assert_eq!(tree.subtasks.len(), 2);
+
+        let error = service
+            .complete_todo(&parent.id, true, false, &None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("cascade", _))
+        ));
+
+        service.complete_todo(&first.id, true, false, &None).await?;
+        assert_eq!(service.get_todo(&parent.id).await?.done, false);
+
+        service
+            .complete_todo(&second.id, true, false, &None)
+            .await?;
+        assert_eq!(service.get_todo(&parent.id).await?.done, true);
+
+        service
+            .complete_todo(&second.id, false, false, &None)
+            .await?;
+        assert_eq!(service.get_todo(&parent.id).await?.done, false);
+
+        service.complete_todo(&parent.id, true, true, &None).await?;
+        assert_eq!(service.get_todo(&second.id).await?.done, true);
+
+        let update = TodoUpdate {
+            parent_id: Some(Some(first.id)),
+            ..TodoUpdate::default()
+        };
+        let error = service
+            .update_todo(&parent.id, &update, &None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("parent_id", _))
+        ));
+
+        let other = service.add_list(&None, "other_list", &None).await?;
+        let error = service
+            .add_todo(&None, &other.id, "stray", &None, &Some(&parent.id), &None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("parent_id", _))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_todo() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "move_todo", &None).await?;
+        let mut ids = Vec::new();
+
+        for description in &["first", "second", "third"] {
+            let todo = service
+                .add_todo(&None, &list.id, description, &None, &None, &None)
+                .await?;
+            ids.push(todo.id);
+        }
+
+        let moved = service.move_todo(&ids[2], &None, &Some(&ids[0])).await?;
+        assert_eq!(moved.version, 2);
+
+        service
+            .move_todo(&ids[0], &Some(&ids[2]), &Some(&ids[1]))
+            .await?;
+
+        // Repeated moves into the same gap grow the key until the list is renumbered.
+        for _ in 0..100 {
+            service.move_todo(&ids[1], &None, &Some(&ids[0])).await?;
+            service.move_todo(&ids[0], &None, &Some(&ids[1])).await?;
+        }
+
+        let page = service
+            .list_todos(&list.id, 10, &None, &None, &[], TodoOrder::Position)
+            .await?;
+        let order = page.items.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(order, vec![ids[2], ids[0], ids[1]]);
+        assert!(page
+            .items
+            .iter()
+            .all(|todo| todo.position.len() <= REBALANCE_LENGTH));
+
+        let error = service
+            .move_todo(&ids[0], &Some(&ids[1]), &Some(&ids[2]))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("before_id", _))
+        ));
+
+        let other = service.add_list(&None, "other_list", &None).await?;
+        let stranger = service
+            .add_todo(&None, &other.id, "stranger", &None, &None, &None)
+            .await?;
+
+        let error = service
+            .move_todo(&ids[0], &Some(&stranger.id), &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("after_id", _))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_todo_to_list() -> Result<()> {
+        let service = create_service().await?;
+
+        let source = service.add_list(&None, "source", &None).await?;
+        let target = service.add_list(&None, "target", &None).await?;
+        let parent = service
+            .add_todo(&None, &source.id, "parent", &None, &None, &None)
+            .await?;
+        let todo = service
+            .add_todo(&None, &source.id, "todo", &None, &Some(&parent.id), &None)
+            .await?;
+        let subtask = service
+            .add_todo(&None, &source.id, "subtask", &None, &Some(&todo.id), &None)
+            .await?;
+        let sibling = service
+            .add_todo(
+                &None,
+                &source.id,
+                "sibling",
+                &None,
+                &Some(&parent.id),
+                &None,
+            )
+            .await?;
+        service
+            .complete_todo(&sibling.id, true, false, &None)
+            .await?;
+
+        let mut events = service.subscribe();
+        let moved = service.move_todo_to_list(&todo.id, &target.id).await?;
+
+        assert_eq!(moved.list_id, target.id);
+        assert_eq!(moved.parent_id, None);
+        assert_eq!(moved.version, todo.version + 1);
+
+        let subtask = service.get_todo(&subtask.id).await?;
+        assert_eq!(subtask.list_id, target.id);
+        assert_eq!(subtask.parent_id, Some(todo.id));
+
+        let tree = service.get_todo_tree(&parent.id).await?;
+        assert_eq!(tree.subtasks.len(), 1);
+        assert!(tree.todo.done);
+
+        for expected in &[todo.id, subtask.id] {
+            match events.recv().await? {
+                Event::Todo {
+                    kind,
+                    list_id,
+                    todo_id,
+                    ..
+                } => {
+                    assert_eq!(kind, ChangeKind::Removed);
+                    assert_eq!(list_id, source.id);
+                    assert_eq!(todo_id, *expected);
+                }
+                event => panic!("unexpected event {:?}", event),
+            };
+
+            match events.recv().await? {
+                Event::Todo { kind, list_id, .. } => {
+                    assert_eq!(kind, ChangeKind::Created);
+                    assert_eq!(list_id, target.id);
+                }
+                event => panic!("unexpected event {:?}", event),
+            };
+        }
+
+        let error = service
+            .move_todo_to_list(&todo.id, &Uuid::new_v4())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Validation("list_id", _))
+        ));
+        assert_eq!(service.get_todo(&todo.id).await?.list_id, target.id);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "batch_todos", &None).await?;
+        let parent_id = Uuid::new_v4();
+
+        let mut invalid = vec![
+            new_todo("subtask", None, Some(parent_id)),
+            new_todo("parent", Some(parent_id), None),
+            new_todo("stray", None, Some(Uuid::new_v4())),
+        ];
+        invalid[1].due = Some(Due::Date(NaiveDate::from_ymd(10000, 1, 1)));
+
+        let error = service.add_todos(&list.id, &invalid).await.unwrap_err();
+
+        match error.downcast_ref::<ServiceError>() {
+            Some(ServiceError::InvalidItems(violations)) => {
+                let fields = violations
+                    .iter()
+                    .map(|violation| (violation.index, violation.field))
+                    .collect::<Vec<(usize, &str)>>();
+
+                assert_eq!(fields, vec![(0, "parent_id"), (1, "due"), (2, "parent_id")]);
+            }
+            _ => panic!("unexpected error {:?}", error),
+        };
+
+        let page = service
+            .list_todos(&list.id, 10, &None, &None, &[], TodoOrder::Id)
+            .await?;
+        assert_eq!(page.items.len(), 0);
+
+        let todos = (0..250)
+            .map(|index| match index {
+                0 => new_todo("parent", Some(parent_id), None),
+                _ => new_todo(&format!("todo_{}", index), None, Some(parent_id)),
+            })
+            .collect::<Vec<NewTodo>>();
+
+        let created = service.add_todos(&list.id, &todos).await?;
+
+        assert_eq!(created.len(), 250);
+        assert_eq!(created[0].id, parent_id);
+        assert!(created
+            .windows(2)
+            .all(|pair| pair[0].position < pair[1].position));
+        assert_eq!(service.get_todo_tree(&parent_id).await?.subtasks.len(), 249);
+
+        service
+            .complete_todo(&created[1].id, true, false, &None)
+            .await?;
+
+        let completed = service.complete_all_todos(&list.id).await?;
+        assert_eq!(completed.len(), 249);
+
+        let removed = service.clear_completed_todos(&list.id).await?;
+        assert_eq!(removed.len(), 250);
+
+        let page = service
+            .list_todos(&list.id, 10, &None, &None, &[], TodoOrder::Id)
+            .await?;
+        assert_eq!(page.items.len(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_todos_checks_existing_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "batch_todos", &None).await?;
+        let other = service.add_list(&None, "other_list", &None).await?;
+        let existing = service
+            .add_todo(&None, &list.id, "existing", &None, &None, &None)
+            .await?;
+        let stranger = service
+            .add_todo(&None, &other.id, "stranger", &None, &None, &None)
+            .await?;
+
+        let invalid = vec![
+            new_todo("duplicate", Some(existing.id), None),
+            new_todo("subtask", None, Some(existing.id)),
+            new_todo("misplaced", None, Some(stranger.id)),
+        ];
+
+        let error = service.add_todos(&list.id, &invalid).await.unwrap_err();
+
+        match error.downcast_ref::<ServiceError>() {
+            Some(ServiceError::InvalidItems(violations)) => {
+                let fields = violations
+                    .iter()
+                    .map(|violation| (violation.index, violation.field))
+                    .collect::<Vec<(usize, &str)>>();
+
+                assert_eq!(fields, vec![(0, "id"), (2, "parent_id")]);
+            }
+            _ => panic!("unexpected error {:?}", error),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ingest_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "ingest_todos", &None).await?;
+        let existing = service
+            .add_todo(&None, &list.id, "existing", &None, &None, &None)
+            .await?;
+        let parent_id = Uuid::new_v4();
+
+        let mut todos = vec![
+            new_todo("parent", Some(parent_id), None),
+            new_todo("orphan", None, Some(parent_id)),
+            new_todo("kept", None, None),
+            new_todo("duplicate", Some(existing.id), None),
+        ];
+        todos[0].due = Some(Due::Date(NaiveDate::from_ymd(10000, 1, 1)));
+
+        let violations = service.ingest_todos(&list.id, &todos).await?;
+        let fields = violations
+            .iter()
+            .map(|violation| (violation.index, violation.field))
+            .collect::<Vec<(usize, &str)>>();
+
+        assert_eq!(fields, vec![(0, "due"), (1, "parent_id"), (3, "id")]);
+
+        let page = service
+            .list_todos(&list.id, 10, &None, &None, &[], TodoOrder::Position)
+            .await?;
+        let descriptions = page
+            .items
+            .iter()
+            .map(|todo| todo.description.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(descriptions, vec!["existing", "kept"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_list_removes_todos() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "remove_list", &None).await?;
+        let todo = service
+            .add_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+
+        service.remove_list(&list.id, &None).await?;
+
+        assert!(service.get_todo(&todo.id).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn publishes_events() -> Result<()> {
+        let service = create_service().await?;
+        let mut events = service.subscribe();
+
+        let list = service.add_list(&None, "publishes_events", &None).await?;
+        let todo = service
+            .add_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+
+        match events.recv().await? {
+            Event::List { kind, list_id, .. } => {
+                assert_eq!(kind, ChangeKind::Created);
+                assert_eq!(list_id, list.id);
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+
+        match events.recv().await? {
+            Event::Todo { kind, todo_id, .. } => {
+                assert_eq!(kind, ChangeKind::Created);
+                assert_eq!(todo_id, todo.id);
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn complete_todo() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "complete_todo", &None).await?;
+        let todo = service
+            .add_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+        let error = service
+            .complete_todo(&todo.id, true, false, &Some(todo.version + 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::PreconditionFailed(_, _))
+        ));
+
+        let todo = service
+            .complete_todo(&todo.id, true, false, &Some(todo.version))
+            .await?;
+
+        assert_eq!(todo.done, true);
+
+        service.remove_todo(&todo.id, &None).await?;
+
+        let error = service.get_todo(&todo.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_todo_removes_subtree() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "remove_todo", &None).await?;
+        let parent = service
+            .add_todo(&None, &list.id, "parent", &None, &None, &None)
+            .await?;
+        let first = service
+            .add_todo(&None, &list.id, "first", &None, &Some(&parent.id), &None)
+            .await?;
+        let second = service
+            .add_todo(&None, &list.id, "second", &None, &Some(&parent.id), &None)
+            .await?;
+        let nested = service
+            .add_todo(&None, &list.id, "nested", &None, &Some(&second.id), &None)
+            .await?;
+        service.complete_todo(&first.id, true, false, &None).await?;
+
+        let mut events = service.subscribe();
+        service.remove_todo(&second.id, &None).await?;
+
+        for removed_id in &[second.id, nested.id] {
+            match events.recv().await? {
+                Event::Todo { kind, todo_id, .. } => {
+                    assert_eq!(kind, ChangeKind::Removed);
+                    assert_eq!(todo_id, *removed_id);
+                }
+                event => panic!("unexpected event {:?}", event),
+            };
+        }
+
+        match events.recv().await? {
+            Event::Todo { kind, todo_id, .. } => {
+                assert_eq!(kind, ChangeKind::Updated);
+                assert_eq!(todo_id, parent.id);
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+
+        assert_eq!(service.get_todo(&parent.id).await?.done, true);
+        assert!(service.get_todo(&nested.id).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clear_completed_todos_removes_open_subtasks() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "clear_completed", &None).await?;
+        let parent = service
+            .add_todo(&None, &list.id, "parent", &None, &None, &None)
+            .await?;
+        let other = service
+            .add_todo(&None, &list.id, "other", &None, &None, &None)
+            .await?;
+        service
+            .complete_todo(&parent.id, true, false, &None)
+            .await?;
+        let open = service
+            .add_todo(&None, &list.id, "open", &None, &Some(&parent.id), &None)
+            .await?;
+
+        let mut removed = service.clear_completed_todos(&list.id).await?;
+        removed.sort();
+
+        let mut expected = vec![parent.id, open.id];
+        expected.sort();
+
+        assert_eq!(removed, expected);
+        assert!(service.get_todo(&open.id).await.is_err());
+        assert_eq!(service.get_todo(&other.id).await?.done, false);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_todo_rolls_up_old_parent() -> Result<()> {
+        let service = create_service().await?;
+
+        let list = service.add_list(&None, "update_todo", &None).await?;
+        let source = service
+            .add_todo(&None, &list.id, "source", &None, &None, &None)
+            .await?;
+        let target = service
+            .add_todo(&None, &list.id, "target", &None, &None, &None)
+            .await?;
+        let done = service
+            .add_todo(&None, &list.id, "done", &None, &Some(&source.id), &None)
+            .await?;
+        let open = service
+            .add_todo(&None, &list.id, "open", &None, &Some(&source.id), &None)
+            .await?;
+        service.complete_todo(&done.id, true, false, &None).await?;
+        assert_eq!(service.get_todo(&source.id).await?.done, false);
+
+        let update = TodoUpdate {
+            parent_id: Some(Some(target.id)),
+            ..TodoUpdate::default()
+        };
+        service.update_todo(&open.id, &update, &None).await?;
+
+        assert_eq!(service.get_todo(&source.id).await?.done, true);
+        assert_eq!(service.get_todo(&target.id).await?.done, false);
 
         Ok(())
     }
@@ -0,0 +1,2306 @@
+use super::super::common::model::Error as ModelError;
+use super::super::common::pagination::{self, Cursor, Page};
+use super::super::common::position;
+use super::super::database;
+use super::idempotency::{self, IdempotencyClaim, IdempotencyRecord};
+use super::model::{
+    self, Due, DueRange, ListOrder, ListUpdate, NewTodo, Tag, Todo, TodoList, TodoOrder, TodoUpdate,
+};
+use super::store::ChecklistStore;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use database::{create_insert_statement, create_not_found_error, create_update_statement};
+use database::{extract_database_error_code, MAX_INSERT_ROWS};
+use database::{ImmediateTransaction, SqliteErrorCode};
+use sqlx::{pool::PoolConnection, sqlite::SqliteQueryAs, Executor, SqliteConnection, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const ENABLE_FOREIGN_KEYS: &str = "PRAGMA foreign_keys = ON;";
+
+const INSERT_LIST: &str = "
+  INSERT INTO lists (id, name, created_at, updated_at)
+  VALUES (
+    ?1,
+    ?2,
+    strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    strftime('%Y-%m-%d %H:%M:%f', 'now')
+  );
+";
+
+const SELECT_LIST: &str = "
+  SELECT id, name, version, created_at, updated_at FROM lists
+  WHERE id = ?1;
+";
+
+const SELECT_LISTS_BY_NAME: &str = "
+  SELECT id, name, version, created_at, updated_at, name FROM lists
+  WHERE (?1 IS NULL OR substr(name, 1, length(?1)) = ?1)
+    AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)
+    AND (?3 IS NULL OR (name, id) > (?3, ?4))
+  ORDER BY name, id
+  LIMIT ?5;
+";
+
+const SELECT_LISTS_BY_CREATED_AT: &str = "
+  SELECT id, name, version, created_at, updated_at, created_at FROM lists
+  WHERE (?1 IS NULL OR substr(name, 1, length(?1)) = ?1)
+    AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)
+    AND (?3 IS NULL OR (created_at, id) > (?3, ?4))
+  ORDER BY created_at, id
+  LIMIT ?5;
+";
+
+const DELETE_LIST: &str = "
+  DELETE FROM lists
+  WHERE id = ?1
+    AND (?2 IS NULL OR version = ?2);
+";
+
+const INSERT_TODO: &str = "
+  INSERT INTO todos (
+    list_id,
+    id,
+    description,
+    done,
+    due_at,
+    due_all_day,
+    parent_id,
+    position,
+    created_at,
+    updated_at
+  )
+  VALUES (
+    ?1,
+    ?2,
+    ?3,
+    ?4,
+    ?5,
+    ?6,
+    ?7,
+    ?8,
+    strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    strftime('%Y-%m-%d %H:%M:%f', 'now')
+  );
+";
+
+const SELECT_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id = ?1;
+";
+
+const SELECT_TODOS_FOR_LISTS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id IN (SELECT value FROM json_each(?1))
+  ORDER BY list_id, position, id;
+";
+
+const SELECT_TODOS_BY_ID: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ?1
+    AND (?2 IS NULL OR done = ?2)
+    AND (?3 IS NULL OR id > ?4)
+    AND (json_array_length(?6) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id IN (SELECT value FROM json_each(?6))
+      GROUP BY todo_id
+      HAVING COUNT(*) = json_array_length(?6)
+    ))
+  ORDER BY id
+  LIMIT ?5;
+";
+
+const SELECT_TODOS_BY_DESCRIPTION: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ?1
+    AND (?2 IS NULL OR done = ?2)
+    AND (?3 IS NULL OR (description, id) > (?3, ?4))
+    AND (json_array_length(?6) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id IN (SELECT value FROM json_each(?6))
+      GROUP BY todo_id
+      HAVING COUNT(*) = json_array_length(?6)
+    ))
+  ORDER BY description, id
+  LIMIT ?5;
+";
+
+const SELECT_TODOS_BY_POSITION: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ?1
+    AND (?2 IS NULL OR done = ?2)
+    AND (?3 IS NULL OR (position, id) > (?3, ?4))
+    AND (json_array_length(?6) = 0 OR id IN (
+      SELECT todo_id FROM todo_tags
+      WHERE tag_id IN (SELECT value FROM json_each(?6))
+      GROUP BY todo_id
+      HAVING COUNT(*) = json_array_length(?6)
+    ))
+  ORDER BY position, id
+  LIMIT ?5;
+";
+
+const SELECT_NEXT_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ?1
+    AND (position, id) > (?2, ?3)
+    AND id <> ?4
+  ORDER BY position, id
+  LIMIT 1;
+";
+
+const SELECT_PREVIOUS_TODO: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE list_id = ?1
+    AND (position, id) < (?2, ?3)
+    AND id <> ?4
+  ORDER BY position DESC, id DESC
+  LIMIT 1;
+";
+
+const SELECT_LAST_POSITION: &str = "
+  SELECT MAX(position) FROM todos
+  WHERE list_id = ?1;
+";
+
+const UPDATE_TODO_POSITION: &str = "
+  UPDATE todos
+  SET position = ?2,
+    updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id = ?1;
+";
+
+const UPDATE_TODO_PARENT: &str = "
+  UPDATE todos
+  SET parent_id = NULL
+  WHERE id = ?1;
+";
+
+const UPDATE_MOVED_TODO: &str = "
+  UPDATE todos
+  SET list_id = ?2,
+    position = ?3,
+    updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id = ?1;
+";
+
+const SELECT_TODO_IDS_BY_POSITION: &str = "
+  SELECT id FROM todos
+  WHERE list_id = ?1
+  ORDER BY position, id;
+";
+
+const UPDATE_POSITION: &str = "
+  UPDATE todos
+  SET position = ?2,
+    updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id = ?1;
+";
+
+const SELECT_TODO_TREE: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE id = ?1
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id IN (SELECT id FROM tree)
+  ORDER BY position, id;
+";
+
+const SELECT_SUBTASKS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE parent_id = ?1
+  ORDER BY position, id;
+";
+
+const UPDATE_TODO_DONE: &str = "
+  UPDATE todos
+  SET done = ?2,
+    completed_at = CASE
+      WHEN ?2 THEN COALESCE(completed_at, strftime('%Y-%m-%d %H:%M:%f', 'now'))
+      ELSE NULL
+    END,
+    updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id = ?1;
+";
+
+const SELECT_SUBTASK_COUNTS: &str = "
+  SELECT done, parent_id,
+    (SELECT COUNT(*) FROM todos AS subtasks WHERE subtasks.parent_id = todos.id),
+    (SELECT COUNT(*) FROM todos AS subtasks WHERE subtasks.parent_id = todos.id AND NOT subtasks.done)
+  FROM todos
+  WHERE id = ?1;
+";
+
+const SELECT_DUE_TODOS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE NOT done
+    AND (
+      (NOT due_all_day AND (?1 IS NULL OR due_at >= ?1) AND due_at < ?2)
+      OR (due_all_day AND (?3 IS NULL OR due_at >= ?3) AND due_at < ?4)
+    )
+  ORDER BY due_at, id;
+";
+
+const SELECT_TODO_TREE_IDS: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE id = ?1
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  SELECT id FROM tree;
+";
+
+const DELETE_TODO: &str = "
+  DELETE FROM todos
+  WHERE id = ?1
+    AND (?2 IS NULL OR version = ?2);
+";
+
+const INSERT_TAG: &str = "
+  INSERT INTO tags (id, name)
+  VALUES (?1, ?2);
+";
+
+const SELECT_TAGS: &str = "
+  SELECT id, name FROM tags
+  ORDER BY name, id;
+";
+
+const UPDATE_TAG: &str = "
+  UPDATE tags
+  SET name = ?2
+  WHERE id = ?1;
+";
+
+const DELETE_TAG: &str = "
+  DELETE FROM tags
+  WHERE id = ?1;
+";
+
+const INSERT_TODO_TAG: &str = "
+  INSERT OR IGNORE INTO todo_tags (todo_id, tag_id)
+  VALUES (?1, ?2);
+";
+
+const DELETE_TODO_TAG: &str = "
+  DELETE FROM todo_tags
+  WHERE todo_id = ?1 AND tag_id = ?2;
+";
+
+const UPDATE_TAGGED_TODO: &str = "
+  UPDATE todos
+  SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id = ?1;
+";
+
+const SELECT_TAGGED_TODO_IDS: &str = "
+  SELECT todo_id FROM todo_tags
+  WHERE tag_id = ?1;
+";
+
+const UPDATE_TAGGED_TODOS: &str = "
+  UPDATE todos
+  SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE id IN (SELECT todo_id FROM todo_tags WHERE tag_id = ?1);
+";
+
+const SELECT_TAGS_FOR_TODO: &str = "
+  SELECT tags.id, tags.name FROM tags
+  JOIN todo_tags ON todo_tags.tag_id = tags.id
+  WHERE todo_tags.todo_id = ?1
+  ORDER BY tags.name, tags.id;
+";
+
+const SELECT_IDEMPOTENCY_RECORD: &str = "
+  SELECT idempotency_key, request_hash, response FROM idempotency_keys
+  WHERE idempotency_key = ?1;
+";
+
+const INSERT_IDEMPOTENCY_CLAIM: &str = "
+  INSERT INTO idempotency_keys (idempotency_key, request_hash, response)
+  VALUES (?1, ?2, '');
+";
+
+const UPDATE_IDEMPOTENCY_RESPONSE: &str = "
+  UPDATE idempotency_keys SET response = ?2
+  WHERE idempotency_key = ?1;
+";
+
+const SELECT_TODOS_BY_IDS: &str = "
+  SELECT list_id, id, description, done, version, created_at, updated_at, completed_at,
+    due_at, due_all_day, parent_id, position
+  FROM todos
+  WHERE id IN (SELECT value FROM json_each(?1))
+  ORDER BY position, id;
+";
+
+const SELECT_OPEN_TODO_IDS: &str = "
+  SELECT id FROM todos
+  WHERE list_id = ?1
+    AND NOT done;
+";
+
+const COMPLETE_TODOS: &str = "
+  UPDATE todos
+  SET done = 1,
+    completed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    version = version + 1
+  WHERE list_id = ?1
+    AND NOT done;
+";
+
+const SELECT_COMPLETED_TODO_IDS: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE list_id = ?1
+      AND done
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  SELECT id FROM tree;
+";
+
+const DELETE_COMPLETED_TODOS: &str = "
+  WITH RECURSIVE tree (id) AS (
+    SELECT id FROM todos
+    WHERE list_id = ?1
+      AND done
+    UNION
+    SELECT todos.id FROM todos
+    JOIN tree ON todos.parent_id = tree.id
+  )
+  DELETE FROM todos
+  WHERE id IN (SELECT id FROM tree);
+";
+
+// Timestamps are bound rather than defaulted, since columns added by later migrations default to ''.
+const TODO_INSERT_COLUMNS: &[&str] = &[
+    "list_id",
+    "id",
+    "description",
+    "done",
+    "due_at",
+    "due_all_day",
+    "parent_id",
+    "position",
+    "created_at",
+    "updated_at",
+];
+
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+// Matches strftime's '%f', so stored timestamps compare correctly as text.
+const STORED_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+type ListRow = (String, String, i64, String, String);
+
+#[derive(sqlx::FromRow)]
+struct TodoRow {
+    list_id: String,
+    id: String,
+    description: String,
+    done: bool,
+    version: i64,
+    created_at: String,
+    updated_at: String,
+    completed_at: Option<String>,
+    due_at: Option<String>,
+    due_all_day: bool,
+    parent_id: Option<String>,
+    position: String,
+}
+
+#[derive(Debug)]
+pub struct SqliteModel {
+    pool: Arc<SqlitePool>,
+}
+
+impl SqliteModel {
+    pub fn new(pool: Arc<SqlitePool>) -> SqliteModel {
+        SqliteModel { pool }
+    }
+
+    // SQLite enforces foreign keys per connection, so every pooled connection is switched on
+    // before use rather than once at pool creation.
+    async fn acquire(&self) -> Result<PoolConnection<SqliteConnection>> {
+        let mut conn = self.pool.acquire().await?;
+        conn.execute(ENABLE_FOREIGN_KEYS).await?;
+        Ok(conn)
+    }
+
+    async fn begin(&self) -> Result<ImmediateTransaction> {
+        ImmediateTransaction::begin(self.acquire().await?).await
+    }
+}
+
+#[async_trait]
+impl ChecklistStore for SqliteModel {
+    async fn create_list(
+        &self,
+        id: &Option<&Uuid>,
+        name: &str,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<TodoList> {
+        let mut tx = self.begin().await?;
+        let list = create_list(&mut tx, id, name, claim).await?;
+        tx.commit().await?;
+
+        Ok(list)
+    }
+
+    async fn get_list(&self, id: &Uuid) -> Result<TodoList> {
+        let mut conn = self.acquire().await?;
+        fetch_list(&mut conn, id).await
+    }
+
+    async fn list_lists(
+        &self,
+        page_size: i64,
+        page_token: &Option<&str>,
+        name_prefix: &Option<&str>,
+        name_contains: &Option<&str>,
+        order: ListOrder,
+    ) -> Result<Page<TodoList>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let query = match order {
+            ListOrder::Name => SELECT_LISTS_BY_NAME,
+            ListOrder::CreatedAt => SELECT_LISTS_BY_CREATED_AT,
+        };
+
+        let (cursor_key, cursor_id) = match cursor {
+            Some(Cursor { key, id }) => (Some(key), Some(id.to_hyphenated().to_string())),
+            None => (None, None),
+        };
+
+        let mut conn = self.acquire().await?;
+
+        let mut rows = sqlx::query_as::<_, (String, String, i64, String, String, String)>(query)
+            .bind(name_prefix.map(str::to_string))
+            .bind(name_contains.map(str::to_string))
+            .bind(cursor_key)
+            .bind(cursor_id)
+            .bind(page_size + 1)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let has_next_page = rows.len() as i64 > page_size;
+        rows.truncate(page_size as usize);
+
+        let next_key = match rows.last() {
+            Some(row) if has_next_page => Some(row.5.clone()),
+            _ => None,
+        };
+
+        let lists = rows
+            .into_iter()
+            .map(|(id, name, version, created_at, updated_at, _)| {
+                create_list_from_row((id, name, version, created_at, updated_at))
+            })
+            .collect::<Result<Vec<TodoList>>>()?;
+
+        let next_page_token = match (next_key, lists.last()) {
+            (Some(key), Some(list)) => Some(pagination::encode_page_token(
+                order.as_str(),
+                &Cursor { key, id: list.id },
+            )),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: lists,
+            next_page_token,
+        })
+    }
+
+    async fn update_list(
+        &self,
+        id: &Uuid,
+        update: &ListUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<TodoList> {
+        let mut conn = self.acquire().await?;
+        let columns = update.columns();
+
+        if columns.is_empty() {
+            let list = fetch_list(&mut conn, id).await?;
+            model::check_version(id, list.version, expected_version)?;
+            return Ok(list);
+        }
+
+        let statement =
+            create_update_statement("lists", &columns, "?", NOW, expected_version.is_some());
+        let mut query = sqlx::query(&statement).bind(id.to_hyphenated().to_string());
+
+        if let Some(name) = &update.name {
+            query = query.bind(name);
+        }
+
+        if let Some(version) = *expected_version {
+            query = query.bind(version);
+        }
+
+        let affected = query.execute(&mut conn).await?;
+
+        // When nothing matched, fetching the row tells a missing row apart from a stale version.
+        let list = fetch_list(&mut conn, id).await?;
+
+        if affected == 0 {
+            model::check_version(id, list.version, expected_version)?;
+        }
+
+        Ok(list)
+    }
+
+    async fn destroy_list(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()> {
+        let mut conn = self.acquire().await?;
+
+        let affected = sqlx::query(DELETE_LIST)
+            .bind(id.to_hyphenated().to_string())
+            .bind(*expected_version)
+            .execute(&mut conn)
+            .await?;
+
+        if affected == 0 {
+            let list = fetch_list(&mut conn, id).await?;
+            model::check_version(id, list.version, expected_version)?;
+        }
+
+        Ok(())
+    }
+
+    async fn create_todo(
+        &self,
+        id: &Option<&Uuid>,
+        list_id: &Uuid,
+        description: &str,
+        due: &Option<Due>,
+        parent_id: &Option<&Uuid>,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<Todo> {
+        let mut tx = self.begin().await?;
+        let todo = create_todo(&mut tx, id, list_id, description, due, parent_id, claim).await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+
+    async fn create_todos(&self, list_id: &Uuid, todos: &[NewTodo]) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let created = create_todos(&mut tx, list_id, todos).await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn get_todo(&self, id: &Uuid) -> Result<Todo> {
+        let mut conn = self.acquire().await?;
+        fetch_todo(&mut conn, id).await
+    }
+
+    async fn get_todos_for_lists(&self, list_ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let list_ids = list_ids
+            .iter()
+            .map(|id| id.to_hyphenated().to_string())
+            .collect::<Vec<String>>();
+
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_FOR_LISTS)
+            .bind(serde_json::to_string(&list_ids)?)
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_todo_from_row).collect()
+    }
+
+    async fn get_todos_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Todo>> {
+        let ids = ids
+            .iter()
+            .map(|id| id.to_hyphenated().to_string())
+            .collect::<Vec<String>>();
+
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+            .bind(serde_json::to_string(&ids)?)
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_todo_from_row).collect()
+    }
+
+    async fn list_todos(
+        &self,
+        list_id: &Uuid,
+        page_size: i64,
+        page_token: &Option<&str>,
+        done: &Option<bool>,
+        tag_ids: &[Uuid],
+        order: TodoOrder,
+    ) -> Result<Page<Todo>> {
+        let cursor = match *page_token {
+            Some(token) => Some(pagination::decode_page_token(token, order.as_str())?),
+            None => None,
+        };
+
+        let query = match order {
+            TodoOrder::Id => SELECT_TODOS_BY_ID,
+            TodoOrder::Description => SELECT_TODOS_BY_DESCRIPTION,
+            TodoOrder::Position => SELECT_TODOS_BY_POSITION,
+        };
+
+        let (cursor_key, cursor_id) = match cursor {
+            Some(Cursor { key, id }) => (Some(key), Some(id.to_hyphenated().to_string())),
+            None => (None, None),
+        };
+
+        let tag_ids = tag_ids
+            .iter()
+            .map(|id| id.to_hyphenated().to_string())
+            .collect::<Vec<String>>();
+
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(query)
+            .bind(list_id.to_hyphenated().to_string())
+            .bind(*done)
+            .bind(cursor_key)
+            .bind(cursor_id)
+            .bind(page_size + 1)
+            .bind(serde_json::to_string(&tag_ids)?)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let mut todos = rows
+            .into_iter()
+            .map(create_todo_from_row)
+            .collect::<Result<Vec<Todo>>>()?;
+
+        let next_page_token = if todos.len() as i64 > page_size {
+            todos.truncate(page_size as usize);
+            todos.last().map(|todo| {
+                let key = match order {
+                    TodoOrder::Id => todo.id.to_hyphenated().to_string(),
+                    TodoOrder::Description => todo.description.clone(),
+                    TodoOrder::Position => todo.position.clone(),
+                };
+
+                pagination::encode_page_token(order.as_str(), &Cursor { key, id: todo.id })
+            })
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items: todos,
+            next_page_token,
+        })
+    }
+
+    async fn update_todo(
+        &self,
+        id: &Uuid,
+        update: &TodoUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo> {
+        let mut conn = self.acquire().await?;
+        let columns = update.columns();
+
+        if columns.is_empty() {
+            let todo = fetch_todo(&mut conn, id).await?;
+            model::check_version(id, todo.version, expected_version)?;
+            return Ok(todo);
+        }
+
+        let statement =
+            create_update_statement("todos", &columns, "?", NOW, expected_version.is_some());
+        let mut query = sqlx::query(&statement).bind(id.to_hyphenated().to_string());
+
+        if let Some(description) = &update.description {
+            query = query.bind(description);
+        }
+
+        if let Some(done) = update.done {
+            query = query.bind(done);
+        }
+
+        if let Some(due) = &update.due {
+            let (due_at, due_all_day) = Due::to_columns(due);
+            query = query.bind(due_at.map(format_timestamp)).bind(due_all_day);
+        }
+
+        if let Some(parent_id) = update.parent_id {
+            query = query.bind(parent_id.map(|id| id.to_hyphenated().to_string()));
+        }
+
+        if let Some(version) = *expected_version {
+            query = query.bind(version);
+        }
+
+        // The transaction holds the write lock through the nesting check, so concurrent re-parents
+        // take turns.
+        let mut tx = ImmediateTransaction::begin(conn).await?;
+
+        if let Some(Some(parent_id)) = update.parent_id {
+            check_nesting(&mut tx, id, &parent_id).await?;
+        }
+
+        let affected = query.execute(&mut *tx).await?;
+        let mut conn = tx.commit().await?;
+
+        let todo = fetch_todo(&mut conn, id).await?;
+
+        if affected == 0 {
+            model::check_version(id, todo.version, expected_version)?;
+        }
+
+        Ok(todo)
+    }
+
+    async fn get_todo_tree(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+            .bind(id.to_hyphenated().to_string())
+            .fetch_all(&mut conn)
+            .await?;
+
+        if rows.is_empty() {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        rows.into_iter().map(create_todo_from_row).collect()
+    }
+
+    async fn get_subtasks(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_SUBTASKS)
+            .bind(parent_id.to_hyphenated().to_string())
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_todo_from_row).collect()
+    }
+
+    async fn move_todo_to_list(&self, id: &Uuid, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let todos = move_todo_to_list(&mut tx, id, list_id).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+
+    async fn get_adjacent_todo(
+        &self,
+        anchor: &Todo,
+        following: bool,
+        exclude_id: &Uuid,
+    ) -> Result<Option<Todo>> {
+        let query = match following {
+            true => SELECT_NEXT_TODO,
+            false => SELECT_PREVIOUS_TODO,
+        };
+
+        let mut conn = self.acquire().await?;
+
+        let row = sqlx::query_as::<_, TodoRow>(query)
+            .bind(anchor.list_id.to_hyphenated().to_string())
+            .bind(&anchor.position)
+            .bind(anchor.id.to_hyphenated().to_string())
+            .bind(exclude_id.to_hyphenated().to_string())
+            .fetch_optional(&mut conn)
+            .await?;
+
+        match row {
+            Some(row) => return Ok(Some(create_todo_from_row(row)?)),
+            None => return Ok(None),
+        };
+    }
+
+    async fn set_position(&self, id: &Uuid, position: &str) -> Result<Todo> {
+        let mut conn = self.acquire().await?;
+
+        let affected = sqlx::query(UPDATE_TODO_POSITION)
+            .bind(id.to_hyphenated().to_string())
+            .bind(position)
+            .execute(&mut conn)
+            .await?;
+
+        if affected == 0 {
+            return Err(Error::new(ModelError::NotFound(*id)));
+        }
+
+        fetch_todo(&mut conn, id).await
+    }
+
+    async fn rebalance_positions(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let todos = rebalance_positions(&mut tx, list_id).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+
+    async fn set_done(&self, id: &Uuid, done: bool) -> Result<Todo> {
+        let mut conn = self.acquire().await?;
+        set_todo_done(&mut conn, id, done).await
+    }
+
+    async fn complete_todo(
+        &self,
+        id: &Uuid,
+        done: bool,
+        cascade: bool,
+        expected_version: &Option<i64>,
+    ) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let changed = complete_todo(&mut tx, id, done, cascade, expected_version).await?;
+        tx.commit().await?;
+
+        Ok(changed)
+    }
+
+    async fn roll_up(&self, parent_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let changed = roll_up(&mut tx, parent_id).await?;
+        tx.commit().await?;
+
+        Ok(changed)
+    }
+
+    async fn get_due_todos(&self, timed: &DueRange, all_day: &DueRange) -> Result<Vec<Todo>> {
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_DUE_TODOS)
+            .bind(timed.start.map(format_timestamp))
+            .bind(format_timestamp(timed.end))
+            .bind(all_day.start.map(format_timestamp))
+            .bind(format_timestamp(all_day.end))
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_todo_from_row).collect()
+    }
+
+    async fn complete_todos(&self, list_id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let todos = complete_todos(&mut tx, list_id).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+
+    async fn destroy_completed_todos(&self, list_id: &Uuid) -> Result<Vec<Uuid>> {
+        let mut tx = self.begin().await?;
+        let ids = destroy_completed_todos(&mut tx, list_id).await?;
+        tx.commit().await?;
+
+        Ok(ids)
+    }
+
+    async fn destroy_todo(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<Vec<Uuid>> {
+        let mut tx = self.begin().await?;
+        let ids = destroy_todo(&mut tx, id, expected_version).await?;
+        tx.commit().await?;
+
+        Ok(ids)
+    }
+
+    async fn create_tag(&self, name: &str) -> Result<Tag> {
+        let tag = Tag {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+        };
+
+        let mut conn = self.acquire().await?;
+
+        let result = sqlx::query(INSERT_TAG)
+            .bind(tag.id.to_hyphenated().to_string())
+            .bind(&tag.name)
+            .execute(&mut conn)
+            .await;
+
+        match result {
+            Ok(_) => return Ok(tag),
+            Err(err) => return Err(model::create_tag_name_error(err, name)),
+        };
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>> {
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, (String, String)>(SELECT_TAGS)
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_tag_from_row).collect()
+    }
+
+    async fn rename_tag(&self, id: &Uuid, name: &str) -> Result<(Tag, Vec<Todo>)> {
+        let mut tx = self.begin().await?;
+        let renamed = rename_tag(&mut tx, id, name).await?;
+        tx.commit().await?;
+
+        Ok(renamed)
+    }
+
+    async fn destroy_tag(&self, id: &Uuid) -> Result<Vec<Todo>> {
+        let mut tx = self.begin().await?;
+        let todos = destroy_tag(&mut tx, id).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+
+    async fn attach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let mut tx = self.begin().await?;
+        let todo = attach_tag(&mut tx, todo_id, tag_id).await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+
+    async fn detach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>> {
+        let mut tx = self.begin().await?;
+        let todo = detach_tag(&mut tx, todo_id, tag_id).await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+
+    async fn get_tags_for_todo(&self, todo_id: &Uuid) -> Result<Vec<Tag>> {
+        let mut conn = self.acquire().await?;
+
+        let rows = sqlx::query_as::<_, (String, String)>(SELECT_TAGS_FOR_TODO)
+            .bind(todo_id.to_hyphenated().to_string())
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter().map(create_tag_from_row).collect()
+    }
+
+    async fn get_idempotency_record(&self, key: &str) -> Result<Option<IdempotencyRecord>> {
+        let mut conn = self.acquire().await?;
+
+        let row = sqlx::query_as::<_, (String, String, String)>(SELECT_IDEMPOTENCY_RECORD)
+            .bind(key)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(row.map(model::create_idempotency_record_from_row))
+    }
+}
+
+async fn fetch_list(conn: &mut PoolConnection<SqliteConnection>, id: &Uuid) -> Result<TodoList> {
+    let result = sqlx::query_as::<_, ListRow>(SELECT_LIST)
+        .bind(id.to_hyphenated().to_string())
+        .fetch_one(conn)
+        .await;
+
+    let row = match result {
+        Err(err) => return Err(create_not_found_error(err, id)),
+        Ok(row) => row,
+    };
+
+    let list = create_list_from_row(row)?;
+
+    Ok(list)
+}
+
+async fn fetch_todo(conn: &mut PoolConnection<SqliteConnection>, id: &Uuid) -> Result<Todo> {
+    let result = sqlx::query_as::<_, TodoRow>(SELECT_TODO)
+        .bind(id.to_hyphenated().to_string())
+        .fetch_one(conn)
+        .await;
+
+    let row = match result {
+        Err(err) => return Err(create_not_found_error(err, id)),
+        Ok(row) => row,
+    };
+
+    let todo = create_todo_from_row(row)?;
+
+    Ok(todo)
+}
+
+async fn create_list(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Option<&Uuid>,
+    name: &str,
+    claim: &Option<&IdempotencyClaim>,
+) -> Result<TodoList> {
+    let id = match *id {
+        Some(id) => id.to_owned(),
+        None => Uuid::new_v4(),
+    };
+
+    if let Some(claim) = claim {
+        claim_idempotency_key(&mut *conn, claim).await?;
+    }
+
+    let result = sqlx::query(INSERT_LIST)
+        .bind(id.to_hyphenated().to_string())
+        .bind(name)
+        .execute(&mut *conn)
+        .await;
+
+    if let Err(err) = result {
+        let error_code = match extract_database_error_code(&err) {
+            Err(_) => return Err(Error::new(err)),
+            Ok(code) => code,
+        };
+
+        match error_code.as_ref() {
+            SqliteErrorCode::PrimaryKeyViolation | SqliteErrorCode::UniqueViolation => {
+                return Err(Error::new(ModelError::Conflict(id)))
+            }
+            _ => return Err(Error::new(err)),
+        };
+    }
+
+    let list = fetch_list(&mut *conn, &id).await?;
+
+    if let Some(claim) = claim {
+        save_idempotency_response(conn, &claim.key, &idempotency::encode_list(&list)).await?;
+    }
+
+    Ok(list)
+}
+
+async fn create_todo(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Option<&Uuid>,
+    list_id: &Uuid,
+    description: &str,
+    due: &Option<Due>,
+    parent_id: &Option<&Uuid>,
+    claim: &Option<&IdempotencyClaim>,
+) -> Result<Todo> {
+    let id = match *id {
+        Some(id) => id.to_owned(),
+        None => Uuid::new_v4(),
+    };
+
+    if let Some(claim) = claim {
+        claim_idempotency_key(&mut *conn, claim).await?;
+    }
+
+    let (due_at, due_all_day) = Due::to_columns(due);
+
+    let (last_position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let result = sqlx::query(INSERT_TODO)
+        .bind(list_id.to_hyphenated().to_string())
+        .bind(id.to_hyphenated().to_string())
+        .bind(description)
+        .bind(false)
+        .bind(due_at.map(format_timestamp))
+        .bind(due_all_day)
+        .bind(parent_id.map(|id| id.to_hyphenated().to_string()))
+        .bind(position::after(last_position.as_deref()))
+        .execute(&mut *conn)
+        .await;
+
+    if let Err(err) = result {
+        let error_code = match extract_database_error_code(&err) {
+            Err(_) => return Err(Error::new(err)),
+            Ok(code) => code,
+        };
+
+        match error_code.as_ref() {
+            SqliteErrorCode::ForeignKeyViolation => {
+                return Err(create_foreign_key_error(conn, list_id, parent_id).await)
+            }
+            SqliteErrorCode::PrimaryKeyViolation | SqliteErrorCode::UniqueViolation => {
+                return Err(Error::new(ModelError::Conflict(id)))
+            }
+            _ => return Err(Error::new(err)),
+        };
+    }
+
+    let todo = fetch_todo(&mut *conn, &id).await?;
+
+    if let Some(claim) = claim {
+        save_idempotency_response(conn, &claim.key, &idempotency::encode_todo(&todo)).await?;
+    }
+
+    Ok(todo)
+}
+
+// SQLite doesn't name the violated constraint, so look for the missing parent before blaming the list.
+async fn create_foreign_key_error(
+    conn: &mut PoolConnection<SqliteConnection>,
+    list_id: &Uuid,
+    parent_id: &Option<&Uuid>,
+) -> Error {
+    let parent_id = match *parent_id {
+        Some(parent_id) => parent_id,
+        None => return model::create_missing_list_error(list_id),
+    };
+
+    match fetch_todo(conn, parent_id).await {
+        Ok(parent) if parent.list_id == *list_id => {
+            return model::create_missing_list_error(list_id)
+        }
+        Ok(_) => return model::create_missing_parent_error(parent_id),
+        Err(err) => match err.downcast_ref::<ModelError>() {
+            Some(ModelError::NotFound(_)) => return model::create_missing_parent_error(parent_id),
+            _ => return err,
+        },
+    };
+}
+
+async fn check_nesting(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    parent_id: &Uuid,
+) -> Result<()> {
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+        .bind(id.to_hyphenated().to_string())
+        .fetch_all(conn)
+        .await?;
+
+    if rows
+        .iter()
+        .any(|row| row.id == parent_id.to_hyphenated().to_string())
+    {
+        return Err(model::create_nesting_error(id));
+    }
+
+    Ok(())
+}
+
+async fn rename_tag(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    name: &str,
+) -> Result<(Tag, Vec<Todo>)> {
+    let result = sqlx::query(UPDATE_TAG)
+        .bind(id.to_hyphenated().to_string())
+        .bind(name)
+        .execute(&mut *conn)
+        .await;
+
+    match result {
+        Ok(0) => return Err(Error::new(ModelError::NotFound(*id))),
+        Ok(_) => {}
+        Err(err) => return Err(model::create_tag_name_error(err, name)),
+    };
+
+    let tag = Tag {
+        id: *id,
+        name: name.to_string(),
+    };
+
+    Ok((tag, touch_tagged_todos(conn, id).await?))
+}
+
+// The tagged todos are touched first, since deleting the tag cascades to their links.
+async fn destroy_tag(conn: &mut PoolConnection<SqliteConnection>, id: &Uuid) -> Result<Vec<Todo>> {
+    let todos = touch_tagged_todos(&mut *conn, id).await?;
+
+    let affected = sqlx::query(DELETE_TAG)
+        .bind(id.to_hyphenated().to_string())
+        .execute(conn)
+        .await?;
+
+    if affected == 0 {
+        return Err(Error::new(ModelError::NotFound(*id)));
+    }
+
+    Ok(todos)
+}
+
+async fn touch_tagged_todos(
+    conn: &mut PoolConnection<SqliteConnection>,
+    tag_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let ids = sqlx::query_as::<_, (String,)>(SELECT_TAGGED_TODO_IDS)
+        .bind(tag_id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<String>>();
+
+    sqlx::query(UPDATE_TAGGED_TODOS)
+        .bind(tag_id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(conn)
+        .await?;
+
+    rows.into_iter().map(create_todo_from_row).collect()
+}
+
+// Tagging only touches the todo when a link was actually added or removed.
+async fn attach_tag(
+    conn: &mut PoolConnection<SqliteConnection>,
+    todo_id: &Uuid,
+    tag_id: &Uuid,
+) -> Result<Option<Todo>> {
+    let result = sqlx::query(INSERT_TODO_TAG)
+        .bind(todo_id.to_hyphenated().to_string())
+        .bind(tag_id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await;
+
+    let error = match result {
+        Ok(0) => return Ok(None),
+        Ok(_) => return touch_todo(conn, todo_id).await.map(Some),
+        Err(err) => err,
+    };
+
+    match extract_database_error_code(&error) {
+        Ok(code) if code == SqliteErrorCode::ForeignKeyViolation => {
+            // Either side may be missing, so look up the todo to report the right one.
+            fetch_todo(conn, todo_id).await?;
+            return Err(Error::new(ModelError::NotFound(*tag_id)));
+        }
+        _ => return Err(Error::new(error)),
+    };
+}
+
+async fn detach_tag(
+    conn: &mut PoolConnection<SqliteConnection>,
+    todo_id: &Uuid,
+    tag_id: &Uuid,
+) -> Result<Option<Todo>> {
+    let affected = sqlx::query(DELETE_TODO_TAG)
+        .bind(todo_id.to_hyphenated().to_string())
+        .bind(tag_id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    if affected == 0 {
+        return Ok(None);
+    }
+
+    touch_todo(conn, todo_id).await.map(Some)
+}
+
+async fn touch_todo(conn: &mut PoolConnection<SqliteConnection>, id: &Uuid) -> Result<Todo> {
+    sqlx::query(UPDATE_TAGGED_TODO)
+        .bind(id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    fetch_todo(conn, id).await
+}
+
+async fn set_todo_done(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    done: bool,
+) -> Result<Todo> {
+    let affected = sqlx::query(UPDATE_TODO_DONE)
+        .bind(id.to_hyphenated().to_string())
+        .bind(done)
+        .execute(&mut *conn)
+        .await?;
+
+    if affected == 0 {
+        return Err(Error::new(ModelError::NotFound(*id)));
+    }
+
+    fetch_todo(conn, id).await
+}
+
+// The todo comes first, followed by the subtasks it closed and the ancestors that rolled up.
+async fn complete_todo(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    done: bool,
+    cascade: bool,
+    expected_version: &Option<i64>,
+) -> Result<Vec<Todo>> {
+    if expected_version.is_some() {
+        let todo = fetch_todo(&mut *conn, id).await?;
+        model::check_version(id, todo.version, expected_version)?;
+    }
+
+    let todo = set_todo_done(&mut *conn, id, done).await?;
+    let parent_id = todo.parent_id;
+    let mut changed = vec![todo];
+
+    if done && cascade {
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+            .bind(id.to_hyphenated().to_string())
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for row in rows.into_iter().filter(|row| !row.done) {
+            let subtask_id = Uuid::parse_str(&row.id)?;
+            changed.push(set_todo_done(&mut *conn, &subtask_id, true).await?);
+        }
+    }
+
+    if let Some(parent_id) = parent_id {
+        changed.extend(roll_up(conn, &parent_id).await?);
+    }
+
+    Ok(changed)
+}
+
+// Finishing the last open subtask completes the parent, and an open subtask reopens it. Either
+// change can carry on up through the ancestors.
+async fn roll_up(
+    conn: &mut PoolConnection<SqliteConnection>,
+    parent_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let mut changed = Vec::new();
+    let mut next = Some(*parent_id);
+
+    while let Some(id) = next {
+        let row = sqlx::query_as::<_, (bool, Option<String>, i64, i64)>(SELECT_SUBTASK_COUNTS)
+            .bind(id.to_hyphenated().to_string())
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let (done, parent_id, subtasks, open) = match row {
+            Some(row) => row,
+            None => break,
+        };
+
+        if subtasks == 0 || done == (open == 0) {
+            break;
+        }
+
+        changed.push(set_todo_done(&mut *conn, &id, open == 0).await?);
+        next = parent_id.map(|id| Uuid::parse_str(&id)).transpose()?;
+    }
+
+    Ok(changed)
+}
+
+async fn claim_idempotency_key(
+    conn: &mut PoolConnection<SqliteConnection>,
+    claim: &IdempotencyClaim,
+) -> Result<()> {
+    let result = sqlx::query(INSERT_IDEMPOTENCY_CLAIM)
+        .bind(&claim.key)
+        .bind(&claim.request_hash)
+        .execute(conn)
+        .await;
+
+    let error = match result {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+
+    let error_code = match extract_database_error_code(&error) {
+        Err(_) => return Err(Error::new(error)),
+        Ok(code) => code,
+    };
+
+    match error_code.as_ref() {
+        SqliteErrorCode::PrimaryKeyViolation | SqliteErrorCode::UniqueViolation => {
+            return Err(Error::new(ModelError::KeyConflict(claim.key.clone())))
+        }
+        _ => return Err(Error::new(error)),
+    };
+}
+
+async fn save_idempotency_response(
+    conn: &mut PoolConnection<SqliteConnection>,
+    key: &str,
+    response: &str,
+) -> Result<()> {
+    sqlx::query(UPDATE_IDEMPOTENCY_RESPONSE)
+        .bind(key)
+        .bind(response)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+// Foreign keys are checked once each statement finishes, so a subtask may share a chunk with its
+// parent.
+async fn create_todos(
+    conn: &mut PoolConnection<SqliteConnection>,
+    list_id: &Uuid,
+    todos: &[NewTodo],
+) -> Result<Vec<Todo>> {
+    let (mut position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let now = format_timestamp(Utc::now());
+    let mut created = Vec::with_capacity(todos.len());
+
+    for chunk in todos.chunks(MAX_INSERT_ROWS) {
+        let statement = create_insert_statement("todos", TODO_INSERT_COLUMNS, chunk.len(), "?");
+        let mut query = sqlx::query(&statement);
+        let mut ids = Vec::with_capacity(chunk.len());
+
+        for new_todo in chunk {
+            let id = match new_todo.id {
+                Some(id) => id,
+                None => Uuid::new_v4(),
+            };
+
+            let next = position::after(position.as_deref());
+            let (due_at, due_all_day) = Due::to_columns(&new_todo.due);
+
+            query = query
+                .bind(list_id.to_hyphenated().to_string())
+                .bind(id.to_hyphenated().to_string())
+                .bind(&new_todo.description)
+                .bind(false)
+                .bind(due_at.map(format_timestamp))
+                .bind(due_all_day)
+                .bind(new_todo.parent_id.map(|id| id.to_hyphenated().to_string()))
+                .bind(&next)
+                .bind(&now)
+                .bind(&now);
+
+            ids.push(id.to_hyphenated().to_string());
+            position = Some(next);
+        }
+
+        if let Err(err) = query.execute(&mut *conn).await {
+            return Err(model::create_batch_write_error(err, list_id));
+        }
+
+        let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+            .bind(serde_json::to_string(&ids)?)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for row in rows {
+            created.push(create_todo_from_row(row)?);
+        }
+    }
+
+    Ok(created)
+}
+
+async fn complete_todos(
+    conn: &mut PoolConnection<SqliteConnection>,
+    list_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let ids = sqlx::query_as::<_, (String,)>(SELECT_OPEN_TODO_IDS)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<String>>();
+
+    sqlx::query(COMPLETE_TODOS)
+        .bind(list_id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(conn)
+        .await?;
+
+    rows.into_iter().map(create_todo_from_row).collect()
+}
+
+// The subtree is read before the delete, since its rows go with the cascade.
+async fn destroy_todo(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    expected_version: &Option<i64>,
+) -> Result<Vec<Uuid>> {
+    let rows = sqlx::query_as::<_, (String,)>(SELECT_TODO_TREE_IDS)
+        .bind(id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let affected = sqlx::query(DELETE_TODO)
+        .bind(id.to_hyphenated().to_string())
+        .bind(*expected_version)
+        .execute(&mut *conn)
+        .await?;
+
+    if affected == 0 {
+        let todo = fetch_todo(conn, id).await?;
+        model::check_version(id, todo.version, expected_version)?;
+    }
+
+    let mut ids = vec![*id];
+
+    for (todo_id,) in rows {
+        let todo_id = Uuid::parse_str(&todo_id)?;
+
+        if todo_id != *id {
+            ids.push(todo_id);
+        }
+    }
+
+    Ok(ids)
+}
+
+async fn destroy_completed_todos(
+    conn: &mut PoolConnection<SqliteConnection>,
+    list_id: &Uuid,
+) -> Result<Vec<Uuid>> {
+    let rows = sqlx::query_as::<_, (String,)>(SELECT_COMPLETED_TODO_IDS)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?;
+
+    sqlx::query(DELETE_COMPLETED_TODOS)
+        .bind(list_id.to_hyphenated().to_string())
+        .execute(conn)
+        .await?;
+
+    let ids = rows
+        .into_iter()
+        .map(|(id,)| Uuid::parse_str(&id))
+        .collect::<std::result::Result<Vec<Uuid>, _>>()?;
+
+    Ok(ids)
+}
+
+// Subtasks follow through the cascading (list_id, parent_id) key, but every todo in the subtree is
+// still rewritten to take a position in the target list, with the todo first.
+async fn move_todo_to_list(
+    conn: &mut PoolConnection<SqliteConnection>,
+    id: &Uuid,
+    list_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let todo = fetch_todo(conn, id).await?;
+
+    if todo.list_id == *list_id {
+        return Ok(vec![todo]);
+    }
+
+    let (mut position,) = sqlx::query_as::<_, (Option<String>,)>(SELECT_LAST_POSITION)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODO_TREE)
+        .bind(id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut ids = rows
+        .into_iter()
+        .map(|row| Uuid::parse_str(&row.id))
+        .collect::<std::result::Result<Vec<Uuid>, _>>()?;
+
+    ids.retain(|todo_id| todo_id != id);
+    ids.insert(0, *id);
+
+    sqlx::query(UPDATE_TODO_PARENT)
+        .bind(id.to_hyphenated().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    let mut todos = Vec::with_capacity(ids.len());
+
+    for todo_id in ids {
+        let next = position::after(position.as_deref());
+
+        let result = sqlx::query(UPDATE_MOVED_TODO)
+            .bind(todo_id.to_hyphenated().to_string())
+            .bind(list_id.to_hyphenated().to_string())
+            .bind(&next)
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(err) = result {
+            match extract_database_error_code(&err) {
+                Ok(code) if code == SqliteErrorCode::ForeignKeyViolation => {
+                    return Err(model::create_missing_list_error(list_id))
+                }
+                _ => return Err(Error::new(err)),
+            };
+        }
+
+        todos.push(fetch_todo(conn, &todo_id).await?);
+        position = Some(next);
+    }
+
+    Ok(todos)
+}
+
+async fn rebalance_positions(
+    conn: &mut PoolConnection<SqliteConnection>,
+    list_id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let ids = sqlx::query_as::<_, (String,)>(SELECT_TODO_IDS_BY_POSITION)
+        .bind(list_id.to_hyphenated().to_string())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<String>>();
+
+    let positions = position::spread(ids.len());
+
+    for (id, position) in ids.iter().zip(positions) {
+        sqlx::query(UPDATE_POSITION)
+            .bind(id)
+            .bind(position)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let rows = sqlx::query_as::<_, TodoRow>(SELECT_TODOS_BY_IDS)
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(conn)
+        .await?;
+
+    rows.into_iter().map(create_todo_from_row).collect()
+}
+
+fn create_list_from_row(row: ListRow) -> Result<TodoList> {
+    let row = (
+        Uuid::parse_str(&row.0)?,
+        row.1,
+        row.2,
+        parse_timestamp(&row.3)?,
+        parse_timestamp(&row.4)?,
+    );
+
+    Ok(model::create_list_from_row(row))
+}
+
+fn create_todo_from_row(row: TodoRow) -> Result<Todo> {
+    let completed_at = match row.completed_at {
+        Some(completed_at) => Some(parse_timestamp(&completed_at)?),
+        None => None,
+    };
+
+    let due_at = match row.due_at {
+        Some(due_at) => Some(parse_timestamp(&due_at)?),
+        None => None,
+    };
+
+    let parent_id = match row.parent_id {
+        Some(parent_id) => Some(Uuid::parse_str(&parent_id)?),
+        None => None,
+    };
+
+    let row = model::TodoRow {
+        list_id: Uuid::parse_str(&row.list_id)?,
+        id: Uuid::parse_str(&row.id)?,
+        description: row.description,
+        done: row.done,
+        version: row.version,
+        created_at: parse_timestamp(&row.created_at)?,
+        updated_at: parse_timestamp(&row.updated_at)?,
+        completed_at,
+        due_at,
+        due_all_day: row.due_all_day,
+        parent_id,
+        position: row.position,
+    };
+
+    Ok(model::create_todo_from_row(row))
+}
+
+fn create_tag_from_row(row: (String, String)) -> Result<Tag> {
+    Ok(model::create_tag_from_row((
+        Uuid::parse_str(&row.0)?,
+        row.1,
+    )))
+}
+
+// SQLite keeps timestamps as UTC text without an offset.
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>> {
+    let timestamp = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)?;
+    Ok(DateTime::from_utc(timestamp, Utc))
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format(STORED_TIMESTAMP_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::migration;
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+    use pretty_assertions::assert_eq;
+
+    async fn create_model() -> Result<SqliteModel> {
+        let pool = database::create_sqlite_pool("sqlite::memory:").await?;
+        migration::up_sqlite(&pool).await?;
+        Ok(SqliteModel::new(pool))
+    }
+
+    #[tokio::test]
+    async fn create_list_conflict() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let error = model
+            .create_list(&Some(&list.id), "new_list", &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Conflict(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_todo_claims_key() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let claim = IdempotencyClaim {
+            key: "new_key".to_string(),
+            request_hash: "hash".to_string(),
+        };
+
+        let error = model
+            .create_todo(
+                &None,
+                &Uuid::new_v4(),
+                "new_todo",
+                &None,
+                &None,
+                &Some(&claim),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(_))
+        ));
+        assert_eq!(model.get_idempotency_record("new_key").await?, None);
+
+        let todo = model
+            .create_todo(&None, &list.id, "new_todo", &None, &None, &Some(&claim))
+            .await?;
+        let error = model
+            .create_todo(&None, &list.id, "new_todo", &None, &None, &Some(&claim))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::KeyConflict(_))
+        ));
+
+        let record = model.get_idempotency_record("new_key").await?.unwrap();
+        assert_eq!(idempotency::decode_todo(&record.response)?.id, todo.id);
+        assert_eq!(model.get_todos_for_lists(&[list.id]).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_todo_missing_parent() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let parent_id = Uuid::new_v4();
+
+        let error = model
+            .create_todo(&None, &list.id, "new_todo", &None, &Some(&parent_id), &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(message)) if message.contains("parent ID")
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn destroy_list_cascades() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let todo = model
+            .create_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+
+        assert_eq!(model.get_todo(&todo.id).await?.description, "new_todo");
+
+        model.destroy_list(&list.id, &None).await?;
+
+        let error = model.get_todo(&todo.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn todo_tags_cascade() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let first = model
+            .create_todo(&None, &list.id, "first", &None, &None, &None)
+            .await?;
+        let second = model
+            .create_todo(&None, &list.id, "second", &None, &None, &None)
+            .await?;
+        let urgent = model.create_tag("urgent").await?;
+        let home = model.create_tag("home").await?;
+
+        let tagged = model.attach_tag(&first.id, &urgent.id).await?;
+        assert_eq!(tagged.map(|todo| todo.version), Some(first.version + 1));
+        assert!(model.attach_tag(&first.id, &urgent.id).await?.is_none());
+
+        model.attach_tag(&first.id, &home.id).await?;
+        model.attach_tag(&second.id, &urgent.id).await?;
+
+        let page = model
+            .list_todos(
+                &list.id,
+                10,
+                &None,
+                &None,
+                &[urgent.id, home.id],
+                TodoOrder::Id,
+            )
+            .await?;
+
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, first.id);
+
+        let touched = model.destroy_tag(&home.id).await?;
+        assert_eq!(touched.len(), 1);
+        assert_eq!(touched[0].version, first.version + 3);
+
+        let tags = model.get_tags_for_todo(&first.id).await?;
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "urgent");
+
+        let error = model.attach_tag(&first.id, &home.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::NotFound(id)) if *id == home.id
+        ));
+
+        model.destroy_list(&list.id, &None).await?;
+
+        assert!(model.get_tags_for_todo(&second.id).await?.is_empty());
+        assert_eq!(model.list_tags().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn todo_tree() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let root = model
+            .create_todo(&None, &list.id, "root", &None, &None, &None)
+            .await?;
+        let child = model
+            .create_todo(&None, &list.id, "child", &None, &Some(&root.id), &None)
+            .await?;
+        let grandchild = model
+            .create_todo(
+                &None,
+                &list.id,
+                "grandchild",
+                &None,
+                &Some(&child.id),
+                &None,
+            )
+            .await?;
+        let other = model
+            .create_todo(&None, &list.id, "other", &None, &None, &None)
+            .await?;
+
+        let tree = model.get_todo_tree(&root.id).await?;
+        let ids = tree.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(tree.len(), 3);
+        assert!(ids.contains(&grandchild.id));
+        assert!(!ids.contains(&other.id));
+        assert_eq!(model.get_subtasks(&root.id).await?[0].id, child.id);
+
+        let update = TodoUpdate {
+            parent_id: Some(Some(grandchild.id)),
+            ..TodoUpdate::default()
+        };
+        let error = model
+            .update_todo(&root.id, &update, &None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(_))
+        ));
+
+        // A cycle written behind the model's back still leaves the tree query finite.
+        sqlx::query("UPDATE todos SET parent_id = ?1 WHERE id = ?2;")
+            .bind(grandchild.id.to_hyphenated().to_string())
+            .bind(root.id.to_hyphenated().to_string())
+            .execute(&mut model.acquire().await?)
+            .await?;
+        assert_eq!(model.get_todo_tree(&root.id).await?.len(), 3);
+
+        model.destroy_todo(&root.id, &None).await?;
+
+        let error = model.get_todo(&grandchild.id).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::NotFound(_))
+        ));
+        assert_eq!(model.get_todo(&other.id).await?.parent_id, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_todo_to_list() -> Result<()> {
+        let model = create_model().await?;
+
+        let source = model.create_list(&None, "source", &None).await?;
+        let target = model.create_list(&None, "target", &None).await?;
+        let existing = model
+            .create_todo(&None, &target.id, "existing", &None, &None, &None)
+            .await?;
+        let root = model
+            .create_todo(&None, &source.id, "root", &None, &None, &None)
+            .await?;
+        let child = model
+            .create_todo(&None, &source.id, "child", &None, &Some(&root.id), &None)
+            .await?;
+
+        let error = model
+            .move_todo_to_list(&root.id, &Uuid::new_v4())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(_))
+        ));
+        assert_eq!(model.get_todo(&child.id).await?.list_id, source.id);
+
+        let moved = model.move_todo_to_list(&root.id, &target.id).await?;
+        let ids = moved.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(ids, vec![root.id, child.id]);
+        assert!(moved.iter().all(|todo| todo.list_id == target.id));
+        assert!(moved[0].position > existing.position);
+        assert_eq!(model.get_subtasks(&root.id).await?[0].id, child.id);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_todos() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let todos = (0..MAX_INSERT_ROWS + 50)
+            .map(|index| NewTodo {
+                id: None,
+                description: format!("todo {}", index),
+                due: None,
+                parent_id: None,
+            })
+            .collect::<Vec<NewTodo>>();
+
+        let created = model.create_todos(&list.id, &todos).await?;
+        let listed = model.get_todos_for_lists(&[list.id]).await?;
+
+        assert_eq!(created.len(), todos.len());
+        assert_eq!(
+            listed.iter().map(|todo| todo.id).collect::<Vec<Uuid>>(),
+            created.iter().map(|todo| todo.id).collect::<Vec<Uuid>>()
+        );
+
+        let duplicate = NewTodo {
+            id: Some(created[0].id),
+            ..todos[0].clone()
+        };
+        let error = model
+            .create_todos(&list.id, &[duplicate])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(_))
+        ));
+
+        assert_eq!(model.complete_todos(&list.id).await?.len(), todos.len());
+        assert!(model.complete_todos(&list.id).await?.is_empty());
+        assert_eq!(
+            model.destroy_completed_todos(&list.id).await?.len(),
+            todos.len()
+        );
+        assert!(model.get_todos_for_lists(&[list.id]).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rebalance_positions() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let first = model
+            .create_todo(&None, &list.id, "first", &None, &None, &None)
+            .await?;
+        let second = model
+            .create_todo(&None, &list.id, "second", &None, &None, &None)
+            .await?;
+
+        assert!(first.position < second.position);
+
+        let next = model.get_adjacent_todo(&first, true, &first.id).await?;
+        assert_eq!(next.map(|todo| todo.id), Some(second.id));
+
+        let moved = model.set_position(&second.id, "0000000001").await?;
+        assert_eq!(moved.version, 2);
+
+        let rebalanced = model.rebalance_positions(&list.id).await?;
+        assert_eq!(rebalanced.len(), 2);
+
+        let todos = model.get_todos_for_lists(&[list.id]).await?;
+        let ids = todos.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(ids, vec![second.id, first.id]);
+        assert!(todos.iter().all(|todo| todo.position.len() == 1));
+        assert_eq!(todos[0].version, 3);
+        assert_eq!(todos[1].version, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_todo_for_missing_list() -> Result<()> {
+        let model = create_model().await?;
+
+        let error = model
+            .create_todo(&None, &Uuid::new_v4(), "new_todo", &None, &None, &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::Validation(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_done_tracks_completion() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let todo = model
+            .create_todo(&None, &list.id, "new_todo", &None, &None, &None)
+            .await?;
+
+        assert_eq!(todo.created_at, todo.updated_at);
+        assert!(todo.completed_at.is_none());
+
+        let completed = model.set_done(&todo.id, true).await?;
+        let completed_at = completed.completed_at.expect("missing completion time");
+        assert!(completed.updated_at >= todo.updated_at);
+
+        let again = model.set_done(&todo.id, true).await?;
+        assert_eq!(again.completed_at, Some(completed_at));
+
+        let reopened = model.set_done(&todo.id, false).await?;
+        assert!(reopened.completed_at.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn complete_todo_cascades_and_rolls_up() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let root = model
+            .create_todo(&None, &list.id, "root", &None, &None, &None)
+            .await?;
+        let child = model
+            .create_todo(&None, &list.id, "child", &None, &Some(&root.id), &None)
+            .await?;
+        let grandchild = model
+            .create_todo(
+                &None,
+                &list.id,
+                "grandchild",
+                &None,
+                &Some(&child.id),
+                &None,
+            )
+            .await?;
+
+        let changed = model.complete_todo(&child.id, true, true, &None).await?;
+        let ids = changed.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(ids, vec![child.id, grandchild.id, root.id]);
+        assert!(changed.iter().all(|todo| todo.done && todo.version == 2));
+
+        let changed = model
+            .complete_todo(&grandchild.id, false, false, &None)
+            .await?;
+        let ids = changed.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+
+        assert_eq!(ids, vec![grandchild.id, child.id, root.id]);
+        assert!(changed.iter().all(|todo| !todo.done));
+
+        let error = model
+            .complete_todo(&Uuid::new_v4(), true, true, &None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_due_todos() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let date = NaiveDate::from_ymd(2020, 6, 9);
+        let time = Utc.ymd(2020, 6, 9).and_hms(15, 30, 0);
+
+        let all_day = model
+            .create_todo(
+                &None,
+                &list.id,
+                "all_day",
+                &Some(Due::Date(date)),
+                &None,
+                &None,
+            )
+            .await?;
+        let timed = model
+            .create_todo(
+                &None,
+                &list.id,
+                "timed",
+                &Some(Due::Time(time)),
+                &None,
+                &None,
+            )
+            .await?;
+        model
+            .create_todo(&None, &list.id, "undated", &None, &None, &None)
+            .await?;
+
+        assert_eq!(all_day.due, Some(Due::Date(date)));
+        assert_eq!(timed.due, Some(Due::Time(time)));
+
+        let range = DueRange {
+            start: Some(Due::date_at(date)),
+            end: Due::date_at(date.succ()),
+        };
+
+        let todos = model.get_due_todos(&range, &range).await?;
+
+        let ids = todos.iter().map(|todo| todo.id).collect::<Vec<Uuid>>();
+        assert_eq!(ids, vec![all_day.id, timed.id]);
+
+        model.set_done(&timed.id, true).await?;
+
+        let todos = model.get_due_todos(&range, &range).await?;
+        assert_eq!(todos.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_list_checks_version() -> Result<()> {
+        let model = create_model().await?;
+
+        let list = model.create_list(&None, "new_list", &None).await?;
+        let update = ListUpdate {
+            name: Some("renamed_list".to_string()),
+        };
+
+        let updated = model
+            .update_list(&list.id, &update, &Some(list.version))
+            .await?;
+        assert_eq!(updated.version, list.version + 1);
+
+        let error = model
+            .update_list(&list.id, &update, &Some(list.version))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::PreconditionFailed(..))
+        ));
+
+        let error = model
+            .destroy_list(&list.id, &Some(list.version))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ModelError>(),
+            Some(ModelError::PreconditionFailed(..))
+        ));
+
+        model.destroy_list(&list.id, &Some(updated.version)).await?;
+
+        Ok(())
+    }
+}
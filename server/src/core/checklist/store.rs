@@ -0,0 +1,156 @@
+use super::super::common::pagination::Page;
+use super::super::{database, migration};
+use super::event::Event;
+use super::idempotency::{IdempotencyClaim, IdempotencyRecord};
+use super::memory::MemoryModel;
+use super::model::{
+    Due, DueRange, ListOrder, ListUpdate, Model, NewTodo, Tag, Todo, TodoList, TodoOrder,
+    TodoUpdate,
+};
+use super::sqlite::SqliteModel;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt::Debug;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+#[async_trait]
+pub trait ChecklistStore: Debug + Send + Sync {
+    async fn create_list(
+        &self,
+        id: &Option<&Uuid>,
+        name: &str,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<TodoList>;
+
+    async fn get_list(&self, id: &Uuid) -> Result<TodoList>;
+
+    async fn list_lists(
+        &self,
+        page_size: i64,
+        page_token: &Option<&str>,
+        name_prefix: &Option<&str>,
+        name_contains: &Option<&str>,
+        order: ListOrder,
+    ) -> Result<Page<TodoList>>;
+
+    async fn update_list(
+        &self,
+        id: &Uuid,
+        update: &ListUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<TodoList>;
+
+    async fn destroy_list(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<()>;
+
+    async fn create_todo(
+        &self,
+        id: &Option<&Uuid>,
+        list_id: &Uuid,
+        description: &str,
+        due: &Option<Due>,
+        parent_id: &Option<&Uuid>,
+        claim: &Option<&IdempotencyClaim>,
+    ) -> Result<Todo>;
+
+    async fn create_todos(&self, list_id: &Uuid, todos: &[NewTodo]) -> Result<Vec<Todo>>;
+
+    async fn get_todo(&self, id: &Uuid) -> Result<Todo>;
+
+    async fn get_todos_for_lists(&self, list_ids: &[Uuid]) -> Result<Vec<Todo>>;
+
+    async fn get_todos_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Todo>>;
+
+    async fn list_todos(
+        &self,
+        list_id: &Uuid,
+        page_size: i64,
+        page_token: &Option<&str>,
+        done: &Option<bool>,
+        tag_ids: &[Uuid],
+        order: TodoOrder,
+    ) -> Result<Page<Todo>>;
+
+    async fn update_todo(
+        &self,
+        id: &Uuid,
+        update: &TodoUpdate,
+        expected_version: &Option<i64>,
+    ) -> Result<Todo>;
+
+    async fn get_todo_tree(&self, id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn get_subtasks(&self, parent_id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn move_todo_to_list(&self, id: &Uuid, list_id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn get_adjacent_todo(
+        &self,
+        anchor: &Todo,
+        following: bool,
+        exclude_id: &Uuid,
+    ) -> Result<Option<Todo>>;
+
+    async fn set_position(&self, id: &Uuid, position: &str) -> Result<Todo>;
+
+    async fn rebalance_positions(&self, list_id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn set_done(&self, id: &Uuid, done: bool) -> Result<Todo>;
+
+    async fn complete_todo(
+        &self,
+        id: &Uuid,
+        done: bool,
+        cascade: bool,
+        expected_version: &Option<i64>,
+    ) -> Result<Vec<Todo>>;
+
+    async fn roll_up(&self, parent_id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn get_due_todos(&self, timed: &DueRange, all_day: &DueRange) -> Result<Vec<Todo>>;
+
+    async fn complete_todos(&self, list_id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn destroy_completed_todos(&self, list_id: &Uuid) -> Result<Vec<Uuid>>;
+
+    async fn destroy_todo(&self, id: &Uuid, expected_version: &Option<i64>) -> Result<Vec<Uuid>>;
+
+    async fn create_tag(&self, name: &str) -> Result<Tag>;
+
+    async fn list_tags(&self) -> Result<Vec<Tag>>;
+
+    async fn rename_tag(&self, id: &Uuid, name: &str) -> Result<(Tag, Vec<Todo>)>;
+
+    async fn destroy_tag(&self, id: &Uuid) -> Result<Vec<Todo>>;
+
+    async fn attach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>>;
+
+    async fn detach_tag(&self, todo_id: &Uuid, tag_id: &Uuid) -> Result<Option<Todo>>;
+
+    async fn get_tags_for_todo(&self, todo_id: &Uuid) -> Result<Vec<Tag>>;
+
+    async fn get_idempotency_record(&self, key: &str) -> Result<Option<IdempotencyRecord>>;
+
+    fn emits_events(&self) -> bool {
+        false
+    }
+
+    async fn listen(&self, _events: broadcast::Sender<Event>) {}
+}
+
+pub async fn create_store(database_url: &str) -> Result<Box<dyn ChecklistStore>> {
+    if database_url.starts_with("memory:") {
+        return Ok(Box::new(MemoryModel::new()));
+    }
+
+    if database_url.starts_with("sqlite:") {
+        let pool = database::create_sqlite_pool(database_url).await?;
+        migration::up_sqlite(&pool).await?;
+        return Ok(Box::new(SqliteModel::new(pool)));
+    }
+
+    let pool = database::create_pool(database_url).await?;
+    migration::up(&pool).await?;
+
+    Ok(Box::new(Model::new(pool)))
+}
@@ -1,2 +1,4 @@
 pub mod model;
+pub mod pagination;
+pub mod position;
 pub mod service;
@@ -9,4 +9,10 @@ pub enum Error {
     NotFound(Uuid),
     #[error("{0}")]
     Validation(String),
+    #[error("'{0}' does not match expected version {1}")]
+    PreconditionFailed(Uuid, i64),
+    #[error("idempotency key '{0}' already claimed")]
+    KeyConflict(String),
+    #[error("name '{0}' already in use")]
+    NameConflict(String),
 }
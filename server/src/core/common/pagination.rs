@@ -0,0 +1,79 @@
+use super::model::Error as ModelError;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cursor {
+    pub key: String,
+    pub id: Uuid,
+}
+
+pub fn encode_page_token(order: &str, cursor: &Cursor) -> String {
+    let token = json!([order, cursor.key, cursor.id.to_hyphenated().to_string()]);
+    base64::encode_config(token.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+pub fn decode_page_token(token: &str, order: &str) -> Result<Cursor, ModelError> {
+    let invalid = || ModelError::Validation(format!("'{}' is not a valid page token", token));
+
+    let bytes = base64::decode_config(token, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+    let value = serde_json::from_slice::<Value>(&bytes).map_err(|_| invalid())?;
+
+    let parts = match value.as_array() {
+        Some(parts) if parts.len() == 3 => parts,
+        _ => return Err(invalid()),
+    };
+
+    let (token_order, key, id) = match (parts[0].as_str(), parts[1].as_str(), parts[2].as_str()) {
+        (Some(token_order), Some(key), Some(id)) => (token_order, key, id),
+        _ => return Err(invalid()),
+    };
+
+    if token_order != order {
+        return Err(ModelError::Validation(
+            "page token was issued for a different ordering".to_string(),
+        ));
+    }
+
+    Ok(Cursor {
+        key: key.to_string(),
+        id: Uuid::parse_str(id).map_err(|_| invalid())?,
+    })
+}
+
+pub fn clamp_page_size(page_size: i64) -> i64 {
+    match page_size {
+        0 => DEFAULT_PAGE_SIZE,
+        size if size > MAX_PAGE_SIZE => MAX_PAGE_SIZE,
+        size => size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn page_token_round_trip() {
+        let cursor = Cursor {
+            key: "description".to_string(),
+            id: Uuid::new_v4(),
+        };
+
+        let token = encode_page_token("description", &cursor);
+
+        assert_eq!(decode_page_token(&token, "description").unwrap(), cursor);
+        assert!(decode_page_token(&token, "id").is_err());
+        assert!(decode_page_token("garbage", "id").is_err());
+    }
+}
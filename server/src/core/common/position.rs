@@ -0,0 +1,160 @@
+// Positions are base-62 fractions written as digit strings without trailing zeros, so comparing
+// them as plain strings orders them numerically. A key can always be found between two distinct
+// keys, which lets an item move by rewriting only its own key.
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// Repeated moves into the same gap lengthen keys by about one digit every six moves, so lists are
+// renumbered once a key grows past this.
+pub const REBALANCE_LENGTH: usize = 24;
+
+pub fn after(before: Option<&str>) -> String {
+    let before = match before {
+        Some(before) => before.as_bytes(),
+        None => return midpoint(b"", None),
+    };
+
+    // Bumping the first digit that can still grow keeps appended keys short.
+    match before
+        .iter()
+        .position(|digit| *digit != DIGITS[DIGITS.len() - 1])
+    {
+        Some(index) => {
+            let mut key = before[..index].to_vec();
+            key.push(DIGITS[digit_value(before[index]) + 1]);
+            to_string(key)
+        }
+        None => {
+            let mut key = before.to_vec();
+            key.extend(midpoint(b"", None).into_bytes());
+            to_string(key)
+        }
+    }
+}
+
+// Equal bounds leave no room for a key between them, and nothing sorts before an empty key. Only
+// renumbering can fix either.
+pub fn between(before: Option<&str>, after: Option<&str>) -> Option<String> {
+    match (before, after) {
+        (_, Some("")) => None,
+        (Some(before), Some(after)) if before >= after => None,
+        (before, None) => Some(self::after(before)),
+        (before, Some(after)) => Some(midpoint(
+            before.unwrap_or_default().as_bytes(),
+            Some(after.as_bytes()),
+        )),
+    }
+}
+
+// Evenly spaced keys of equal width for `count` items, used when renumbering a list.
+pub fn spread(count: usize) -> Vec<String> {
+    let base = DIGITS.len() as u128;
+    let slots = count as u128 + 1;
+    let mut width = 1;
+
+    while base.pow(width) <= slots {
+        width += 1;
+    }
+
+    let step = base.pow(width) / slots;
+
+    (1..slots).map(|slot| encode(slot * step, width)).collect()
+}
+
+fn midpoint(before: &[u8], after: Option<&[u8]>) -> String {
+    if let Some(after) = after {
+        let common = after
+            .iter()
+            .enumerate()
+            .take_while(|(index, digit)| before.get(*index).unwrap_or(&DIGITS[0]) == *digit)
+            .count();
+
+        if common > 0 {
+            let mut key = after[..common].to_vec();
+            let rest = midpoint(&before[common.min(before.len())..], Some(&after[common..]));
+            key.extend(rest.into_bytes());
+            return to_string(key);
+        }
+    }
+
+    let low = before.first().map_or(0, |digit| digit_value(*digit));
+    let high = after.map_or(DIGITS.len(), |after| digit_value(after[0]));
+
+    if high - low > 1 {
+        return to_string(vec![DIGITS[(low + high + 1) / 2]]);
+    }
+
+    match after {
+        Some(after) if after.len() > 1 => to_string(after[..1].to_vec()),
+        _ => {
+            let mut key = vec![DIGITS[low]];
+            key.extend(midpoint(before.get(1..).unwrap_or_default(), None).into_bytes());
+            to_string(key)
+        }
+    }
+}
+
+fn encode(mut value: u128, width: u32) -> String {
+    let base = DIGITS.len() as u128;
+    let mut key = vec![DIGITS[0]; width as usize];
+
+    for digit in key.iter_mut().rev() {
+        *digit = DIGITS[(value % base) as usize];
+        value /= base;
+    }
+
+    while key.last() == Some(&DIGITS[0]) {
+        key.pop();
+    }
+
+    to_string(key)
+}
+
+fn digit_value(digit: u8) -> usize {
+    DIGITS.iter().position(|d| *d == digit).unwrap_or(0)
+}
+
+fn to_string(key: Vec<u8>) -> String {
+    // Every byte comes from DIGITS, which is ASCII.
+    String::from_utf8(key).expect("position digits are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn keys_fall_between_bounds() {
+        let first = after(None);
+        let last = after(Some(&first));
+        let middle = between(Some(&first), Some(&last)).unwrap();
+        let start = between(None, Some(&first)).unwrap();
+
+        assert!(start < first && first < middle && middle < last);
+        assert_eq!(between(Some(&last), Some(&first)), None);
+        assert_eq!(between(Some(&first), Some(&first)), None);
+        assert_eq!(between(None, Some("")), None);
+
+        let mut low = first.clone();
+
+        for _ in 0..100 {
+            let key = between(Some(&low), Some(&middle)).unwrap();
+            assert!(low < key && key < middle && !key.ends_with('0'));
+            low = key;
+        }
+
+        assert!(after(Some("zz")) > "zz".to_string());
+    }
+
+    #[test]
+    fn spread_orders_keys() {
+        let keys = spread(100);
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        assert_eq!(keys.len(), 100);
+        assert_eq!(keys, sorted);
+        assert!(keys.iter().all(|key| key.len() <= 2));
+    }
+}
@@ -1,3 +1,5 @@
+use super::super::database;
+use sqlx::Error as SqlxError;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -5,8 +7,55 @@ use uuid::Uuid;
 pub enum Error {
     #[error("'{0}' already in collection")]
     Conflict(Uuid),
+    #[error("name '{0}' already in use")]
+    NameConflict(String),
     #[error("'{0}' not found in collection")]
     NotFound(Uuid),
-    #[error("{0}")]
-    Validation(String),
+    #[error("{1}")]
+    Validation(&'static str, String),
+    #[error("'{0}' does not match expected version {1}")]
+    PreconditionFailed(Uuid, i64),
+    #[error("{}", describe_violations(.0))]
+    InvalidItems(Vec<ItemViolation>),
+}
+
+// Batch requests check every item before writing any, so a rejected batch reports each bad item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemViolation {
+    pub index: usize,
+    pub field: &'static str,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Conflict,
+    NotFound,
+    Validation,
+    PreconditionFailed,
+    Unavailable,
+    Unknown,
+}
+
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    match error.downcast_ref::<Error>() {
+        Some(Error::Conflict(_)) | Some(Error::NameConflict(_)) => return ErrorKind::Conflict,
+        Some(Error::NotFound(_)) => return ErrorKind::NotFound,
+        Some(Error::Validation(..)) | Some(Error::InvalidItems(_)) => return ErrorKind::Validation,
+        Some(Error::PreconditionFailed(..)) => return ErrorKind::PreconditionFailed,
+        None => {}
+    };
+
+    match error.downcast_ref::<SqlxError>() {
+        Some(err) if database::is_unavailable(err) => return ErrorKind::Unavailable,
+        _ => return ErrorKind::Unknown,
+    };
+}
+
+fn describe_violations(violations: &[ItemViolation]) -> String {
+    violations
+        .iter()
+        .map(|violation| format!("item {}: {}", violation.index, violation.description))
+        .collect::<Vec<String>>()
+        .join("; ")
 }
@@ -1,29 +1,26 @@
-use anyhow::Result;
-use sqlx::PgPool;
+use super::common::model::Error as ModelError;
+use anyhow::{Error, Result};
+use sqlx::{
+    pool::PoolConnection, Error as SqlxError, Executor, PgPool, SqliteConnection, SqlitePool,
+};
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-
-const CREATE_LISTS_TABLE: &str = "
-  CREATE TABLE IF NOT EXISTS lists (
-    id CHAR(36) PRIMARY KEY NOT NULL,
-    name TEXT NOT NULL
-  );
-";
-
-const CREATE_TODOS_TABLE: &str = "
-  CREATE TABLE IF NOT EXISTS todos (
-    id CHAR(36) PRIMARY KEY NOT NULL,
-    list_id CHAR(36) NOT NULL REFERENCES lists ON DELETE CASCADE,
-    description TEXT NOT NULL,
-    done BOOLEAN NOT NULL,
-    FOREIGN KEY (list_id) REFERENCES lists (id)
-  );
-";
+use uuid::Uuid;
 
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
 pub mod ErrorCode {
     pub const ForeignKeyViolation: &str = "23503";
     pub const UniqueViolation: &str = "23505";
+    pub const CannotConnectNow: &str = "57P03";
+}
+
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod SqliteErrorCode {
+    pub const ForeignKeyViolation: &str = "787";
+    pub const PrimaryKeyViolation: &str = "1555";
+    pub const UniqueViolation: &str = "2067";
 }
 
 pub async fn create_pool(database_url: &str) -> Result<Arc<PgPool>> {
@@ -32,8 +29,193 @@ pub async fn create_pool(database_url: &str) -> Result<Arc<PgPool>> {
     ))
 }
 
-pub async fn create_schema(pool: &PgPool) -> Result<()> {
-    sqlx::query(CREATE_LISTS_TABLE).execute(pool).await?;
-    sqlx::query(CREATE_TODOS_TABLE).execute(pool).await?;
-    Ok(())
+pub async fn create_sqlite_pool(database_url: &str) -> Result<Arc<SqlitePool>> {
+    // Every connection to an in-memory database opens a separate database, so the pool must
+    // never hand out more than one.
+    let max_size = if database_url.contains(":memory:") {
+        1
+    } else {
+        10
+    };
+
+    Ok(Arc::new(
+        SqlitePool::builder()
+            .min_size(1)
+            .max_size(max_size)
+            .build(&database_url)
+            .await?,
+    ))
+}
+
+// sqlx only opens deferred transactions, and SQLite fails a deferred transaction that later writes
+// with SQLITE_BUSY instead of waiting for the lock. Dropping one unfinished rolls it back.
+pub struct ImmediateTransaction {
+    conn: Option<PoolConnection<SqliteConnection>>,
+}
+
+impl ImmediateTransaction {
+    pub async fn begin(mut conn: PoolConnection<SqliteConnection>) -> Result<ImmediateTransaction> {
+        conn.execute("BEGIN IMMEDIATE").await?;
+        Ok(ImmediateTransaction { conn: Some(conn) })
+    }
+
+    pub async fn commit(mut self) -> Result<PoolConnection<SqliteConnection>> {
+        self.execute("COMMIT").await?;
+        Ok(self.conn.take().unwrap())
+    }
+}
+
+impl Deref for ImmediateTransaction {
+    type Target = PoolConnection<SqliteConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for ImmediateTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Drop for ImmediateTransaction {
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            tokio::spawn(async move {
+                let _ = conn.execute("ROLLBACK").await;
+            });
+        }
+    }
+}
+
+pub fn create_not_found_error(error: SqlxError, id: &Uuid) -> Error {
+    match error {
+        SqlxError::RowNotFound => return Error::new(ModelError::NotFound(id.to_owned())),
+        _ => return Error::new(error),
+    }
+}
+
+pub fn extract_database_error_code(error: &SqlxError) -> std::result::Result<String, &SqlxError> {
+    let database_error = match error {
+        SqlxError::Database(err) => err,
+        _ => return Err(error),
+    };
+
+    match database_error.code() {
+        None => return Err(error),
+        Some(code) => return Ok(code.to_string()),
+    };
+}
+
+// Both backends' codes are matched, so the model helpers built on these serve either one.
+pub fn is_foreign_key_violation(error: &SqlxError) -> bool {
+    match extract_database_error_code(error) {
+        Ok(code) => {
+            return code == ErrorCode::ForeignKeyViolation
+                || code == SqliteErrorCode::ForeignKeyViolation
+        }
+        Err(_) => return false,
+    };
+}
+
+pub fn is_unique_violation(error: &SqlxError) -> bool {
+    match extract_database_error_code(error) {
+        Ok(code) => {
+            return code == ErrorCode::UniqueViolation
+                || code == SqliteErrorCode::UniqueViolation
+                || code == SqliteErrorCode::PrimaryKeyViolation
+        }
+        Err(_) => return false,
+    };
+}
+
+pub fn is_unavailable(error: &SqlxError) -> bool {
+    match error {
+        SqlxError::Io(_) | SqlxError::PoolTimedOut(..) | SqlxError::PoolClosed => return true,
+        _ => {}
+    };
+
+    // Class 08 covers connection exceptions; the server also refuses connections while starting up
+    // or shutting down.
+    match extract_database_error_code(error) {
+        Ok(code) => return code.starts_with("08") || code == ErrorCode::CannotConnectNow,
+        Err(_) => return false,
+    };
+}
+
+// Older SQLite builds accept at most 999 parameters per statement, so multi-row inserts are split
+// into chunks of this many rows.
+pub const MAX_INSERT_ROWS: usize = 100;
+
+// Columns always come from the model rather than the request, so they are safe to interpolate.
+// The row ID is bound first, each column's value follows in order, and the expected version, when
+// checked, comes last. `now` is the backend's SQL expression for the current time.
+pub fn create_update_statement(
+    table: &str,
+    columns: &[&str],
+    parameter: &str,
+    now: &str,
+    check_version: bool,
+) -> String {
+    let mut assignments = Vec::new();
+
+    for (index, column) in columns.iter().enumerate() {
+        assignments.push(format!("{} = {}{}", column, parameter, index + 2));
+
+        // A todo keeps the time it was first completed until it is reopened.
+        if *column == "done" {
+            assignments.push(format!(
+                "completed_at = CASE WHEN {}{} THEN COALESCE(completed_at, {}) ELSE NULL END",
+                parameter,
+                index + 2,
+                now
+            ));
+        }
+    }
+
+    assignments.push(format!("updated_at = {}", now));
+    assignments.push("version = version + 1".to_string());
+
+    let mut statement = format!(
+        "UPDATE {} SET {} WHERE id = {}1",
+        table,
+        assignments.join(", "),
+        parameter
+    );
+
+    if check_version {
+        statement.push_str(&format!(
+            " AND version = {}{}",
+            parameter,
+            columns.len() + 2
+        ));
+    }
+
+    statement
+}
+
+// Parameters are numbered row by row, so each row binds its columns in order before the next row.
+pub fn create_insert_statement(
+    table: &str,
+    columns: &[&str],
+    rows: usize,
+    parameter: &str,
+) -> String {
+    let values = (0..rows)
+        .map(|row| {
+            let parameters = (1..=columns.len())
+                .map(|column| format!("{}{}", parameter, row * columns.len() + column))
+                .collect::<Vec<String>>();
+
+            format!("({})", parameters.join(", "))
+        })
+        .collect::<Vec<String>>();
+
+    format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        table,
+        columns.join(", "),
+        values.join(", ")
+    )
 }
@@ -0,0 +1,465 @@
+use super::database::ImmediateTransaction;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgQueryAs;
+use sqlx::sqlite::SqliteQueryAs;
+use sqlx::{pool::PoolConnection, Executor, PgConnection, PgPool, Transaction};
+use sqlx::{SqliteConnection, SqlitePool};
+
+const MIGRATION_LOCK_KEY: i64 = 4_201_220_623;
+
+const CREATE_MIGRATIONS_TABLE: &str = "
+  CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    checksum CHAR(64) NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+  );
+";
+
+const SELECT_MIGRATIONS: &str = "
+  SELECT version, name, checksum FROM schema_migrations
+  ORDER BY version;
+";
+
+const INSERT_MIGRATION: &str = "
+  INSERT INTO schema_migrations (version, name, checksum)
+  VALUES ($1, $2, $3);
+";
+
+const DELETE_MIGRATION: &str = "
+  DELETE FROM schema_migrations
+  WHERE version = $1;
+";
+
+const ACQUIRE_LOCK: &str = "SELECT pg_advisory_xact_lock($1);";
+
+const CREATE_SQLITE_MIGRATIONS_TABLE: &str = "
+  CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+  );
+";
+
+const INSERT_SQLITE_MIGRATION: &str = "
+  INSERT INTO schema_migrations (version, name, checksum)
+  VALUES (?1, ?2, ?3);
+";
+
+const DELETE_SQLITE_MIGRATION: &str = "
+  DELETE FROM schema_migrations
+  WHERE version = ?1;
+";
+
+const DISABLE_FOREIGN_KEYS: &str = "PRAGMA foreign_keys = OFF;";
+
+const CHECK_FOREIGN_KEYS: &str = "PRAGMA foreign_key_check;";
+
+type MigrationRow = (i64, String, String);
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: &'static str,
+    down: &'static str,
+    sqlite_up: &'static str,
+    sqlite_down: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.up.as_bytes()))
+    }
+
+    pub fn sqlite_checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sqlite_up.as_bytes()))
+    }
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr, $file:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $file, ".up.sql")),
+            down: include_str!(concat!("../../migrations/", $file, ".down.sql")),
+            sqlite_up: include_str!(concat!("../../migrations/sqlite/", $file, ".up.sql")),
+            sqlite_down: include_str!(concat!("../../migrations/sqlite/", $file, ".down.sql")),
+        }
+    };
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "create_lists", "0001_create_lists"),
+    migration!(2, "create_todos", "0002_create_todos"),
+    migration!(3, "add_lists_created_at", "0003_add_lists_created_at"),
+    migration!(4, "use_uuid_ids", "0004_use_uuid_ids"),
+    migration!(5, "create_idempotency_keys", "0005_create_idempotency_keys"),
+    migration!(6, "add_versions", "0006_add_versions"),
+    migration!(7, "add_timestamps", "0007_add_timestamps"),
+    migration!(8, "add_todos_due_at", "0008_add_todos_due_at"),
+    migration!(9, "create_tags", "0009_create_tags"),
+    migration!(10, "add_todos_parent_id", "0010_add_todos_parent_id"),
+    migration!(11, "add_todos_position", "0011_add_todos_position"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum State {
+    Applied,
+    Pending,
+    Modified,
+}
+
+#[derive(Debug)]
+pub struct Status {
+    pub version: i64,
+    pub name: &'static str,
+    pub state: State,
+}
+
+pub async fn up(pool: &PgPool) -> Result<Vec<i64>> {
+    let guard = lock(pool).await?;
+    let versions = apply_pending(pool).await?;
+    guard.commit().await?;
+    Ok(versions)
+}
+
+pub async fn down(pool: &PgPool) -> Result<Option<i64>> {
+    let guard = lock(pool).await?;
+    let version = revert_latest(pool).await?;
+    guard.commit().await?;
+    Ok(version)
+}
+
+pub async fn status(pool: &PgPool) -> Result<Vec<Status>> {
+    let guard = lock(pool).await?;
+    let applied = select_applied(pool).await?;
+    guard.commit().await?;
+
+    Ok(create_statuses(&applied, Migration::checksum))
+}
+
+pub async fn up_sqlite(pool: &SqlitePool) -> Result<Vec<i64>> {
+    let mut tx = lock_sqlite(pool).await?;
+    let versions = apply_pending_sqlite(&mut tx).await?;
+    check_foreign_keys(&mut tx).await?;
+    tx.commit().await?;
+    Ok(versions)
+}
+
+pub async fn down_sqlite(pool: &SqlitePool) -> Result<Option<i64>> {
+    let mut tx = lock_sqlite(pool).await?;
+    let version = revert_latest_sqlite(&mut tx).await?;
+    check_foreign_keys(&mut tx).await?;
+    tx.commit().await?;
+    Ok(version)
+}
+
+pub async fn status_sqlite(pool: &SqlitePool) -> Result<Vec<Status>> {
+    let mut tx = lock_sqlite(pool).await?;
+    let applied = select_applied_sqlite(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(create_statuses(&applied, Migration::sqlite_checksum))
+}
+
+fn create_statuses(applied: &[MigrationRow], checksum: fn(&Migration) -> String) -> Vec<Status> {
+    MIGRATIONS
+        .iter()
+        .map(|migration| {
+            let state = match applied.iter().find(|row| row.0 == migration.version) {
+                None => State::Pending,
+                Some(row) if row.2 == checksum(migration) => State::Applied,
+                Some(_) => State::Modified,
+            };
+
+            Status {
+                version: migration.version,
+                name: migration.name,
+                state,
+            }
+        })
+        .collect()
+}
+
+fn select_pending(
+    applied: &[MigrationRow],
+    checksum: fn(&Migration) -> String,
+) -> Result<Vec<&'static Migration>> {
+    let mut pending = Vec::new();
+
+    for migration in MIGRATIONS {
+        match applied.iter().find(|row| row.0 == migration.version) {
+            Some(row) if row.2 != checksum(migration) => {
+                return Err(anyhow!(
+                    "migration {} ({}) was modified after it was applied",
+                    migration.version,
+                    migration.name
+                ))
+            }
+            Some(_) => continue,
+            None => pending.push(migration),
+        };
+    }
+
+    Ok(pending)
+}
+
+fn select_latest(applied: &[MigrationRow]) -> Result<Option<&'static Migration>> {
+    let version = match applied.last() {
+        None => return Ok(None),
+        Some(row) => row.0,
+    };
+
+    match MIGRATIONS.iter().find(|m| m.version == version) {
+        None => return Err(anyhow!("migration {} is not known to this build", version)),
+        Some(migration) => return Ok(Some(migration)),
+    };
+}
+
+async fn lock(pool: &PgPool) -> Result<Transaction<PoolConnection<PgConnection>>> {
+    let mut guard = pool.begin().await?;
+
+    // The lock belongs to the guard's transaction, so it is released however the guard ends,
+    // including when it is dropped partway through a run.
+    sqlx::query(ACQUIRE_LOCK)
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut guard)
+        .await?;
+
+    // Concurrent runners would otherwise race each other to create the table.
+    let mut conn = pool.acquire().await?;
+    conn.execute(CREATE_MIGRATIONS_TABLE).await?;
+
+    Ok(guard)
+}
+
+async fn select_applied(pool: &PgPool) -> Result<Vec<MigrationRow>> {
+    let rows = sqlx::query_as::<_, MigrationRow>(SELECT_MIGRATIONS)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+async fn apply_pending(pool: &PgPool) -> Result<Vec<i64>> {
+    let applied = select_applied(pool).await?;
+    let mut versions = Vec::new();
+
+    for migration in select_pending(&applied, Migration::checksum)? {
+        log::info!(
+            "applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+
+        let mut tx = pool.begin().await?;
+        apply(&mut tx, migration).await?;
+        tx.commit().await?;
+
+        versions.push(migration.version);
+    }
+
+    Ok(versions)
+}
+
+async fn apply(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    migration: &Migration,
+) -> Result<()> {
+    tx.execute(migration.up).await?;
+
+    sqlx::query(INSERT_MIGRATION)
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn revert_latest(pool: &PgPool) -> Result<Option<i64>> {
+    let applied = select_applied(pool).await?;
+
+    let migration = match select_latest(&applied)? {
+        None => return Ok(None),
+        Some(migration) => migration,
+    };
+
+    log::info!(
+        "reverting migration {} ({})",
+        migration.version,
+        migration.name
+    );
+
+    let mut tx = pool.begin().await?;
+    revert(&mut tx, migration).await?;
+    tx.commit().await?;
+
+    Ok(Some(migration.version))
+}
+
+async fn revert(
+    tx: &mut Transaction<PoolConnection<PgConnection>>,
+    migration: &Migration,
+) -> Result<()> {
+    tx.execute(migration.down).await?;
+
+    sqlx::query(DELETE_MIGRATION)
+        .bind(migration.version)
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+// SQLite has no advisory locks, so a run holds the write lock for one transaction instead. Foreign
+// keys stay off until it commits, since rebuilding a table drops the old one and would cascade.
+async fn lock_sqlite(pool: &SqlitePool) -> Result<ImmediateTransaction> {
+    let mut conn = pool.acquire().await?;
+    conn.execute(DISABLE_FOREIGN_KEYS).await?;
+
+    let mut tx = ImmediateTransaction::begin(conn).await?;
+    tx.execute(CREATE_SQLITE_MIGRATIONS_TABLE).await?;
+
+    Ok(tx)
+}
+
+async fn select_applied_sqlite(
+    conn: &mut PoolConnection<SqliteConnection>,
+) -> Result<Vec<MigrationRow>> {
+    let rows = sqlx::query_as::<_, MigrationRow>(SELECT_MIGRATIONS)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(rows)
+}
+
+async fn apply_pending_sqlite(conn: &mut PoolConnection<SqliteConnection>) -> Result<Vec<i64>> {
+    let applied = select_applied_sqlite(conn).await?;
+    let mut versions = Vec::new();
+
+    for migration in select_pending(&applied, Migration::sqlite_checksum)? {
+        log::info!(
+            "applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+
+        execute_script(conn, migration.sqlite_up).await?;
+
+        sqlx::query(INSERT_SQLITE_MIGRATION)
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.sqlite_checksum())
+            .execute(&mut *conn)
+            .await?;
+
+        versions.push(migration.version);
+    }
+
+    Ok(versions)
+}
+
+async fn revert_latest_sqlite(conn: &mut PoolConnection<SqliteConnection>) -> Result<Option<i64>> {
+    let applied = select_applied_sqlite(conn).await?;
+
+    let migration = match select_latest(&applied)? {
+        None => return Ok(None),
+        Some(migration) => migration,
+    };
+
+    log::info!(
+        "reverting migration {} ({})",
+        migration.version,
+        migration.name
+    );
+
+    execute_script(conn, migration.sqlite_down).await?;
+
+    sqlx::query(DELETE_SQLITE_MIGRATION)
+        .bind(migration.version)
+        .execute(conn)
+        .await?;
+
+    Ok(Some(migration.version))
+}
+
+// SQLite prepares one statement at a time, so a script is split on its terminators.
+async fn execute_script(conn: &mut PoolConnection<SqliteConnection>, script: &str) -> Result<()> {
+    for statement in script.split(';').map(str::trim) {
+        if !statement.is_empty() {
+            conn.execute(statement).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_foreign_keys(conn: &mut PoolConnection<SqliteConnection>) -> Result<()> {
+    let violations = sqlx::query_as::<_, (String,)>(CHECK_FOREIGN_KEYS)
+        .fetch_all(conn)
+        .await?;
+
+    match violations.first() {
+        Some((table,)) => return Err(anyhow!("migration left a broken foreign key in {}", table)),
+        None => return Ok(()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::database;
+    use super::*;
+    use dotenv::dotenv;
+    use pretty_assertions::assert_eq;
+    use std::env;
+
+    #[test]
+    fn versions_are_ordered() {
+        let versions = MIGRATIONS.iter().map(|m| m.version).collect::<Vec<i64>>();
+        let mut sorted = versions.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        assert_eq!(versions, sorted);
+    }
+
+    #[tokio::test]
+    async fn up_is_idempotent() -> Result<()> {
+        dotenv().ok();
+        let pool = database::create_pool(&env::var("DATABASE_URL")?).await?;
+
+        up(&pool).await?;
+        let applied = up(&pool).await?;
+
+        assert!(applied.is_empty());
+
+        let statuses = status(&pool).await?;
+        assert!(statuses.iter().all(|s| s.state == State::Applied));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sqlite_migrations_revert_and_reapply() -> Result<()> {
+        let pool = database::create_sqlite_pool("sqlite::memory:").await?;
+
+        let applied = up_sqlite(&pool).await?;
+        assert_eq!(applied.len(), MIGRATIONS.len());
+
+        while down_sqlite(&pool).await?.is_some() {}
+
+        let statuses = status_sqlite(&pool).await?;
+        assert!(statuses.iter().all(|s| s.state == State::Pending));
+
+        up_sqlite(&pool).await?;
+        assert!(up_sqlite(&pool).await?.is_empty());
+
+        Ok(())
+    }
+}
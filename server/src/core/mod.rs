@@ -1,3 +1,4 @@
 pub mod checklist;
 pub mod common;
 pub mod database;
+pub mod migration;
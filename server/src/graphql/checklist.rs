@@ -1,22 +1,312 @@
-use juniper::{FieldResult, GraphQLInputObject, GraphQLObject};
+use super::super::core::checklist::service::Service;
+use super::super::core::checklist::{event, model};
+use super::super::core::common::service::{self, ErrorKind};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use dataloader::non_cached::Loader;
+use dataloader::BatchFn;
+use futures::{future, Stream, StreamExt};
+use juniper::{graphql_value, FieldError, FieldResult};
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLObject};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast::RecvError;
+use uuid::Uuid;
 
-#[derive(Debug)]
-pub struct Context {}
+type ListChangeStream = Pin<Box<dyn Stream<Item = Result<ListChange, FieldError>> + Send>>;
+
+type TodoChangeStream = Pin<Box<dyn Stream<Item = Result<TodoChange, FieldError>> + Send>>;
+
+pub type TodosLoader = Loader<Uuid, Result<Vec<model::Todo>, String>, TodosBatcher>;
+
+pub struct TodosBatcher {
+    service: Arc<Service>,
+}
+
+#[async_trait]
+impl BatchFn<Uuid, Result<Vec<model::Todo>, String>> for TodosBatcher {
+    async fn load(&self, keys: &[Uuid]) -> HashMap<Uuid, Result<Vec<model::Todo>, String>> {
+        let mut todos_by_list = keys
+            .iter()
+            .map(|key| (*key, Ok(Vec::new())))
+            .collect::<HashMap<Uuid, Result<Vec<model::Todo>, String>>>();
+
+        let todos = match self.service.get_todos_for_lists(keys).await {
+            Ok(todos) => todos,
+            Err(err) => {
+                log::error!("{:?}", err);
+
+                for value in todos_by_list.values_mut() {
+                    *value = Err("Unknown Error".to_string());
+                }
+
+                return todos_by_list;
+            }
+        };
+
+        for todo in todos {
+            if let Some(Ok(list_todos)) = todos_by_list.get_mut(&todo.list_id) {
+                list_todos.push(todo);
+            }
+        }
+
+        todos_by_list
+    }
+}
+
+pub struct Context {
+    pub service: Arc<Service>,
+    pub todos_loader: TodosLoader,
+}
+
+impl Context {
+    pub fn new(service: Arc<Service>) -> Context {
+        let todos_loader = Loader::new(TodosBatcher {
+            service: service.clone(),
+        });
+
+        Context {
+            service,
+            todos_loader,
+        }
+    }
+}
 
 impl juniper::Context for Context {}
 
+#[derive(Debug)]
+pub struct TodoList {
+    pub id: Uuid,
+    pub name: String,
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[juniper::graphql_object(Context = Context, description = "A named list of todos")]
+impl TodoList {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    async fn todos(&self, context: &Context) -> FieldResult<Vec<Todo>> {
+        let result = context.todos_loader.load(self.id).await;
+
+        match result {
+            Ok(todos) => return Ok(todos.into_iter().map(Todo::from).collect()),
+            Err(msg) => return Err(FieldError::new(msg, graphql_value!({ "code": "INTERNAL" }))),
+        };
+    }
+}
+
 #[derive(GraphQLObject, Debug)]
 #[graphql(description = "A todo entry")]
 pub struct Todo {
-    pub id: String,
+    pub list_id: Uuid,
+    pub id: Uuid,
+    pub description: String,
+    pub done: bool,
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub parent_id: Option<Uuid>,
+    pub position: String,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "A todo with its nested subtasks")]
+pub struct TodoTree {
+    pub todo: Todo,
+    pub subtasks: Vec<TodoTree>,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "A label that can be attached to todos")]
+pub struct Tag {
+    pub id: Uuid,
     pub name: String,
 }
 
+#[derive(GraphQLObject, Debug)]
+#[graphql(Context = Context, description = "A page of lists")]
+pub struct TodoListPage {
+    pub lists: Vec<TodoList>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "A page of todos")]
+pub struct TodoPage {
+    pub todos: Vec<Todo>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(Context = Context, description = "A change to a list")]
+pub struct ListChange {
+    pub kind: ChangeKind,
+    pub list_id: Uuid,
+    pub list: Option<TodoList>,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "A change to a todo")]
+pub struct TodoChange {
+    pub kind: ChangeKind,
+    pub list_id: Uuid,
+    pub todo_id: Uuid,
+    pub todo: Option<Todo>,
+}
+
+#[derive(GraphQLEnum, Debug, Clone, Copy)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Removed,
+}
+
+#[derive(GraphQLEnum, Debug, Clone, Copy)]
+pub enum ListOrder {
+    Name,
+    CreatedAt,
+}
+
+#[derive(GraphQLEnum, Debug, Clone, Copy)]
+pub enum DueWindow {
+    Overdue,
+    Today,
+    ThisWeek,
+}
+
+#[derive(GraphQLEnum, Debug, Clone, Copy)]
+pub enum TodoOrder {
+    Id,
+    Description,
+    Position,
+}
+
 #[derive(GraphQLInputObject, Debug)]
-#[graphql(description = "A todo entry")]
+#[graphql(description = "A todo to add in a batch")]
 pub struct NewTodo {
-    pub id: String,
-    pub name: String,
+    pub id: Option<Uuid>,
+    pub description: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub parent_id: Option<Uuid>,
+}
+
+impl From<model::TodoList> for TodoList {
+    fn from(list: model::TodoList) -> TodoList {
+        TodoList {
+            id: list.id,
+            name: list.name,
+            version: list.version.to_string(),
+            created_at: list.created_at,
+            updated_at: list.updated_at,
+        }
+    }
+}
+
+impl From<model::Todo> for Todo {
+    fn from(todo: model::Todo) -> Todo {
+        Todo {
+            list_id: todo.list_id,
+            id: todo.id,
+            description: todo.description,
+            done: todo.done,
+            version: todo.version.to_string(),
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            completed_at: todo.completed_at,
+            due_at: match todo.due {
+                Some(model::Due::Time(time)) => Some(time),
+                _ => None,
+            },
+            due_date: match todo.due {
+                Some(model::Due::Date(date)) => Some(date),
+                _ => None,
+            },
+            parent_id: todo.parent_id,
+            position: todo.position,
+        }
+    }
+}
+
+impl From<model::TodoTree> for TodoTree {
+    fn from(tree: model::TodoTree) -> TodoTree {
+        TodoTree {
+            todo: tree.todo.into(),
+            subtasks: tree.subtasks.into_iter().map(TodoTree::from).collect(),
+        }
+    }
+}
+
+impl From<model::Tag> for Tag {
+    fn from(tag: model::Tag) -> Tag {
+        Tag {
+            id: tag.id,
+            name: tag.name,
+        }
+    }
+}
+
+impl From<event::ChangeKind> for ChangeKind {
+    fn from(kind: event::ChangeKind) -> ChangeKind {
+        match kind {
+            event::ChangeKind::Created => ChangeKind::Created,
+            event::ChangeKind::Updated => ChangeKind::Updated,
+            event::ChangeKind::Removed => ChangeKind::Removed,
+        }
+    }
+}
+
+impl From<ListOrder> for model::ListOrder {
+    fn from(order: ListOrder) -> model::ListOrder {
+        match order {
+            ListOrder::Name => model::ListOrder::Name,
+            ListOrder::CreatedAt => model::ListOrder::CreatedAt,
+        }
+    }
+}
+
+impl From<DueWindow> for model::DueWindow {
+    fn from(window: DueWindow) -> model::DueWindow {
+        match window {
+            DueWindow::Overdue => model::DueWindow::Overdue,
+            DueWindow::Today => model::DueWindow::Today,
+            DueWindow::ThisWeek => model::DueWindow::ThisWeek,
+        }
+    }
+}
+
+impl From<TodoOrder> for model::TodoOrder {
+    fn from(order: TodoOrder) -> model::TodoOrder {
+        match order {
+            TodoOrder::Id => model::TodoOrder::Id,
+            TodoOrder::Description => model::TodoOrder::Description,
+            TodoOrder::Position => model::TodoOrder::Position,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -24,12 +314,107 @@ pub struct Query;
 
 #[juniper::graphql_object(Context = Context)]
 impl Query {
-    async fn todo(_context: &Context, id: String) -> FieldResult<Todo> {
-        Ok(Todo {
-            id,
-            name: "test".to_string(),
+    async fn list(context: &Context, id: Uuid) -> FieldResult<TodoList> {
+        let result = context.service.get_list(&id).await;
+        result.map(TodoList::from).map_err(convert_error)
+    }
+
+    async fn lists(
+        context: &Context,
+        page_size: Option<i32>,
+        page_token: Option<String>,
+        name_prefix: Option<String>,
+        name_contains: Option<String>,
+        order_by: Option<ListOrder>,
+    ) -> FieldResult<TodoListPage> {
+        let order = order_by.unwrap_or(ListOrder::Name);
+
+        let result = context
+            .service
+            .list_lists(
+                page_size.unwrap_or_default(),
+                &page_token.as_deref(),
+                &name_prefix.as_deref(),
+                &name_contains.as_deref(),
+                order.into(),
+            )
+            .await;
+
+        let page = result.map_err(convert_error)?;
+
+        Ok(TodoListPage {
+            lists: page.items.into_iter().map(TodoList::from).collect(),
+            next_page_token: page.next_page_token,
         })
     }
+
+    async fn todo(context: &Context, id: Uuid) -> FieldResult<Todo> {
+        let result = context.service.get_todo(&id).await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn todo_tree(context: &Context, id: Uuid) -> FieldResult<TodoTree> {
+        let result = context.service.get_todo_tree(&id).await;
+        result.map(TodoTree::from).map_err(convert_error)
+    }
+
+    async fn todos(
+        context: &Context,
+        list_id: Uuid,
+        page_size: Option<i32>,
+        page_token: Option<String>,
+        done: Option<bool>,
+        tag_ids: Option<Vec<Uuid>>,
+        order_by: Option<TodoOrder>,
+    ) -> FieldResult<TodoPage> {
+        let order = order_by.unwrap_or(TodoOrder::Id);
+
+        let result = context
+            .service
+            .list_todos(
+                &list_id,
+                page_size.unwrap_or_default(),
+                &page_token.as_deref(),
+                &done,
+                &tag_ids.unwrap_or_default(),
+                order.into(),
+            )
+            .await;
+
+        let page = result.map_err(convert_error)?;
+
+        Ok(TodoPage {
+            todos: page.items.into_iter().map(Todo::from).collect(),
+            next_page_token: page.next_page_token,
+        })
+    }
+
+    async fn due_todos(
+        context: &Context,
+        window: DueWindow,
+        time_zone: Option<String>,
+    ) -> FieldResult<Vec<Todo>> {
+        let result = context
+            .service
+            .list_due_todos(window.into(), &time_zone.as_deref())
+            .await;
+
+        let todos = result.map_err(convert_error)?;
+
+        Ok(todos.into_iter().map(Todo::from).collect())
+    }
+
+    async fn tags(context: &Context) -> FieldResult<Vec<Tag>> {
+        let tags = context.service.list_tags().await.map_err(convert_error)?;
+        Ok(tags.into_iter().map(Tag::from).collect())
+    }
+
+    async fn todo_tags(context: &Context, todo_id: Uuid) -> FieldResult<Vec<Tag>> {
+        let result = context.service.list_todo_tags(&todo_id).await;
+        let tags = result.map_err(convert_error)?;
+
+        Ok(tags.into_iter().map(Tag::from).collect())
+    }
 }
 
 #[derive(Debug)]
@@ -37,7 +422,424 @@ pub struct Mutation;
 
 #[juniper::graphql_object(Context = Context)]
 impl Mutation {
-    async fn add_todo(_context: &Context, id: String, name: String) -> FieldResult<Todo> {
-        Ok(Todo { id, name })
+    async fn add_list(
+        context: &Context,
+        name: String,
+        id: Option<Uuid>,
+        idempotency_key: Option<String>,
+    ) -> FieldResult<TodoList> {
+        let result = context
+            .service
+            .add_list(&id.as_ref(), &name, &idempotency_key.as_deref())
+            .await;
+        result.map(TodoList::from).map_err(convert_error)
+    }
+
+    async fn update_list(
+        context: &Context,
+        id: Uuid,
+        name: Option<String>,
+        expected_version: Option<String>,
+    ) -> FieldResult<TodoList> {
+        let update = model::ListUpdate { name };
+        let result = context
+            .service
+            .update_list(&id, &update, &parse_version(expected_version)?)
+            .await;
+        result.map(TodoList::from).map_err(convert_error)
+    }
+
+    async fn remove_list(
+        context: &Context,
+        id: Uuid,
+        expected_version: Option<String>,
+    ) -> FieldResult<bool> {
+        let result = context
+            .service
+            .remove_list(&id, &parse_version(expected_version)?)
+            .await;
+        result.map(|_| true).map_err(convert_error)
+    }
+
+    async fn add_todo(
+        context: &Context,
+        list_id: Uuid,
+        description: String,
+        id: Option<Uuid>,
+        idempotency_key: Option<String>,
+        due_at: Option<DateTime<Utc>>,
+        due_date: Option<NaiveDate>,
+        parent_id: Option<Uuid>,
+    ) -> FieldResult<Todo> {
+        let due = convert_due(due_at, due_date)?;
+        let result = context
+            .service
+            .add_todo(
+                &id.as_ref(),
+                &list_id,
+                &description,
+                &due,
+                &parent_id.as_ref(),
+                &idempotency_key.as_deref(),
+            )
+            .await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn add_todos(
+        context: &Context,
+        list_id: Uuid,
+        todos: Vec<NewTodo>,
+    ) -> FieldResult<Vec<Todo>> {
+        let todos = todos
+            .into_iter()
+            .map(convert_new_todo)
+            .collect::<FieldResult<Vec<model::NewTodo>>>()?;
+        let result = context.service.add_todos(&list_id, &todos).await;
+        result
+            .map(|todos| todos.into_iter().map(Todo::from).collect())
+            .map_err(convert_error)
+    }
+
+    async fn update_todo(
+        context: &Context,
+        id: Uuid,
+        description: Option<String>,
+        done: Option<bool>,
+        due_at: Option<DateTime<Utc>>,
+        due_date: Option<NaiveDate>,
+        clear_due: Option<bool>,
+        parent_id: Option<Uuid>,
+        clear_parent: Option<bool>,
+        expected_version: Option<String>,
+    ) -> FieldResult<Todo> {
+        let due = match clear_due {
+            Some(true) => Some(None),
+            _ => convert_due(due_at, due_date)?.map(Some),
+        };
+
+        let parent_id = match clear_parent {
+            Some(true) => Some(None),
+            _ => parent_id.map(Some),
+        };
+
+        let update = model::TodoUpdate {
+            description,
+            done,
+            due,
+            parent_id,
+        };
+        let result = context
+            .service
+            .update_todo(&id, &update, &parse_version(expected_version)?)
+            .await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn move_todo(
+        context: &Context,
+        id: Uuid,
+        before_id: Option<Uuid>,
+        after_id: Option<Uuid>,
+    ) -> FieldResult<Todo> {
+        let result = context
+            .service
+            .move_todo(&id, &after_id.as_ref(), &before_id.as_ref())
+            .await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn move_todo_to_list(context: &Context, id: Uuid, list_id: Uuid) -> FieldResult<Todo> {
+        let result = context.service.move_todo_to_list(&id, &list_id).await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn remove_todo(
+        context: &Context,
+        id: Uuid,
+        expected_version: Option<String>,
+    ) -> FieldResult<bool> {
+        let result = context
+            .service
+            .remove_todo(&id, &parse_version(expected_version)?)
+            .await;
+        result.map(|_| true).map_err(convert_error)
+    }
+
+    async fn complete_todo(
+        context: &Context,
+        id: Uuid,
+        done: bool,
+        cascade: Option<bool>,
+        expected_version: Option<String>,
+    ) -> FieldResult<Todo> {
+        let result = context
+            .service
+            .complete_todo(
+                &id,
+                done,
+                cascade.unwrap_or_default(),
+                &parse_version(expected_version)?,
+            )
+            .await;
+        result.map(Todo::from).map_err(convert_error)
+    }
+
+    async fn complete_all_todos(context: &Context, list_id: Uuid) -> FieldResult<Vec<Todo>> {
+        let result = context.service.complete_all_todos(&list_id).await;
+        result
+            .map(|todos| todos.into_iter().map(Todo::from).collect())
+            .map_err(convert_error)
+    }
+
+    async fn clear_completed_todos(context: &Context, list_id: Uuid) -> FieldResult<Vec<Uuid>> {
+        let result = context.service.clear_completed_todos(&list_id).await;
+        result.map_err(convert_error)
+    }
+
+    async fn add_tag(context: &Context, name: String) -> FieldResult<Tag> {
+        let result = context.service.add_tag(&name).await;
+        result.map(Tag::from).map_err(convert_error)
+    }
+
+    async fn rename_tag(context: &Context, id: Uuid, name: String) -> FieldResult<Tag> {
+        let result = context.service.rename_tag(&id, &name).await;
+        result.map(Tag::from).map_err(convert_error)
+    }
+
+    async fn remove_tag(context: &Context, id: Uuid) -> FieldResult<bool> {
+        let result = context.service.remove_tag(&id).await;
+        result.map(|_| true).map_err(convert_error)
+    }
+
+    async fn attach_tag(context: &Context, todo_id: Uuid, tag_id: Uuid) -> FieldResult<bool> {
+        let result = context.service.attach_tag(&todo_id, &tag_id).await;
+        result.map(|_| true).map_err(convert_error)
+    }
+
+    async fn detach_tag(context: &Context, todo_id: Uuid, tag_id: Uuid) -> FieldResult<bool> {
+        let result = context.service.detach_tag(&todo_id, &tag_id).await;
+        result.map(|_| true).map_err(convert_error)
+    }
+}
+
+#[derive(Debug)]
+pub struct Subscription;
+
+#[juniper::graphql_subscription(Context = Context)]
+impl Subscription {
+    async fn list_changed(context: &Context, id: Uuid) -> ListChangeStream {
+        let stream = context.service.subscribe().filter_map(move |event| {
+            let change = match event {
+                Ok(event::Event::List {
+                    kind,
+                    list_id,
+                    list,
+                }) if list_id == id => Some(Ok(ListChange {
+                    kind: kind.into(),
+                    list_id,
+                    list: list.map(TodoList::from),
+                })),
+                Err(RecvError::Lagged(skipped)) => Some(Err(create_lagged_error(skipped))),
+                _ => None,
+            };
+
+            future::ready(change)
+        });
+
+        end_after_error(stream)
+    }
+
+    async fn todo_changed(context: &Context, list_id: Uuid) -> TodoChangeStream {
+        let stream = context.service.subscribe().filter_map(move |event| {
+            let change = match event {
+                Ok(event::Event::Todo {
+                    kind,
+                    list_id: todo_list_id,
+                    todo_id,
+                    todo,
+                }) if todo_list_id == list_id => Some(Ok(TodoChange {
+                    kind: kind.into(),
+                    list_id,
+                    todo_id,
+                    todo: todo.map(Todo::from),
+                })),
+                Err(RecvError::Lagged(skipped)) => Some(Err(create_lagged_error(skipped))),
+                _ => None,
+            };
+
+            future::ready(change)
+        });
+
+        end_after_error(stream)
+    }
+}
+
+fn create_lagged_error(skipped: u64) -> FieldError {
+    FieldError::new(
+        format!("subscriber fell behind by {} events", skipped),
+        graphql_value!({ "code": "RESOURCE_EXHAUSTED" }),
+    )
+}
+
+// A subscriber that fell behind has missed changes, so its stream ends once it has been told.
+fn end_after_error<T, S>(stream: S) -> Pin<Box<dyn Stream<Item = FieldResult<T>> + Send>>
+where
+    T: Send + 'static,
+    S: Stream<Item = FieldResult<T>> + Send + 'static,
+{
+    let mut failed = false;
+
+    Box::pin(stream.take_while(move |change| {
+        let open = !failed;
+        failed = change.is_err();
+        future::ready(open)
+    }))
+}
+
+fn convert_error(error: anyhow::Error) -> FieldError {
+    let code = match service::error_kind(&error) {
+        ErrorKind::Conflict => "ALREADY_EXISTS",
+        ErrorKind::NotFound => "NOT_FOUND",
+        ErrorKind::Validation => "INVALID_ARGUMENT",
+        ErrorKind::PreconditionFailed => "FAILED_PRECONDITION",
+        ErrorKind::Unavailable => {
+            log::warn!("{:?}", error);
+            return FieldError::new(
+                "Service Unavailable",
+                graphql_value!({ "code": "UNAVAILABLE" }),
+            );
+        }
+        ErrorKind::Unknown => {
+            log::error!("{:?}", error);
+            return FieldError::new("Unknown Error", graphql_value!({ "code": "INTERNAL" }));
+        }
+    };
+
+    FieldError::new(error.to_string(), graphql_value!({ "code": code }))
+}
+
+fn convert_due(
+    due_at: Option<DateTime<Utc>>,
+    due_date: Option<NaiveDate>,
+) -> FieldResult<Option<model::Due>> {
+    match (due_at, due_date) {
+        (Some(_), Some(_)) => {
+            return Err(FieldError::new(
+                "only one of dueAt and dueDate may be set",
+                graphql_value!({ "code": "INVALID_ARGUMENT" }),
+            ))
+        }
+        (Some(time), None) => return Ok(Some(model::Due::Time(time))),
+        (None, Some(date)) => return Ok(Some(model::Due::Date(date))),
+        (None, None) => return Ok(None),
+    };
+}
+
+fn convert_new_todo(todo: NewTodo) -> FieldResult<model::NewTodo> {
+    Ok(model::NewTodo {
+        id: todo.id,
+        description: todo.description,
+        due: convert_due(todo.due_at, todo.due_date)?,
+        parent_id: todo.parent_id,
+    })
+}
+
+// GraphQL Int is only 32 bits wide, so versions travel as strings to keep all 64 bits.
+fn parse_version(version: Option<String>) -> FieldResult<Option<i64>> {
+    let version = match version {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+
+    match version.parse::<i64>() {
+        Ok(version) => return Ok(Some(version)),
+        Err(_) => {
+            return Err(FieldError::new(
+                format!("version '{}' is not an integer", version),
+                graphql_value!({ "code": "INVALID_ARGUMENT" }),
+            ))
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::core::checklist::memory::MemoryModel;
+    use super::*;
+    use anyhow::{anyhow, Result};
+    use juniper::{RootNode, Variables};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    type Schema = RootNode<'static, Query, Mutation, Subscription>;
+
+    fn create_context() -> Context {
+        Context::new(Service::new(Box::new(MemoryModel::new())))
+    }
+
+    async fn execute(context: &Context, document: &str) -> Result<Value> {
+        let schema = Schema::new(Query, Mutation, Subscription);
+        let (data, errors) = juniper::execute(document, None, &schema, &Variables::new(), context)
+            .await
+            .map_err(|err| anyhow!("{:?}", err))?;
+
+        Ok(json!({ "data": data, "errors": errors }))
+    }
+
+    #[tokio::test]
+    async fn update_list_with_expected_version() -> Result<()> {
+        let context = create_context();
+
+        let added = execute(
+            &context,
+            r#"mutation { addList(name: "new_list") { id version } }"#,
+        )
+        .await?;
+        let id = added["data"]["addList"]["id"].as_str().unwrap().to_string();
+        assert_eq!(added["data"]["addList"]["version"], json!("1"));
+
+        let document = format!(
+            r#"mutation {{ updateList(id: "{}", name: "renamed", expectedVersion: "1") {{ version }} }}"#,
+            id
+        );
+
+        let updated = execute(&context, &document).await?;
+        assert_eq!(updated["data"]["updateList"]["version"], json!("2"));
+
+        let stale = execute(&context, &document).await?;
+        assert_eq!(
+            stale["errors"][0]["extensions"]["code"],
+            json!("FAILED_PRECONDITION")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_todo_with_invalid_version() -> Result<()> {
+        let context = create_context();
+
+        let document = format!(
+            r#"mutation {{ removeTodo(id: "{}", expectedVersion: "first") }}"#,
+            Uuid::new_v4()
+        );
+
+        let removed = execute(&context, &document).await?;
+        assert_eq!(
+            removed["errors"][0]["extensions"]["code"],
+            json!("INVALID_ARGUMENT")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_version_beyond_int() {
+        assert_eq!(
+            parse_version(Some("4294967296".to_string())).ok(),
+            Some(Some(4_294_967_296))
+        );
+        assert_eq!(parse_version(None).ok(), Some(None));
     }
 }
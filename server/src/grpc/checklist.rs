@@ -2,51 +2,226 @@ mod checklist {
     tonic::include_proto!("checklist");
 }
 
-use super::super::core::checklist::service::Service;
-use super::super::core::common::service::Error as ServiceError;
+use super::super::core::checklist::event::{ChangeKind, Event};
+use super::super::core::checklist::model::{
+    Due, DueWindow, ListOrder, ListUpdate, NewTodo, Tag, Todo, TodoList, TodoOrder, TodoTree,
+    TodoUpdate,
+};
+use super::super::core::checklist::service::{create_item_violation, Service};
+use super::super::core::common::service::{Error as ServiceError, ItemViolation};
+use super::super::core::database::MAX_INSERT_ROWS;
+use super::status::{attach_detail, create_field_status, create_status};
+use super::status::{LIST_RESOURCE, TAG_RESOURCE, TODO_RESOURCE};
 use checklist::checklist_server::{Checklist, ChecklistServer};
 
+use checklist::{add_todo_request, new_todo, todo_reply, update_todo_request};
+use checklist::{
+    AddListRequest, AddTodoRequest, CompleteTodoRequest, DoneFilter, EmptyReply, GetListRequest,
+    GetTodoRequest, ListDueTodosReply, ListDueTodosRequest, ListEvent, ListListsReply,
+    ListListsRequest, ListReply, ListTodosReply, ListTodosRequest, RemoveListRequest,
+    RemoveTodoRequest, TodoReply, UpdateListRequest, UpdateTodoRequest, WatchListRequest,
+};
 use checklist::{
-    AddListRequest, AddTodoRequest, EmptyReply, GetListRequest, ListReply, RemoveListRequest,
-    TodoReply, UpdateListRequest,
+    AddTagRequest, AttachTagRequest, DetachTagRequest, ListTagsReply, ListTagsRequest,
+    ListTodoTagsRequest, RemoveTagRequest, RenameTagRequest, TagReply,
 };
+use checklist::{BatchAddTodosReply, BatchAddTodosRequest, BatchSummary, ItemFailure};
+use checklist::{ClearCompletedTodosReply, ClearCompletedTodosRequest};
+use checklist::{CompleteAllTodosReply, CompleteAllTodosRequest};
+use checklist::{GetTodoTreeRequest, TodoTreeReply};
+use checklist::{MoveTodoRequest, MoveTodoToListRequest};
 
+use anyhow::Error;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use futures::{future, Stream, StreamExt};
+use prost_types::{FieldMask, Timestamp};
+use std::pin::Pin;
 use std::sync::Arc;
-use tonic::{Request, Response, Status};
+use tokio::sync::broadcast::RecvError;
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::{Request, Response, Status, Streaming};
 use uuid::Uuid;
 
+const LIST_UPDATE_PATHS: &[&str] = &["name"];
+
+const TODO_UPDATE_PATHS: &[&str] = &["description", "done", "due", "parent_id"];
+
+// Clients written before update masks only ever changed the description.
+const TODO_DEFAULT_PATHS: &[&str] = &["description"];
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Only the first failures are reported so a bad stream can't grow the summary without bound.
+const MAX_REPORTED_FAILURES: usize = 1000;
+
 #[derive(Debug)]
 pub struct Controller {
     service: Arc<Service>,
 }
 
+#[derive(Debug, Default)]
+struct StreamBatch {
+    list_id: Option<Uuid>,
+    indexes: Vec<usize>,
+    todos: Vec<NewTodo>,
+}
+
 impl Controller {
     pub fn new(service: Arc<Service>) -> ChecklistServer<Controller> {
         ChecklistServer::new(Controller { service })
     }
+
+    async fn flush_batch(
+        &self,
+        batch: &mut StreamBatch,
+        summary: &mut BatchSummary,
+    ) -> Result<(), Status> {
+        let list_id = match batch.list_id {
+            Some(list_id) if !batch.todos.is_empty() => list_id,
+            _ => return Ok(()),
+        };
+
+        let result = self.service.ingest_todos(&list_id, &batch.todos).await;
+
+        let violations = match result {
+            Ok(violations) => violations,
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+
+        summary.added += (batch.todos.len() - violations.len()) as u32;
+
+        for violation in violations {
+            let index = batch.indexes[violation.index];
+            record_failure(summary, ItemViolation { index, ..violation });
+        }
+
+        batch.indexes.clear();
+        batch.todos.clear();
+
+        Ok(())
+    }
+
+    async fn add_streamed_todos(
+        &self,
+        stream: &mut Streaming<AddTodoRequest>,
+        summary: &mut BatchSummary,
+    ) -> Result<(), Status> {
+        let mut batch = StreamBatch::default();
+
+        while let Some(request) = stream.message().await? {
+            let index = summary.received as usize;
+            summary.received += 1;
+
+            let (list_id, todo, idempotency_key) = match convert_streamed_todo(index, request) {
+                Ok(item) => item,
+                Err(violation) => {
+                    record_failure(summary, violation);
+                    continue;
+                }
+            };
+
+            // Keyed items go through add_todo, so earlier items are flushed first to keep order.
+            if batch.list_id != Some(list_id)
+                || batch.todos.len() == MAX_INSERT_ROWS
+                || idempotency_key.is_some()
+            {
+                self.flush_batch(&mut batch, summary).await?;
+            }
+
+            match idempotency_key {
+                Some(key) => {
+                    self.add_streamed_todo(index, &list_id, &todo, &key, summary)
+                        .await?
+                }
+                None => {
+                    batch.list_id = Some(list_id);
+                    batch.indexes.push(index);
+                    batch.todos.push(todo);
+                }
+            };
+        }
+
+        self.flush_batch(&mut batch, summary).await
+    }
+
+    async fn add_streamed_todo(
+        &self,
+        index: usize,
+        list_id: &Uuid,
+        todo: &NewTodo,
+        idempotency_key: &str,
+        summary: &mut BatchSummary,
+    ) -> Result<(), Status> {
+        let result = self
+            .service
+            .add_todo(
+                &todo.id.as_ref(),
+                list_id,
+                &todo.description,
+                &todo.due,
+                &todo.parent_id.as_ref(),
+                &Some(idempotency_key),
+            )
+            .await;
+
+        match result {
+            Ok(_) => summary.added += 1,
+            Err(err) => match create_item_violation(index, list_id, err) {
+                Ok(violation) => record_failure(summary, violation),
+                Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+            },
+        };
+
+        Ok(())
+    }
+
+    async fn list_due_todos(
+        &self,
+        window: DueWindow,
+        request: Request<ListDueTodosRequest>,
+    ) -> Result<Response<ListDueTodosReply>, Status> {
+        let time_zone = request.into_inner().time_zone;
+        let time_zone = convert_optional_str(time_zone.as_ref());
+
+        let result = self.service.list_due_todos(window, &time_zone).await;
+
+        match result {
+            Ok(todos) => {
+                return Ok(Response::new(ListDueTodosReply {
+                    todos: todos.into_iter().map(create_todo_reply).collect(),
+                }))
+            }
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
 }
 
 #[tonic::async_trait]
 impl Checklist for Controller {
+    type WatchListStream =
+        Pin<Box<dyn Stream<Item = Result<ListEvent, Status>> + Send + Sync + 'static>>;
+
     async fn add_list(
         &self,
         request: Request<AddListRequest>,
     ) -> Result<Response<ListReply>, Status> {
-        let AddListRequest { name } = request.into_inner();
+        let AddListRequest {
+            name,
+            id,
+            idempotency_key,
+        } = request.into_inner();
+
+        let id = convert_optional_id("id", id.as_ref())?;
+        let idempotency_key = convert_optional_str(idempotency_key.as_ref());
 
-        let result = self.service.add_list(&None, &name).await;
+        let result = self
+            .service
+            .add_list(&id.as_ref(), &name, &idempotency_key)
+            .await;
 
         match result {
-            Ok(list) => {
-                return Ok(Response::new(ListReply {
-                    id: list.id.to_hyphenated().to_string(),
-                    name: list.name,
-                }));
-            }
-            Err(err) => {
-                log::error!("{:?}", err);
-                return Err(Status::new(tonic::Code::Internal, "Unknown Error"));
-            }
+            Ok(list) => return Ok(create_list_response(list)),
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
         };
     }
 
@@ -54,89 +229,133 @@ impl Checklist for Controller {
         &self,
         request: Request<GetListRequest>,
     ) -> Result<Response<ListReply>, Status> {
-        let id = convert_id(request.into_inner().id.as_ref())?;
+        let id = convert_id("id", request.into_inner().id.as_ref())?;
 
         let result = self.service.get_list(&id).await;
 
-        let error = match result {
-            Ok(list) => {
-                return Ok(Response::new(ListReply {
-                    id: list.id.to_hyphenated().to_string(),
-                    name: list.name,
-                }))
-            }
-            Err(err) => err,
+        match result {
+            Ok(list) => return Ok(create_list_response(list)),
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
         };
+    }
 
-        let status = match error.downcast_ref::<ServiceError>() {
-            Some(ServiceError::NotFound(_)) => {
-                Status::new(tonic::Code::NotFound, error.to_string())
-            }
-            _ => {
-                log::error!("{:?}", error);
-                Status::new(tonic::Code::Internal, "Unknown Error")
-            }
+    async fn list_lists(
+        &self,
+        request: Request<ListListsRequest>,
+    ) -> Result<Response<ListListsReply>, Status> {
+        let request = request.into_inner();
+
+        let order = match request.order_by() {
+            checklist::ListOrder::Name => ListOrder::Name,
+            checklist::ListOrder::CreatedAt => ListOrder::CreatedAt,
         };
 
-        Err(status)
+        let page_token = convert_optional_str(request.page_token.as_ref());
+        let name_prefix = convert_optional_str(request.name_prefix.as_ref());
+        let name_contains = convert_optional_str(request.name_contains.as_ref());
+
+        let result = self
+            .service
+            .list_lists(
+                request.page_size,
+                &page_token,
+                &name_prefix,
+                &name_contains,
+                order,
+            )
+            .await;
+
+        match result {
+            Ok(page) => {
+                return Ok(Response::new(ListListsReply {
+                    lists: page.items.into_iter().map(create_list_reply).collect(),
+                    next_page_token: page.next_page_token.unwrap_or_default(),
+                }))
+            }
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
+        };
     }
 
     async fn update_list(
         &self,
         request: Request<UpdateListRequest>,
     ) -> Result<Response<ListReply>, Status> {
-        let UpdateListRequest { id, name } = request.into_inner();
-        let id = convert_id(id.as_ref())?;
+        let metadata = request.metadata().clone();
+
+        let UpdateListRequest {
+            id,
+            name,
+            update_mask,
+            expected_version,
+        } = request.into_inner();
 
-        let result = self.service.update_list(&id, &name).await;
+        let id = convert_id("id", id.as_ref())?;
+        let paths = convert_update_mask(update_mask, LIST_UPDATE_PATHS, LIST_UPDATE_PATHS)?;
+        let expected_version = convert_expected_version(expected_version, &metadata)?;
 
-        let error = match result {
-            Ok(list) => {
-                return Ok(Response::new(ListReply {
-                    id: list.id.to_hyphenated().to_string(),
-                    name: list.name,
-                }))
-            }
-            Err(err) => err,
+        let update = ListUpdate {
+            name: Some(name).filter(|_| paths.contains(&"name")),
         };
 
-        let status = match error.downcast_ref::<ServiceError>() {
-            Some(ServiceError::NotFound(_)) => {
-                Status::new(tonic::Code::NotFound, error.to_string())
-            }
-            _ => {
-                log::error!("{:?}", error);
-                Status::new(tonic::Code::Internal, "Unknown Error")
-            }
-        };
+        let result = self
+            .service
+            .update_list(&id, &update, &expected_version)
+            .await;
 
-        Err(status)
+        match result {
+            Ok(list) => return Ok(create_list_response(list)),
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
+        };
     }
 
     async fn remove_list(
         &self,
         request: Request<RemoveListRequest>,
     ) -> Result<Response<EmptyReply>, Status> {
-        let id = convert_id(request.into_inner().id.as_ref())?;
+        let metadata = request.metadata().clone();
+        let RemoveListRequest {
+            id,
+            expected_version,
+        } = request.into_inner();
 
-        let result = self.service.remove_list(&id).await;
+        let id = convert_id("id", id.as_ref())?;
+        let expected_version = convert_expected_version(expected_version, &metadata)?;
 
-        let error = match result {
+        let result = self.service.remove_list(&id, &expected_version).await;
+
+        match result {
             Ok(_) => return Ok(Response::new(EmptyReply {})),
-            Err(err) => err,
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
         };
+    }
 
-        let status = match error.downcast_ref::<ServiceError>() {
-            Some(ServiceError::NotFound(_)) => {
-                Status::new(tonic::Code::NotFound, error.to_string())
-            }
-            _ => {
-                log::error!("{:?}", error);
-                Status::new(tonic::Code::Internal, "Unknown Error")
-            }
-        };
+    async fn watch_list(
+        &self,
+        request: Request<WatchListRequest>,
+    ) -> Result<Response<Self::WatchListStream>, Status> {
+        let list_id = convert_id("list_id", request.into_inner().list_id.as_ref())?;
+
+        // Subscribe before checking the list exists so no change in between is missed.
+        let events = self.service.subscribe();
+        self.service
+            .get_list(&list_id)
+            .await
+            .map_err(|err| create_status(err, LIST_RESOURCE))?;
+
+        let stream = events.filter_map(move |event| {
+            let reply = match event {
+                Ok(event) if event.list_id() == list_id => Some(Ok(create_list_event(event))),
+                Ok(_) | Err(RecvError::Closed) => None,
+                Err(RecvError::Lagged(skipped)) => Some(Err(Status::new(
+                    tonic::Code::ResourceExhausted,
+                    format!("watcher fell behind by {} events", skipped),
+                ))),
+            };
 
-        Err(status)
+            future::ready(reply)
+        });
+
+        Ok(Response::new(Box::pin(stream)))
     }
 
     async fn add_todo(
@@ -146,46 +365,900 @@ impl Checklist for Controller {
         let AddTodoRequest {
             list_id,
             description,
+            id,
+            idempotency_key,
+            due,
+            parent_id,
         } = request.into_inner();
 
-        let list_id = convert_id(list_id.as_ref())?;
+        let list_id = convert_id("list_id", list_id.as_ref())?;
+        let id = convert_optional_id("id", id.as_ref())?;
+        let parent_id = convert_optional_id("parent_id", parent_id.as_ref())?;
+        let idempotency_key = convert_optional_str(idempotency_key.as_ref());
+
+        let due = match due {
+            Some(add_todo_request::Due::DueAt(due_at)) => Some(convert_due_at("due_at", due_at)?),
+            Some(add_todo_request::Due::DueDate(due_date)) => {
+                Some(convert_due_date("due_date", &due_date)?)
+            }
+            None => None,
+        };
+
+        let result = self
+            .service
+            .add_todo(
+                &id.as_ref(),
+                &list_id,
+                &description,
+                &due,
+                &parent_id.as_ref(),
+                &idempotency_key,
+            )
+            .await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn batch_add_todos(
+        &self,
+        request: Request<BatchAddTodosRequest>,
+    ) -> Result<Response<BatchAddTodosReply>, Status> {
+        let BatchAddTodosRequest { list_id, todos } = request.into_inner();
+
+        let list_id = convert_id("list_id", list_id.as_ref())?;
+        let todos = todos
+            .into_iter()
+            .enumerate()
+            .map(|(index, todo)| convert_new_todo(index, todo))
+            .collect::<Result<Vec<NewTodo>, Status>>()?;
 
-        let result = self.service.add_todo(&list_id, &description).await;
+        let result = self.service.add_todos(&list_id, &todos).await;
 
-        let error = match result {
-            Ok(todo) => {
-                return Ok(Response::new(TodoReply {
-                    list_id: todo.list_id.to_hyphenated().to_string(),
-                    id: todo.id.to_hyphenated().to_string(),
-                    description: todo.description,
-                    done: todo.done,
+        match result {
+            Ok(todos) => {
+                return Ok(Response::new(BatchAddTodosReply {
+                    todos: todos.into_iter().map(create_todo_reply).collect(),
                 }))
             }
-            Err(err) => err,
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn stream_add_todos(
+        &self,
+        request: Request<Streaming<AddTodoRequest>>,
+    ) -> Result<Response<BatchSummary>, Status> {
+        let mut stream = request.into_inner();
+        let mut summary = BatchSummary::default();
+
+        let result = self.add_streamed_todos(&mut stream, &mut summary).await;
+
+        match result {
+            Ok(_) => return Ok(Response::new(summary)),
+            // Items flushed before the failure stay written, so the client learns which they were.
+            Err(status) => return Err(attach_detail(status, "checklist.BatchSummary", &summary)),
+        };
+    }
+
+    async fn list_todos(
+        &self,
+        request: Request<ListTodosRequest>,
+    ) -> Result<Response<ListTodosReply>, Status> {
+        let request = request.into_inner();
+        let list_id = convert_id("list_id", request.list_id.as_ref())?;
+
+        let done = match request.filter_done() {
+            DoneFilter::Any => None,
+            DoneFilter::Done => Some(true),
+            DoneFilter::NotDone => Some(false),
         };
 
-        let status = match error.downcast_ref::<ServiceError>() {
-            Some(ServiceError::Validation(_)) => {
-                Status::new(tonic::Code::InvalidArgument, error.to_string())
+        let order = match request.order_by() {
+            checklist::TodoOrder::Id => TodoOrder::Id,
+            checklist::TodoOrder::Description => TodoOrder::Description,
+            checklist::TodoOrder::Position => TodoOrder::Position,
+        };
+
+        let page_token = convert_optional_str(request.page_token.as_ref());
+
+        let tag_ids = request
+            .tag_ids
+            .iter()
+            .map(|tag_id| convert_id("tag_ids", tag_id))
+            .collect::<Result<Vec<Uuid>, Status>>()?;
+
+        let result = self
+            .service
+            .list_todos(
+                &list_id,
+                request.page_size,
+                &page_token,
+                &done,
+                &tag_ids,
+                order,
+            )
+            .await;
+
+        match result {
+            Ok(page) => {
+                return Ok(Response::new(ListTodosReply {
+                    todos: page.items.into_iter().map(create_todo_reply).collect(),
+                    next_page_token: page.next_page_token.unwrap_or_default(),
+                }))
             }
-            _ => {
-                log::error!("{:?}", error);
-                Status::new(tonic::Code::Internal, "Unknown Error")
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
+        };
+    }
+
+    async fn get_todo(
+        &self,
+        request: Request<GetTodoRequest>,
+    ) -> Result<Response<TodoReply>, Status> {
+        let id = convert_id("id", request.into_inner().id.as_ref())?;
+
+        let result = self.service.get_todo(&id).await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn get_todo_tree(
+        &self,
+        request: Request<GetTodoTreeRequest>,
+    ) -> Result<Response<TodoTreeReply>, Status> {
+        let id = convert_id("id", request.into_inner().id.as_ref())?;
+
+        let result = self.service.get_todo_tree(&id).await;
+
+        match result {
+            Ok(tree) => return Ok(Response::new(create_todo_tree_reply(tree))),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn update_todo(
+        &self,
+        request: Request<UpdateTodoRequest>,
+    ) -> Result<Response<TodoReply>, Status> {
+        let metadata = request.metadata().clone();
+
+        let UpdateTodoRequest {
+            id,
+            description,
+            done,
+            update_mask,
+            expected_version,
+            due,
+            parent_id,
+            clear_due,
+        } = request.into_inner();
+
+        let id = convert_id("id", id.as_ref())?;
+        let paths = convert_update_mask(update_mask, TODO_UPDATE_PATHS, TODO_DEFAULT_PATHS)?;
+        let expected_version = convert_expected_version(expected_version, &metadata)?;
+
+        // Only clear_due removes a due date, so an unset oneof never clears one by accident.
+        let due = match (due, clear_due) {
+            (Some(_), true) => {
+                return Err(create_field_status(
+                    "clear_due",
+                    "clear_due cannot be combined with a due date",
+                ))
+            }
+            (Some(update_todo_request::Due::DueAt(due_at)), false) => {
+                Some(Some(convert_due_at("due_at", due_at)?)).filter(|_| paths.contains(&"due"))
             }
+            (Some(update_todo_request::Due::DueDate(due_date)), false) => {
+                Some(Some(convert_due_date("due_date", &due_date)?))
+                    .filter(|_| paths.contains(&"due"))
+            }
+            (None, true) if paths.contains(&"due") => Some(None),
+            (None, true) => {
+                return Err(create_field_status(
+                    "clear_due",
+                    "clear_due requires 'due' in the update mask",
+                ))
+            }
+            (None, false) => None,
+        };
+
+        let update = TodoUpdate {
+            description: Some(description).filter(|_| paths.contains(&"description")),
+            done: Some(done).filter(|_| paths.contains(&"done")),
+            due,
+            parent_id: Some(convert_optional_id("parent_id", parent_id.as_ref())?)
+                .filter(|_| paths.contains(&"parent_id")),
         };
 
-        Err(status)
+        let result = self
+            .service
+            .update_todo(&id, &update, &expected_version)
+            .await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn move_todo(
+        &self,
+        request: Request<MoveTodoRequest>,
+    ) -> Result<Response<TodoReply>, Status> {
+        let MoveTodoRequest {
+            id,
+            before_id,
+            after_id,
+        } = request.into_inner();
+
+        let id = convert_id("id", id.as_ref())?;
+        let before_id = convert_optional_id("before_id", before_id.as_ref())?;
+        let after_id = convert_optional_id("after_id", after_id.as_ref())?;
+
+        let result = self
+            .service
+            .move_todo(&id, &after_id.as_ref(), &before_id.as_ref())
+            .await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn move_todo_to_list(
+        &self,
+        request: Request<MoveTodoToListRequest>,
+    ) -> Result<Response<TodoReply>, Status> {
+        let MoveTodoToListRequest { id, list_id } = request.into_inner();
+
+        let id = convert_id("id", id.as_ref())?;
+        let list_id = convert_id("list_id", list_id.as_ref())?;
+
+        let result = self.service.move_todo_to_list(&id, &list_id).await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn remove_todo(
+        &self,
+        request: Request<RemoveTodoRequest>,
+    ) -> Result<Response<EmptyReply>, Status> {
+        let metadata = request.metadata().clone();
+        let RemoveTodoRequest {
+            id,
+            expected_version,
+        } = request.into_inner();
+
+        let id = convert_id("id", id.as_ref())?;
+        let expected_version = convert_expected_version(expected_version, &metadata)?;
+
+        let result = self.service.remove_todo(&id, &expected_version).await;
+
+        match result {
+            Ok(_) => return Ok(Response::new(EmptyReply {})),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn complete_todo(
+        &self,
+        request: Request<CompleteTodoRequest>,
+    ) -> Result<Response<TodoReply>, Status> {
+        let metadata = request.metadata().clone();
+        let CompleteTodoRequest {
+            id,
+            done,
+            cascade,
+            expected_version,
+        } = request.into_inner();
+
+        let id = convert_id("id", id.as_ref())?;
+        let expected_version = convert_expected_version(expected_version, &metadata)?;
+
+        let result = self
+            .service
+            .complete_todo(&id, done, cascade, &expected_version)
+            .await;
+
+        match result {
+            Ok(todo) => return Ok(create_todo_response(todo)),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
+    }
+
+    async fn complete_all_todos(
+        &self,
+        request: Request<CompleteAllTodosRequest>,
+    ) -> Result<Response<CompleteAllTodosReply>, Status> {
+        let list_id = convert_id("list_id", request.into_inner().list_id.as_ref())?;
+
+        let result = self.service.complete_all_todos(&list_id).await;
+
+        match result {
+            Ok(todos) => {
+                return Ok(Response::new(CompleteAllTodosReply {
+                    todos: todos.into_iter().map(create_todo_reply).collect(),
+                }))
+            }
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
+        };
+    }
+
+    async fn clear_completed_todos(
+        &self,
+        request: Request<ClearCompletedTodosRequest>,
+    ) -> Result<Response<ClearCompletedTodosReply>, Status> {
+        let list_id = convert_id("list_id", request.into_inner().list_id.as_ref())?;
+
+        let result = self.service.clear_completed_todos(&list_id).await;
+
+        match result {
+            Ok(ids) => {
+                return Ok(Response::new(ClearCompletedTodosReply {
+                    ids: ids
+                        .into_iter()
+                        .map(|id| id.to_hyphenated().to_string())
+                        .collect(),
+                }))
+            }
+            Err(err) => return Err(create_status(err, LIST_RESOURCE)),
+        };
+    }
+
+    async fn list_overdue_todos(
+        &self,
+        request: Request<ListDueTodosRequest>,
+    ) -> Result<Response<ListDueTodosReply>, Status> {
+        self.list_due_todos(DueWindow::Overdue, request).await
+    }
+
+    async fn list_todos_due_today(
+        &self,
+        request: Request<ListDueTodosRequest>,
+    ) -> Result<Response<ListDueTodosReply>, Status> {
+        self.list_due_todos(DueWindow::Today, request).await
+    }
+
+    async fn list_todos_due_this_week(
+        &self,
+        request: Request<ListDueTodosRequest>,
+    ) -> Result<Response<ListDueTodosReply>, Status> {
+        self.list_due_todos(DueWindow::ThisWeek, request).await
+    }
+
+    async fn add_tag(&self, request: Request<AddTagRequest>) -> Result<Response<TagReply>, Status> {
+        let result = self.service.add_tag(&request.into_inner().name).await;
+
+        match result {
+            Ok(tag) => return Ok(Response::new(create_tag_reply(tag))),
+            Err(err) => return Err(create_status(err, TAG_RESOURCE)),
+        };
+    }
+
+    async fn list_tags(
+        &self,
+        _request: Request<ListTagsRequest>,
+    ) -> Result<Response<ListTagsReply>, Status> {
+        let result = self.service.list_tags().await;
+
+        match result {
+            Ok(tags) => return Ok(Response::new(create_list_tags_reply(tags))),
+            Err(err) => return Err(create_status(err, TAG_RESOURCE)),
+        };
+    }
+
+    async fn rename_tag(
+        &self,
+        request: Request<RenameTagRequest>,
+    ) -> Result<Response<TagReply>, Status> {
+        let RenameTagRequest { id, name } = request.into_inner();
+        let id = convert_id("id", id.as_ref())?;
+
+        let result = self.service.rename_tag(&id, &name).await;
+
+        match result {
+            Ok(tag) => return Ok(Response::new(create_tag_reply(tag))),
+            Err(err) => return Err(create_status(err, TAG_RESOURCE)),
+        };
+    }
+
+    async fn remove_tag(
+        &self,
+        request: Request<RemoveTagRequest>,
+    ) -> Result<Response<EmptyReply>, Status> {
+        let id = convert_id("id", request.into_inner().id.as_ref())?;
+
+        let result = self.service.remove_tag(&id).await;
+
+        match result {
+            Ok(_) => return Ok(Response::new(EmptyReply {})),
+            Err(err) => return Err(create_status(err, TAG_RESOURCE)),
+        };
+    }
+
+    async fn attach_tag(
+        &self,
+        request: Request<AttachTagRequest>,
+    ) -> Result<Response<EmptyReply>, Status> {
+        let AttachTagRequest { todo_id, tag_id } = request.into_inner();
+        let todo_id = convert_id("todo_id", todo_id.as_ref())?;
+        let tag_id = convert_id("tag_id", tag_id.as_ref())?;
+
+        let result = self.service.attach_tag(&todo_id, &tag_id).await;
+
+        match result {
+            Ok(_) => return Ok(Response::new(EmptyReply {})),
+            Err(err) => return Err(create_todo_tag_status(err, &tag_id)),
+        };
+    }
+
+    async fn detach_tag(
+        &self,
+        request: Request<DetachTagRequest>,
+    ) -> Result<Response<EmptyReply>, Status> {
+        let DetachTagRequest { todo_id, tag_id } = request.into_inner();
+        let todo_id = convert_id("todo_id", todo_id.as_ref())?;
+        let tag_id = convert_id("tag_id", tag_id.as_ref())?;
+
+        let result = self.service.detach_tag(&todo_id, &tag_id).await;
+
+        match result {
+            Ok(_) => return Ok(Response::new(EmptyReply {})),
+            Err(err) => return Err(create_todo_tag_status(err, &tag_id)),
+        };
+    }
+
+    async fn list_todo_tags(
+        &self,
+        request: Request<ListTodoTagsRequest>,
+    ) -> Result<Response<ListTagsReply>, Status> {
+        let todo_id = convert_id("todo_id", request.into_inner().todo_id.as_ref())?;
+
+        let result = self.service.list_todo_tags(&todo_id).await;
+
+        match result {
+            Ok(tags) => return Ok(Response::new(create_list_tags_reply(tags))),
+            Err(err) => return Err(create_status(err, TODO_RESOURCE)),
+        };
     }
 }
 
-fn convert_id(id: &str) -> Result<Uuid, Status> {
+fn convert_id(field: &str, id: &str) -> Result<Uuid, Status> {
     match Uuid::parse_str(id) {
         Err(_) => {
-            return Err(Status::new(
-                tonic::Code::InvalidArgument,
-                format!("'{}' is not a valid v4 UUID", id),
+            return Err(create_field_status(
+                field,
+                &format!("'{}' is not a valid v4 UUID", id),
             ))
         }
         Ok(id) => return Ok(id),
     };
 }
+
+fn convert_optional_id(field: &str, id: &str) -> Result<Option<Uuid>, Status> {
+    match id {
+        "" => return Ok(None),
+        id => return convert_id(field, id).map(Some),
+    };
+}
+
+// A missing or empty mask updates the default fields, while "*" replaces every updatable field.
+fn convert_update_mask(
+    update_mask: Option<FieldMask>,
+    allowed: &[&'static str],
+    default: &[&'static str],
+) -> Result<Vec<&'static str>, Status> {
+    let paths = match update_mask {
+        Some(mask) if !mask.paths.is_empty() => mask.paths,
+        _ => return Ok(default.to_vec()),
+    };
+
+    if paths.iter().any(|path| path == "*") {
+        return Ok(allowed.to_vec());
+    }
+
+    let mut fields = Vec::new();
+
+    for path in paths {
+        match allowed.iter().find(|field| **field == path) {
+            Some(field) => fields.push(*field),
+            None => {
+                return Err(create_field_status(
+                    "update_mask",
+                    &format!("'{}' is not an updatable field", path),
+                ))
+            }
+        };
+    }
+
+    Ok(fields)
+}
+
+// A zero version defers to an If-Match header, and neither (or "*") skips the check.
+fn convert_expected_version(
+    expected_version: i64,
+    metadata: &MetadataMap,
+) -> Result<Option<i64>, Status> {
+    if expected_version < 0 {
+        return Err(create_field_status(
+            "expected_version",
+            "expected_version must not be negative",
+        ));
+    }
+
+    if expected_version > 0 {
+        return Ok(Some(expected_version));
+    }
+
+    let etag = match metadata.get("if-match") {
+        Some(value) => value.to_str().unwrap_or_default().trim(),
+        None => return Ok(None),
+    };
+
+    if etag == "*" {
+        return Ok(None);
+    }
+
+    let version = etag.trim_start_matches("W/").trim_matches('"');
+
+    match version.parse::<i64>() {
+        Ok(version) if version > 0 => return Ok(Some(version)),
+        _ => {
+            return Err(create_field_status(
+                "if-match",
+                &format!("'{}' is not a valid entity tag", etag),
+            ))
+        }
+    };
+}
+
+fn convert_due_at(field: &str, due_at: Timestamp) -> Result<Due, Status> {
+    let time = match due_at.nanos {
+        nanos if nanos >= 0 => Utc.timestamp_opt(due_at.seconds, nanos as u32).single(),
+        _ => None,
+    };
+
+    match time {
+        Some(time) => return Ok(Due::Time(time)),
+        None => {
+            return Err(create_field_status(
+                field,
+                &format!("{} is not a valid timestamp", field),
+            ))
+        }
+    };
+}
+
+fn convert_due_date(field: &str, due_date: &str) -> Result<Due, Status> {
+    match NaiveDate::parse_from_str(due_date, DATE_FORMAT) {
+        Ok(date) => return Ok(Due::Date(date)),
+        Err(_) => {
+            return Err(create_field_status(
+                field,
+                &format!("'{}' is not a valid YYYY-MM-DD date", due_date),
+            ))
+        }
+    };
+}
+
+fn convert_new_todo(index: usize, todo: checklist::NewTodo) -> Result<NewTodo, Status> {
+    let field = |name: &str| format!("todos[{}].{}", index, name);
+
+    let due = match todo.due {
+        Some(new_todo::Due::DueAt(due_at)) => Some(convert_due_at(&field("due_at"), due_at)?),
+        Some(new_todo::Due::DueDate(due_date)) => {
+            Some(convert_due_date(&field("due_date"), &due_date)?)
+        }
+        None => None,
+    };
+
+    Ok(NewTodo {
+        id: convert_optional_id(&field("id"), todo.id.as_ref())?,
+        description: todo.description,
+        due,
+        parent_id: convert_optional_id(&field("parent_id"), todo.parent_id.as_ref())?,
+    })
+}
+
+fn convert_streamed_todo(
+    index: usize,
+    request: AddTodoRequest,
+) -> Result<(Uuid, NewTodo, Option<String>), ItemViolation> {
+    let violation = |field: &'static str| {
+        move |status: Status| ItemViolation {
+            index,
+            field,
+            description: status.message().to_string(),
+        }
+    };
+
+    let list_id = convert_id("list_id", request.list_id.as_ref()).map_err(violation("list_id"))?;
+    let id = convert_optional_id("id", request.id.as_ref()).map_err(violation("id"))?;
+    let parent_id = convert_optional_id("parent_id", request.parent_id.as_ref())
+        .map_err(violation("parent_id"))?;
+    let idempotency_key = convert_optional_str(request.idempotency_key.as_ref()).map(String::from);
+
+    let due = match request.due {
+        Some(add_todo_request::Due::DueAt(due_at)) => {
+            Some(convert_due_at("due_at", due_at).map_err(violation("due_at"))?)
+        }
+        Some(add_todo_request::Due::DueDate(due_date)) => {
+            Some(convert_due_date("due_date", &due_date).map_err(violation("due_date"))?)
+        }
+        None => None,
+    };
+
+    let todo = NewTodo {
+        id,
+        description: request.description,
+        due,
+        parent_id,
+    };
+
+    Ok((list_id, todo, idempotency_key))
+}
+
+fn record_failure(summary: &mut BatchSummary, violation: ItemViolation) {
+    summary.failed += 1;
+
+    if summary.failures.len() < MAX_REPORTED_FAILURES {
+        summary.failures.push(ItemFailure {
+            index: violation.index as u32,
+            field: violation.field.to_string(),
+            description: violation.description,
+        });
+    }
+}
+
+fn convert_optional_str(value: &str) -> Option<&str> {
+    match value {
+        "" => None,
+        value => Some(value),
+    }
+}
+
+fn create_list_reply(list: TodoList) -> ListReply {
+    ListReply {
+        id: list.id.to_hyphenated().to_string(),
+        name: list.name,
+        version: list.version,
+        created_at: Some(create_timestamp(list.created_at)),
+        updated_at: Some(create_timestamp(list.updated_at)),
+    }
+}
+
+fn create_todo_reply(todo: Todo) -> TodoReply {
+    TodoReply {
+        list_id: todo.list_id.to_hyphenated().to_string(),
+        id: todo.id.to_hyphenated().to_string(),
+        description: todo.description,
+        done: todo.done,
+        version: todo.version,
+        created_at: Some(create_timestamp(todo.created_at)),
+        updated_at: Some(create_timestamp(todo.updated_at)),
+        completed_at: todo.completed_at.map(create_timestamp),
+        due: todo.due.map(|due| match due {
+            Due::Date(date) => todo_reply::Due::DueDate(date.format(DATE_FORMAT).to_string()),
+            Due::Time(time) => todo_reply::Due::DueAt(create_timestamp(time)),
+        }),
+        parent_id: todo
+            .parent_id
+            .map(|parent_id| parent_id.to_hyphenated().to_string())
+            .unwrap_or_default(),
+        position: todo.position,
+    }
+}
+
+fn create_todo_tree_reply(tree: TodoTree) -> TodoTreeReply {
+    TodoTreeReply {
+        todo: Some(create_todo_reply(tree.todo)),
+        subtasks: tree
+            .subtasks
+            .into_iter()
+            .map(create_todo_tree_reply)
+            .collect(),
+    }
+}
+
+fn create_tag_reply(tag: Tag) -> TagReply {
+    TagReply {
+        id: tag.id.to_hyphenated().to_string(),
+        name: tag.name,
+    }
+}
+
+fn create_list_tags_reply(tags: Vec<Tag>) -> ListTagsReply {
+    ListTagsReply {
+        tags: tags.into_iter().map(create_tag_reply).collect(),
+    }
+}
+
+// Attaching and detaching can miss either the todo or the tag, so the resource follows the ID.
+fn create_todo_tag_status(error: Error, tag_id: &Uuid) -> Status {
+    match error.downcast_ref::<ServiceError>() {
+        Some(ServiceError::NotFound(id)) if id == tag_id => create_status(error, TAG_RESOURCE),
+        _ => create_status(error, TODO_RESOURCE),
+    }
+}
+
+fn create_timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn create_list_response(list: TodoList) -> Response<ListReply> {
+    let etag = create_etag(list.version);
+    let mut response = Response::new(create_list_reply(list));
+    response.metadata_mut().insert("etag", etag);
+    response
+}
+
+fn create_todo_response(todo: Todo) -> Response<TodoReply> {
+    let etag = create_etag(todo.version);
+    let mut response = Response::new(create_todo_reply(todo));
+    response.metadata_mut().insert("etag", etag);
+    response
+}
+
+fn create_etag(version: i64) -> MetadataValue<Ascii> {
+    // A quoted decimal is always a valid header value.
+    MetadataValue::from_str(&format!("\"{}\"", version)).expect("etag is valid ASCII")
+}
+
+fn create_list_event(event: Event) -> ListEvent {
+    let convert_kind = |kind: ChangeKind| match kind {
+        ChangeKind::Created => checklist::ChangeKind::Created,
+        ChangeKind::Updated => checklist::ChangeKind::Updated,
+        ChangeKind::Removed => checklist::ChangeKind::Removed,
+    };
+
+    match event {
+        Event::List {
+            kind,
+            list_id,
+            list,
+        } => ListEvent {
+            kind: convert_kind(kind) as i32,
+            list_id: list_id.to_hyphenated().to_string(),
+            todo_id: String::new(),
+            list: list.map(create_list_reply),
+            todo: None,
+        },
+        Event::Todo {
+            kind,
+            list_id,
+            todo_id,
+            todo,
+        } => ListEvent {
+            kind: convert_kind(kind) as i32,
+            list_id: list_id.to_hyphenated().to_string(),
+            todo_id: todo_id.to_hyphenated().to_string(),
+            list: None,
+            todo: todo.map(create_todo_reply),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::core::checklist::memory::MemoryModel;
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn create_controller() -> Controller {
+        Controller {
+            service: Service::new(Box::new(MemoryModel::new())),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_todo_without_mask() -> anyhow::Result<()> {
+        let controller = create_controller();
+
+        let list = controller
+            .service
+            .add_list(&None, "update_todo", &None)
+            .await?;
+        let parent = controller
+            .service
+            .add_todo(&None, &list.id, "parent", &None, &None, &None)
+            .await?;
+        let due = Some(Due::Date(NaiveDate::from_ymd(2020, 1, 1)));
+        let todo = controller
+            .service
+            .add_todo(&None, &list.id, "todo", &due, &Some(&parent.id), &None)
+            .await?;
+        controller
+            .service
+            .complete_todo(&todo.id, true, false, &None)
+            .await?;
+
+        let reply = controller
+            .update_todo(Request::new(UpdateTodoRequest {
+                id: todo.id.to_hyphenated().to_string(),
+                description: "updated".to_string(),
+                ..UpdateTodoRequest::default()
+            }))
+            .await?
+            .into_inner();
+
+        assert_eq!(reply.description, "updated");
+        assert!(reply.done);
+        assert_eq!(
+            reply.due,
+            Some(todo_reply::Due::DueDate("2020-01-01".to_string()))
+        );
+        assert_eq!(reply.parent_id, parent.id.to_hyphenated().to_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_todo_clear_due() -> anyhow::Result<()> {
+        let controller = create_controller();
+
+        let list = controller
+            .service
+            .add_list(&None, "update_todo", &None)
+            .await?;
+        let due = Some(Due::Date(NaiveDate::from_ymd(2020, 1, 1)));
+        let todo = controller
+            .service
+            .add_todo(&None, &list.id, "todo", &due, &None, &None)
+            .await?;
+
+        let reply = controller
+            .update_todo(Request::new(UpdateTodoRequest {
+                id: todo.id.to_hyphenated().to_string(),
+                description: "updated".to_string(),
+                update_mask: Some(FieldMask {
+                    paths: vec!["*".to_string()],
+                }),
+                ..UpdateTodoRequest::default()
+            }))
+            .await?
+            .into_inner();
+
+        assert!(reply.due.is_some());
+
+        let status = controller
+            .update_todo(Request::new(UpdateTodoRequest {
+                id: todo.id.to_hyphenated().to_string(),
+                description: "updated".to_string(),
+                clear_due: true,
+                ..UpdateTodoRequest::default()
+            }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let reply = controller
+            .update_todo(Request::new(UpdateTodoRequest {
+                id: todo.id.to_hyphenated().to_string(),
+                description: "updated".to_string(),
+                update_mask: Some(FieldMask {
+                    paths: vec!["description".to_string(), "due".to_string()],
+                }),
+                clear_due: true,
+                ..UpdateTodoRequest::default()
+            }))
+            .await?
+            .into_inner();
+
+        assert_eq!(reply.due, None);
+
+        Ok(())
+    }
+}
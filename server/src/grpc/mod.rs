@@ -1 +1,2 @@
 pub mod checklist;
+mod status;
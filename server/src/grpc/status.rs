@@ -0,0 +1,298 @@
+mod rpc {
+    tonic::include_proto!("google.rpc");
+}
+
+use super::super::core::common::service::{self, Error as ServiceError, ErrorKind};
+use anyhow::Error;
+use bytes::Bytes;
+use prost::Message;
+use prost_types::Any;
+use rpc::bad_request::FieldViolation;
+use rpc::precondition_failure::Violation;
+use rpc::{BadRequest, ErrorInfo, PreconditionFailure, ResourceInfo};
+use std::collections::HashMap;
+use tonic::{Code, Status};
+
+const ERROR_DOMAIN: &str = "checklist";
+
+pub const LIST_RESOURCE: &str = "checklist.TodoList";
+pub const TODO_RESOURCE: &str = "checklist.Todo";
+pub const TAG_RESOURCE: &str = "checklist.Tag";
+
+pub fn create_status(error: Error, resource_type: &str) -> Status {
+    let kind = service::error_kind(&error);
+
+    let (code, message) = match kind {
+        ErrorKind::Conflict => (Code::AlreadyExists, error.to_string()),
+        ErrorKind::NotFound => (Code::NotFound, error.to_string()),
+        ErrorKind::Validation => (Code::InvalidArgument, error.to_string()),
+        ErrorKind::PreconditionFailed => (Code::FailedPrecondition, error.to_string()),
+        ErrorKind::Unavailable => {
+            log::warn!("{:?}", error);
+            (Code::Unavailable, "Service Unavailable".to_string())
+        }
+        ErrorKind::Unknown => {
+            log::error!("{:?}", error);
+            (Code::Internal, "Unknown Error".to_string())
+        }
+    };
+
+    let mut details = vec![create_error_info(kind)];
+
+    match error.downcast_ref::<ServiceError>() {
+        Some(ServiceError::Conflict(id)) | Some(ServiceError::NotFound(id)) => {
+            details.push(pack_any(
+                "google.rpc.ResourceInfo",
+                &ResourceInfo {
+                    resource_type: resource_type.to_string(),
+                    resource_name: id.to_hyphenated().to_string(),
+                    owner: String::new(),
+                    description: message.clone(),
+                },
+            ));
+        }
+        Some(ServiceError::NameConflict(name)) => {
+            details.push(pack_any(
+                "google.rpc.ResourceInfo",
+                &ResourceInfo {
+                    resource_type: resource_type.to_string(),
+                    resource_name: name.clone(),
+                    owner: String::new(),
+                    description: message.clone(),
+                },
+            ));
+        }
+        Some(ServiceError::Validation(field, description)) => {
+            details.push(create_bad_request(field, description));
+        }
+        Some(ServiceError::InvalidItems(violations)) => {
+            details.push(pack_any(
+                "google.rpc.BadRequest",
+                &BadRequest {
+                    field_violations: violations
+                        .iter()
+                        .map(|violation| FieldViolation {
+                            field: format!("todos[{}].{}", violation.index, violation.field),
+                            description: violation.description.clone(),
+                        })
+                        .collect(),
+                },
+            ));
+        }
+        Some(ServiceError::PreconditionFailed(id, _)) => {
+            details.push(pack_any(
+                "google.rpc.PreconditionFailure",
+                &PreconditionFailure {
+                    violations: vec![Violation {
+                        r#type: "VERSION".to_string(),
+                        subject: format!("{}/{}", resource_type, id.to_hyphenated()),
+                        description: message.clone(),
+                    }],
+                },
+            ));
+        }
+        None => {}
+    };
+
+    create_status_with_details(code, message, details)
+}
+
+pub fn create_field_status(field: &str, description: &str) -> Status {
+    let details = vec![
+        create_error_info(ErrorKind::Validation),
+        create_bad_request(field, description),
+    ];
+
+    create_status_with_details(Code::InvalidArgument, description.to_string(), details)
+}
+
+pub fn attach_detail<M: Message>(status: Status, type_name: &str, message: &M) -> Status {
+    let mut details =
+        rpc::Status::decode(status.details()).map_or(Vec::new(), |status| status.details);
+    details.push(pack_any(type_name, message));
+
+    create_status_with_details(status.code(), status.message().to_string(), details)
+}
+
+fn create_error_info(kind: ErrorKind) -> Any {
+    let reason = match kind {
+        ErrorKind::Conflict => "RESOURCE_ALREADY_EXISTS",
+        ErrorKind::NotFound => "RESOURCE_NOT_FOUND",
+        ErrorKind::Validation => "INVALID_ARGUMENT",
+        ErrorKind::PreconditionFailed => "VERSION_MISMATCH",
+        ErrorKind::Unavailable => "DATABASE_UNAVAILABLE",
+        ErrorKind::Unknown => "UNKNOWN",
+    };
+
+    let error_info = ErrorInfo {
+        reason: reason.to_string(),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata: HashMap::new(),
+    };
+
+    pack_any("google.rpc.ErrorInfo", &error_info)
+}
+
+fn create_bad_request(field: &str, description: &str) -> Any {
+    let bad_request = BadRequest {
+        field_violations: vec![FieldViolation {
+            field: field.to_string(),
+            description: description.to_string(),
+        }],
+    };
+
+    pack_any("google.rpc.BadRequest", &bad_request)
+}
+
+fn create_status_with_details(code: Code, message: String, details: Vec<Any>) -> Status {
+    let status = rpc::Status {
+        code: code as i32,
+        message: message.clone(),
+        details,
+    };
+
+    Status::with_details(code, message, Bytes::from(encode(&status)))
+}
+
+fn pack_any<M: Message>(type_name: &str, message: &M) -> Any {
+    Any {
+        type_url: format!("type.googleapis.com/{}", type_name),
+        value: encode(message),
+    }
+}
+
+fn encode<M: Message>(message: &M) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(message.encoded_len());
+
+    // Encoding only fails when the buffer runs out of capacity, and a Vec grows as needed.
+    message
+        .encode(&mut buf)
+        .expect("protobuf encoding into a Vec cannot fail");
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    fn decode_details(status: &Status) -> rpc::Status {
+        rpc::Status::decode(status.details()).unwrap()
+    }
+
+    fn find_detail<M: Message + Default>(details: &rpc::Status, type_name: &str) -> Option<M> {
+        details
+            .details
+            .iter()
+            .find(|any| any.type_url == format!("type.googleapis.com/{}", type_name))
+            .map(|any| M::decode(any.value.as_slice()).unwrap())
+    }
+
+    #[test]
+    fn maps_service_errors() {
+        let id = Uuid::new_v4();
+
+        let conflict = create_status(Error::new(ServiceError::Conflict(id)), LIST_RESOURCE);
+        let not_found = create_status(Error::new(ServiceError::NotFound(id)), TODO_RESOURCE);
+        let validation = create_status(
+            Error::new(ServiceError::Validation("page_size", "bad".to_string())),
+            LIST_RESOURCE,
+        );
+
+        assert_eq!(conflict.code(), Code::AlreadyExists);
+        assert_eq!(not_found.code(), Code::NotFound);
+        assert_eq!(validation.code(), Code::InvalidArgument);
+        assert_eq!(validation.message(), "bad");
+
+        let stale = create_status(
+            Error::new(ServiceError::PreconditionFailed(id, 1)),
+            LIST_RESOURCE,
+        );
+
+        assert_eq!(stale.code(), Code::FailedPrecondition);
+
+        let taken = create_status(
+            Error::new(ServiceError::NameConflict("home".to_string())),
+            TAG_RESOURCE,
+        );
+
+        assert_eq!(taken.code(), Code::AlreadyExists);
+    }
+
+    #[test]
+    fn maps_database_errors() {
+        let unavailable = create_status(Error::new(sqlx::Error::PoolClosed), LIST_RESOURCE);
+        let unknown = create_status(Error::new(sqlx::Error::RowNotFound), LIST_RESOURCE);
+
+        assert_eq!(unavailable.code(), Code::Unavailable);
+        assert_eq!(unknown.code(), Code::Internal);
+        assert_eq!(unknown.message(), "Unknown Error");
+    }
+
+    #[test]
+    fn attaches_details() {
+        let id = Uuid::new_v4();
+
+        let not_found = create_status(Error::new(ServiceError::NotFound(id)), TODO_RESOURCE);
+        let details = decode_details(&not_found);
+
+        let error_info = find_detail::<ErrorInfo>(&details, "google.rpc.ErrorInfo").unwrap();
+        let resource_info =
+            find_detail::<ResourceInfo>(&details, "google.rpc.ResourceInfo").unwrap();
+
+        assert_eq!(details.code, Code::NotFound as i32);
+        assert_eq!(error_info.reason, "RESOURCE_NOT_FOUND");
+        assert_eq!(error_info.domain, ERROR_DOMAIN);
+        assert_eq!(resource_info.resource_type, TODO_RESOURCE);
+        assert_eq!(resource_info.resource_name, id.to_hyphenated().to_string());
+
+        let invalid = create_field_status("list_id", "'x' is not a valid v4 UUID");
+        let details = decode_details(&invalid);
+
+        let bad_request = find_detail::<BadRequest>(&details, "google.rpc.BadRequest").unwrap();
+
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+        assert_eq!(bad_request.field_violations[0].field, "list_id");
+
+        let violations = (0..2)
+            .map(|index| service::ItemViolation {
+                index,
+                field: "parent_id",
+                description: "bad".to_string(),
+            })
+            .collect();
+
+        let invalid_items = create_status(
+            Error::new(ServiceError::InvalidItems(violations)),
+            TODO_RESOURCE,
+        );
+        let details = decode_details(&invalid_items);
+
+        let bad_request = find_detail::<BadRequest>(&details, "google.rpc.BadRequest").unwrap();
+
+        assert_eq!(invalid_items.code(), Code::InvalidArgument);
+        assert_eq!(bad_request.field_violations.len(), 2);
+        assert_eq!(bad_request.field_violations[1].field, "todos[1].parent_id");
+    }
+
+    #[test]
+    fn attaches_extra_details() {
+        let status = create_status(Error::new(sqlx::Error::PoolClosed), TODO_RESOURCE);
+        let resource_info = ResourceInfo {
+            resource_type: TODO_RESOURCE.to_string(),
+            ..ResourceInfo::default()
+        };
+
+        let status = attach_detail(status, "google.rpc.ResourceInfo", &resource_info);
+        let details = decode_details(&status);
+
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(find_detail::<ErrorInfo>(&details, "google.rpc.ErrorInfo").is_some());
+        assert_eq!(
+            find_detail::<ResourceInfo>(&details, "google.rpc.ResourceInfo"),
+            Some(resource_info)
+        );
+    }
+}
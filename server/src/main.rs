@@ -6,17 +6,22 @@ mod core;
 mod graphql;
 mod grpc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use dotenv::dotenv;
 
+use crate::core::checklist::service::Service;
+use futures::FutureExt;
 use juniper::{
     http::{graphiql::graphiql_source, GraphQLRequest},
-    EmptySubscription, RootNode,
+    RootNode,
 };
+use juniper_subscriptions::Coordinator;
+use juniper_warp::subscriptions::graphql_subscriptions;
 
 use std::convert::Infallible;
 use std::env;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tonic::transport::Server;
 use warp::Filter;
 
@@ -24,7 +29,7 @@ type GraphqlSchema = RootNode<
     'static,
     graphql::checklist::Query,
     graphql::checklist::Mutation,
-    EmptySubscription<graphql::checklist::Context>,
+    graphql::checklist::Subscription,
 >;
 
 #[tokio::main]
@@ -41,18 +46,94 @@ async fn main() {
 }
 
 async fn run() -> Result<()> {
-    run_grpc().await?;
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let args = args.iter().map(String::as_ref).collect::<Vec<&str>>();
+
+    match args.as_slice() {
+        [] => run_servers().await?,
+        ["migrate", command] => run_migrate(command).await?,
+        _ => return Err(anyhow!("usage: rust-web [migrate up|down|status]")),
+    };
+
     Ok(())
 }
 
-#[allow(dead_code)]
-async fn run_grpc() -> Result<()> {
-    let pool = core::database::create_pool(&env::var("DATABASE_URL")?).await?;
-    core::database::create_schema(&pool).await?;
+async fn run_migrate(command: &str) -> Result<()> {
+    use crate::core::migration;
+
+    let database_url = env::var("DATABASE_URL")?;
+
+    if database_url.starts_with("sqlite:") {
+        let pool = core::database::create_sqlite_pool(&database_url).await?;
+
+        match command {
+            "up" => report_applied(&migration::up_sqlite(&pool).await?),
+            "down" => report_reverted(&migration::down_sqlite(&pool).await?),
+            "status" => print_statuses(&migration::status_sqlite(&pool).await?),
+            _ => return Err(anyhow!("unknown migrate command '{}'", command)),
+        };
+
+        return Ok(());
+    }
+
+    let pool = core::database::create_pool(&database_url).await?;
+
+    match command {
+        "up" => report_applied(&migration::up(&pool).await?),
+        "down" => report_reverted(&migration::down(&pool).await?),
+        "status" => print_statuses(&migration::status(&pool).await?),
+        _ => return Err(anyhow!("unknown migrate command '{}'", command)),
+    };
+
+    Ok(())
+}
+
+fn report_applied(versions: &[i64]) {
+    log::info!("applied {} migration(s)", versions.len());
+}
+
+fn report_reverted(version: &Option<i64>) {
+    match version {
+        Some(version) => log::info!("reverted migration {}", version),
+        None => log::info!("no migrations to revert"),
+    };
+}
+
+fn print_statuses(statuses: &[core::migration::Status]) {
+    use crate::core::migration::State;
 
-    let checklist_model = core::checklist::model::Model::new(pool.clone());
-    let checklist_service = core::checklist::service::Service::new(checklist_model);
-    let checklist_controller = grpc::checklist::Controller::new(checklist_service.clone());
+    for status in statuses {
+        let state = match status.state {
+            State::Applied => "applied",
+            State::Pending => "pending",
+            State::Modified => "modified",
+        };
+
+        println!("{:>4} {:<32} {}", status.version, status.name, state);
+    }
+}
+
+async fn run_servers() -> Result<()> {
+    let checklist_store = core::checklist::store::create_store(&env::var("DATABASE_URL")?).await?;
+    let checklist_service = core::checklist::service::Service::new(checklist_store);
+
+    // The listener reconnects on its own, so only the servers decide when the process exits.
+    let listen = async {
+        checklist_service.listen().await;
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(
+        listen,
+        run_grpc(checklist_service.clone()),
+        run_graphql(checklist_service.clone())
+    )?;
+
+    Ok(())
+}
+
+async fn run_grpc(checklist_service: Arc<Service>) -> Result<()> {
+    let checklist_controller = grpc::checklist::Controller::new(checklist_service);
 
     Server::builder()
         .add_service(checklist_controller)
@@ -62,13 +143,20 @@ async fn run_grpc() -> Result<()> {
     Ok(())
 }
 
-#[allow(dead_code)]
-async fn run_graphql() -> Result<()> {
-    use graphql::checklist::{Context, Mutation, Query};
+async fn run_graphql(checklist_service: Arc<Service>) -> Result<()> {
+    use graphql::checklist::{Context, Mutation, Query, Subscription};
+
+    let socket_addr = env::var("GRAPHQL_SOCKET_ADDR")?.parse::<SocketAddr>()?;
 
     let checklist_schema =
-        warp::any().map(move || GraphqlSchema::new(Query, Mutation, EmptySubscription::new()));
-    let checklist_context = warp::any().map(move || Context {});
+        warp::any().map(move || GraphqlSchema::new(Query, Mutation, Subscription));
+    let subscription_service = checklist_service.clone();
+    let checklist_context = warp::any().map(move || Context::new(checklist_service.clone()));
+    let checklist_coordinator = Arc::new(Coordinator::new(GraphqlSchema::new(
+        Query,
+        Mutation,
+        Subscription,
+    )));
 
     let graphql_route = warp::post()
         .and(warp::path!("graphql"))
@@ -77,15 +165,30 @@ async fn run_graphql() -> Result<()> {
         .and(warp::body::json())
         .and_then(graphql);
 
+    let subscriptions_route = warp::path!("subscriptions")
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let coordinator = checklist_coordinator.clone();
+            let context = Context::new(subscription_service.clone());
+
+            ws.on_upgrade(move |websocket| {
+                graphql_subscriptions(websocket, coordinator, context).map(|result| {
+                    if let Err(err) = result {
+                        log::error!("{:?}", err);
+                    }
+                })
+            })
+        })
+        .map(|reply| warp::reply::with_header(reply, "Sec-WebSocket-Protocol", "graphql-ws"));
+
+    let subscriptions_url = format!("ws://{}/subscriptions", socket_addr);
     let graphiql_route = warp::get()
         .and(warp::path!("graphiql"))
-        .map(|| warp::reply::html(graphiql_source("graphql", None)));
+        .map(move || warp::reply::html(graphiql_source("graphql", Some(&subscriptions_url))));
 
-    let routes = graphql_route.or(graphiql_route);
+    let routes = graphql_route.or(subscriptions_route).or(graphiql_route);
 
-    warp::serve(routes)
-        .run(env::var("SOCKET_ADDR")?.parse::<SocketAddr>()?)
-        .await;
+    warp::serve(routes).run(socket_addr).await;
 
     Ok(())
 }